@@ -0,0 +1,69 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+// Requests to change what the clock shows, sent to the main loop by the keyboard controls, the
+// HTTP API and MQTT.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    NextState,
+    PreviousState,
+    RefreshWeather,
+    ToggleUnit,
+
+    // Changes the brightness by the given amount, starting from the current light level, and
+    // keeps it there regardless of the light sensor.
+    AdjustBrightness(f32),
+
+    // Fixes the brightness regardless of the light sensor, or with None goes back to following
+    // the light sensor.
+    SetBrightness(Option<f32>),
+
+    // Shows a message on the displays for a while.
+    ShowMessage(String, Duration),
+
+    Quit,
+}
+
+// The longest a message can be shown for, a day.
+const MAX_MESSAGE_SECONDS: u64 = 24 * 60 * 60;
+
+#[derive(Deserialize)]
+struct BrightnessRequest {
+    brightness: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct MessageRequest {
+    text: String,
+    seconds: u64,
+}
+
+impl Command {
+    // Parses a request to set the brightness, e.g. {"brightness": 0.5}, or {"brightness": null}
+    // to follow the light sensor again.
+    pub(crate) fn from_brightness_json(body: &str) -> Result<Command, String> {
+        match serde_json::from_str::<BrightnessRequest>(body) {
+            Ok(BrightnessRequest {
+                brightness: Some(b),
+            }) if !(0.0..=1.0).contains(&b) => {
+                Err("brightness must be between 0 and 1".to_string())
+            }
+            Ok(request) => Ok(Command::SetBrightness(request.brightness)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    // Parses a request to show a message, e.g. {"text": "Laundry done", "seconds": 30}
+    pub(crate) fn from_message_json(body: &str) -> Result<Command, String> {
+        match serde_json::from_str::<MessageRequest>(body) {
+            Ok(request) if request.seconds > MAX_MESSAGE_SECONDS => {
+                Err(format!("seconds must be at most {}", MAX_MESSAGE_SECONDS))
+            }
+            Ok(request) => Ok(Command::ShowMessage(
+                request.text,
+                Duration::from_secs(request.seconds),
+            )),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
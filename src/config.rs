@@ -6,4 +6,7 @@ pub struct Config {
     pub weather_request_polling_interval: Duration,
     pub weather_request_timeout: Duration,
     pub state_duration: Duration,
+
+    // Loop iterations that scrolling text stays at each place.
+    pub scroll_ticks: u32,
 }
@@ -1,19 +1,36 @@
+mod backpack;
+mod composite;
 mod error;
-
-use crate::weather::{
-    high_low_temp, next_precipitation_change, Main, OpenWeather, PrecipitationChange,
-};
+mod font;
+mod frame;
+mod hd44780;
+mod ht16k33;
+mod lcd;
+mod snapshot;
+mod spec;
+mod terminal;
+mod web;
+mod wiring;
+
+use crate::weather::{FetchStatus, OpenWeather};
+pub use backpack::SegmentPanel;
+pub use composite::CompositeDisplay;
 pub use error::Error;
+pub use font::{alphanum_segments, custom_glyph, hd44780_glyph, seven_segment_digit};
+pub use frame::{Frame, Layout, Screen, TemperatureUnit, View};
+pub use hd44780::HD44780Emulator;
+pub use ht16k33::HT16K33Emulator;
+pub use lcd::{CharacterLcd, LcdPanel, ShadowBuffer};
+pub use snapshot::SnapshotDisplay;
+pub use spec::{DisplayKind, DisplayOptions, DisplaySpec};
+pub use terminal::TerminalDisplay;
+pub use web::WebDisplay;
+pub use wiring::{I2cDevice, LcdWiring, Wiring};
+
+use chrono::{DateTime, Local};
 
-use chrono::{DateTime, Datelike, Local, Month, Timelike};
-use num_traits::cast::FromPrimitive;
-
-#[cfg(feature = "rpi-hw")]
-use hd44780_driver::{
-    bus::FourBitBus, Cursor, CursorBlink, Display as HD44780DisplaySetting, DisplayMode, HD44780,
-};
 #[cfg(feature = "rpi-hw")]
-use ht16k33::HT16K33;
+use hd44780_driver::bus::{DataBus, FourBitBus};
 #[cfg(feature = "rpi-hw")]
 use linux_embedded_hal::sysfs_gpio::Direction;
 #[cfg(feature = "rpi-hw")]
@@ -25,10 +42,32 @@ use rppal::i2c::I2c;
 #[cfg(feature = "rpi-hw")]
 use rppal::pwm::{Channel, Polarity, Pwm};
 
-const UNIT_CHAR: char = 'F';
+/// Everything a display needs to know to show a single iteration of the main loop.
+pub struct RenderContext<'a> {
+    pub time: DateTime<Local>,
+    /// The step of the screen rotation, which each display takes modulo the length of its
+    /// playlist.
+    pub current_state_index: u32,
+    /// How many places text too long to show at once has scrolled since the rotation step or
+    /// message last changed.
+    pub scroll_step: u32,
+    pub weather: &'a Option<OpenWeather>,
+    pub light: f32,
+    pub fetch_status: &'a FetchStatus,
+    pub unit: TemperatureUnit,
+
+    /// A message to show in place of part of the usual layout.
+    pub message: Option<Message<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub text: &'a str,
+}
 
-// To enable heterogenous abstractions over multiple display types
-pub enum DisplayType<'a> {
+/// The displays built into the clock, e.g. from a DisplaySpec. Displays from other crates implement
+/// Display instead, and can be put in a CompositeDisplay alongside these.
+pub enum DisplayType {
     Console16x2(Console16x2Display),
     Console20x4(Console20x4Display),
 
@@ -43,52 +82,153 @@ pub enum DisplayType<'a> {
     #[cfg(feature = "rpi-hw")]
     SevenSegment4(SevenSegment4Display),
 
-    Composite(&'a mut [DisplayType<'a>]),
+    Snapshot(SnapshotDisplay),
+
+    Terminal(TerminalDisplay),
+
+    Web(WebDisplay),
 }
 
-impl DisplayType<'_> {
-    pub fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        current_state_index: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        match &mut *self {
-            Self::Console16x2(display) => display.print(time, current_state_index, weather, light),
-            Self::Console20x4(display) => display.print(time, current_state_index, weather, light),
+impl DisplayType {
+    fn inner(&self) -> &dyn Display {
+        match self {
+            Self::Console16x2(display) => display,
+            Self::Console20x4(display) => display,
+
+            #[cfg(feature = "rpi-hw")]
+            Self::LCD16x2(display) => display,
+            #[cfg(feature = "rpi-hw")]
+            Self::LCD20x4(display) => display,
+
+            #[cfg(feature = "rpi-hw")]
+            Self::AlphaNum4(display) => display,
+
+            #[cfg(feature = "rpi-hw")]
+            Self::SevenSegment4(display) => display,
+
+            Self::Snapshot(display) => display,
+
+            Self::Terminal(display) => display,
+
+            Self::Web(display) => display,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn Display {
+        match self {
+            Self::Console16x2(display) => display,
+            Self::Console20x4(display) => display,
 
             #[cfg(feature = "rpi-hw")]
-            Self::LCD16x2(display) => display.print(time, current_state_index, weather, light),
+            Self::LCD16x2(display) => display,
             #[cfg(feature = "rpi-hw")]
-            Self::LCD20x4(display) => display.print(time, current_state_index, weather, light),
+            Self::LCD20x4(display) => display,
 
             #[cfg(feature = "rpi-hw")]
-            Self::AlphaNum4(display) => display.print(time, current_state_index, weather, light),
+            Self::AlphaNum4(display) => display,
 
             #[cfg(feature = "rpi-hw")]
-            Self::SevenSegment4(display) => {
-                display.print(time, current_state_index, weather, light)
-            }
-
-            Self::Composite(displays) => {
-                for d in displays.iter_mut() {
-                    d.print(time, current_state_index, weather, light)?;
-                }
-                Ok(())
-            }
+            Self::SevenSegment4(display) => display,
+
+            Self::Snapshot(display) => display,
+
+            Self::Terminal(display) => display,
+
+            Self::Web(display) => display,
         }
     }
 }
 
+impl Display for DisplayType {
+    fn name(&self) -> &'static str {
+        self.inner().name()
+    }
+
+    fn label(&self) -> String {
+        self.inner().label()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner().capabilities()
+    }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.inner_mut().init()
+    }
+
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.inner_mut().print(ctx)
+    }
+
+    fn reinit(&mut self) -> Result<(), Error> {
+        self.inner_mut().reinit()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.inner_mut().shutdown()
+    }
+
+    fn frames(&self, ctx: &RenderContext) -> Vec<(&'static str, Frame)> {
+        self.inner().frames(ctx)
+    }
+}
+
+/// What a display shows and how it behaves, for code that handles displays it doesn't know.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// What the display shows, with one view for each panel of displays that show several.
+    pub views: Vec<View>,
+
+    /// Whether the display is dimmed according to the light level.
+    pub dimmable: bool,
+
+    /// Whether the display is a device that can fail and be set up again, rather than e.g. a file
+    /// or a web page.
+    pub hardware: bool,
+}
+
+/// A display that the clock prints to. Displays are set up with init() before they're first
+/// printed to, and blanked with shutdown() when the clock stops.
 pub trait Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        current_state_index: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error>;
+    /// A short name for the display, for logs, metrics and errors.
+    fn name(&self) -> &'static str;
+
+    /// The name, with whatever tells the display apart from others of its kind, e.g. its I2C
+    /// address.
+    fn label(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// What the display shows and how it behaves.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Sets the display up before it's first printed to.
+    fn init(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Shows a single iteration of the main loop.
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error>;
+
+    /// Sets the display up again after it's stopped working, e.g. because it lost power or a
+    /// glitch on the bus left it in a bad state.
+    fn reinit(&mut self) -> Result<(), Error> {
+        self.init()
+    }
+
+    /// Blanks the display when the clock stops.
+    fn shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The frames that the display shows for the context, labelled with the display's name.
+    fn frames(&self, ctx: &RenderContext) -> Vec<(&'static str, Frame)> {
+        self.capabilities()
+            .views
+            .iter()
+            .map(|view| (self.name(), view.frame(ctx)))
+            .collect()
+    }
 }
 
 pub struct Console16x2Display {}
@@ -106,75 +246,43 @@ impl Default for Console16x2Display {
 }
 
 impl Display for Console16x2Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        _: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let (weather_desc, temp_str) = console_weather_and_temp_str(weather, 3, 7);
-
-        let first_row = format!("{} {:>10}", console_time_str(time), weather_desc);
-        let second_row = format!("{} {}", console_date_str(time), temp_str);
-
-        println!();
-        println!("-{}-", "-".repeat(16));
-        println!("|{}|", first_row);
-        println!("|{}|", second_row);
-        println!("-{}-", "-".repeat(16));
-
-        println!("Current light: {}", light);
+    fn name(&self) -> &'static str {
+        "console-16x2"
+    }
 
-        Ok(())
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![Layout::Text16x2.into()],
+            ..Default::default()
+        }
     }
-}
 
-fn console_date_str(time: &DateTime<Local>) -> String {
-    format!(
-        "{} {} {:<2}",
-        &time.weekday().to_string()[0..3],
-        &mmm_from_time(time),
-        time.day()
-    )
-}
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        print_console_frame(&Layout::Text16x2.frame(ctx), 16);
 
-fn console_time_str(time: &DateTime<Local>) -> String {
-    let st = split_time(time);
-    format!("{}{}:{}{}", st[0], st[1], st[2], st[3])
-}
+        println!("Current light: {}", ctx.light);
 
-fn console_weather_and_temp_str(
-    weather: &Option<OpenWeather>,
-    temp_digits: usize,
-    weather_chars: usize,
-) -> (String, String) {
-    match weather {
-        Some(w) => (
-            format!(
-                "{:>width$}",
-                truncate_to_characters(&w.current.weather[0].main.to_string(), weather_chars),
-                width = weather_chars
-            ),
-            format!(
-                "{:>width$}°{}",
-                w.current.temp.round(),
-                UNIT_CHAR,
-                width = temp_digits
-            ),
-        ),
-        None => (
-            format!("{:>width$}", "WEATHER", width = weather_chars),
-            format!("{:>width$}", "ERR", width = temp_digits + 2),
-        ),
+        Ok(())
     }
 }
 
-pub struct Console20x4Display {}
+pub struct Console20x4Display {
+    view: View,
+}
 
 impl Console20x4Display {
     pub fn new() -> Console20x4Display {
-        Console20x4Display {}
+        Self::with_playlist(Layout::Text20x4.default_playlist())
+    }
+
+    // Rotates the fourth row through the playlist's screens.
+    pub fn with_playlist(playlist: Vec<Screen>) -> Console20x4Display {
+        Console20x4Display {
+            view: View {
+                layout: Layout::Text20x4,
+                playlist,
+            },
+        }
     }
 }
 
@@ -185,176 +293,50 @@ impl Default for Console20x4Display {
 }
 
 impl Display for Console20x4Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        current_state_index: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let (weather_desc, temp_str) = console_weather_and_temp_str(weather, 3, 14);
-
-        let (high_temp_str, low_temp_str) = high_low_strs(weather);
-
-        // time is always 5 chars, date is always 10 chars
-        let first_row = format!("{} {:>14}", console_time_str(time), weather_desc);
-        let second_row = format!("{} {:>9}", console_date_str(time), temp_str);
-
-        let third_row = format!("{:<20}", "");
-
-        let fourth_row = match current_state_index {
-            0 => format!("{:<20}", rain_forecast_str(weather)),
-            1 => format!("{:<20}", high_temp_str,),
-            2 => format!("{:<20}", low_temp_str),
-            _ => panic!("Invalid state index"),
-        };
-
-        println!();
-        println!("-{}-", "-".repeat(20));
-        println!("|{}|", first_row);
-        println!("|{}|", second_row);
-        println!("|{}|", third_row);
-        println!("|{}|", fourth_row);
-        println!("-{}-", "-".repeat(20));
-
-        println!("Current light: {}", light);
-
-        Ok(())
+    fn name(&self) -> &'static str {
+        "console-20x4"
     }
-}
 
-fn rain_forecast_str(weather: &Option<OpenWeather>) -> String {
-    match weather {
-        Some(w) => match next_precipitation_change(w) {
-            PrecipitationChange::Start(ts, p) => {
-                format!("{} starts at {:02}:00", printable_rain_type(p), ts.hour())
-            }
-            PrecipitationChange::Stop(ts, p) => {
-                format!("{} stops at {:02}:00", printable_rain_type(p), ts.hour())
-            }
-            PrecipitationChange::NoChange(maybe_p) => match maybe_p {
-                Some(p) => {
-                    format!("{} for next 24h", printable_rain_type(p))
-                }
-                None => "No rain for next 24h".to_string(),
-            },
-        },
-        None => "".to_string(),
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.view.clone()],
+            ..Default::default()
+        }
     }
-}
 
-fn printable_rain_type(p: Main) -> Main {
-    match p {
-        Main::Drizzle | Main::Thunderstorm => Main::Rain,
-        x => x,
-    }
-}
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        print_console_frame(&self.view.frame(ctx), 20);
 
-fn high_low_strs(weather: &Option<OpenWeather>) -> (String, String) {
-    match weather {
-        Some(w) => {
-            let ((high_time, high_temp), (low_time, low_temp)) = high_low_temp(w);
-            (
-                format!(
-                    "High: {}°F at {:02}:00",
-                    high_temp.round(),
-                    high_time.hour()
-                ),
-                format!("Low: {}°F at {:02}:00", low_temp.round(), low_time.hour()),
-            )
-        }
-        None => ("".to_string(), "".to_string()),
+        println!("Current light: {}", ctx.light);
+
+        Ok(())
     }
 }
 
-fn mmm_from_time(time: &DateTime<Local>) -> String {
-    Month::from_u32(time.month())
-        .expect("failed to parse month from datetime provided by operating system")
-        .name()[0..3]
-        .to_owned()
+fn print_console_frame(frame: &Frame, width: usize) {
+    if let Frame::Text(_) = frame {
+        println!();
+        print!("{}", frame.boxed(width));
+    }
 }
 
 #[cfg(feature = "rpi-hw")]
 pub struct LCD16x2Display {
-    lcd: HD44780<
-        FourBitBus<
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-        >,
-    >,
+    panel: LcdPanel<LcdBus>,
 
     brightness_pwm: Pwm,
+    pwm_channel: u8,
 }
 
 #[cfg(feature = "rpi-hw")]
 impl LCD16x2Display {
-    pub fn new() -> Result<Self, Error> {
-        // Using BCM numbers
-        // i.e. pin 0 corresponds to wiringpi 30 and physical 27
-
-        let rs = Pin::new(21);
-        let en = Pin::new(20);
-        let db4 = Pin::new(26);
-        let db5 = Pin::new(13);
-        let db6 = Pin::new(6);
-        let db7 = Pin::new(5);
-        let r = Pin::new(17);
-        let g = Pin::new(16);
-        let b = Pin::new(19);
-
-        let default_brightness = 1.0;
-        // pwm0 is pin 18
-        let pwm0 = Pwm::with_frequency(
-            Channel::Pwm0,
-            20000.0,
-            default_brightness,
-            Polarity::Normal,
-            false,
-        )?;
-
-        pwm0.enable()?;
-
-        rs.export()?;
-        en.export()?;
-        db4.export()?;
-        db5.export()?;
-        db6.export()?;
-        db7.export()?;
-        r.export()?;
-        g.export()?;
-        b.export()?;
-
-        rs.set_direction(Direction::Low)?;
-        en.set_direction(Direction::Low)?;
-        db4.set_direction(Direction::Low)?;
-        db5.set_direction(Direction::Low)?;
-        db6.set_direction(Direction::Low)?;
-        db7.set_direction(Direction::Low)?;
-        r.set_direction(Direction::Low)?; // Default to red on; green and blue off
-        g.set_direction(Direction::High)?;
-        b.set_direction(Direction::High)?;
-
-        let mut lcd = HD44780::new_4bit(rs, en, db4, db5, db6, db7, &mut Delay)?;
-
-        lcd.reset(&mut Delay)?;
-        lcd.clear(&mut Delay)?;
-
-        lcd.set_display_mode(
-            DisplayMode {
-                display: HD44780DisplaySetting::On,
-                cursor_visibility: Cursor::Invisible,
-                cursor_blink: CursorBlink::Off,
-            },
-            &mut Delay,
-        )?;
+    pub fn new(wiring: &LcdWiring) -> Result<Self, Error> {
+        let (bus, brightness_pwm) = connect_lcd(wiring)?;
 
         Ok(LCD16x2Display {
-            lcd,
-            brightness_pwm: pwm0,
+            panel: LcdPanel::new(bus, Layout::Text16x2.into()),
+            brightness_pwm,
+            pwm_channel: wiring.pwm_channel,
         })
     }
 
@@ -369,121 +351,68 @@ impl LCD16x2Display {
 
 #[cfg(feature = "rpi-hw")]
 impl Display for LCD16x2Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        _: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let (weather_desc, temp_str) = console_weather_and_temp_str(weather, 3, 14);
-
-        // time is always 5 chars, date is always 10 chars
-        let first_row = format!("{} {:>14}", console_time_str(time), weather_desc);
-        let second_row = format!("{} {:>9}", console_date_str(time), temp_str);
-
-        // Move to beginning of first row.
-        self.lcd.reset(&mut Delay)?;
+    fn name(&self) -> &'static str {
+        "lcd-16x2"
+    }
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(&first_row), &mut Delay)?;
+    // LCDs each have a PWM channel of their own
+    fn label(&self) -> String {
+        format!("{}@pwm={}", self.name(), self.pwm_channel)
+    }
 
-        // Move to line 2
-        self.lcd.set_cursor_pos(0x40, &mut Delay)?;
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.panel.view().clone()],
+            dimmable: true,
+            hardware: true,
+        }
+    }
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(&second_row), &mut Delay)?;
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.panel.print(ctx)?;
 
         let min_brightness = 0.01;
-        let light = light.max(min_brightness);
+        let light = ctx.light.max(min_brightness);
 
         self.set_brightness(light)?;
 
         Ok(())
     }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.brightness_pwm.enable()?;
+        self.panel.init()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.panel.shutdown()?;
+        self.brightness_pwm.disable()?;
+
+        Ok(())
+    }
 }
 
 #[cfg(feature = "rpi-hw")]
 pub struct LCD20x4Display {
-    lcd: HD44780<
-        FourBitBus<
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-            linux_embedded_hal::Pin,
-        >,
-    >,
+    panel: LcdPanel<LcdBus>,
 
     brightness_pwm: Pwm,
+    pwm_channel: u8,
 }
 
 #[cfg(feature = "rpi-hw")]
 impl LCD20x4Display {
-    pub fn new() -> Result<Self, Error> {
-        // Using BCM numbers
-        // i.e. pin 0 corresponds to wiringpi 30 and physical 27
-
-        let rs = Pin::new(21);
-        let en = Pin::new(20);
-        let db4 = Pin::new(19); // prev: 26
-        let db5 = Pin::new(13);
-        let db6 = Pin::new(6);
-        let db7 = Pin::new(5);
-        let r = Pin::new(17);
-        // let g = Pin::new(16);
-        // let b = Pin::new(19);
-
-        let default_brightness = 1.0;
-        // pwm0 is pin 18
-        let pwm0 = Pwm::with_frequency(
-            Channel::Pwm0,
-            20000.0,
-            default_brightness,
-            Polarity::Normal,
-            false,
-        )?;
-
-        pwm0.enable()?;
-
-        rs.export()?;
-        en.export()?;
-        db4.export()?;
-        db5.export()?;
-        db6.export()?;
-        db7.export()?;
-        r.export()?;
-        // g.export()?;
-        // b.export()?;
-
-        rs.set_direction(Direction::Low)?;
-        en.set_direction(Direction::Low)?;
-        db4.set_direction(Direction::Low)?;
-        db5.set_direction(Direction::Low)?;
-        db6.set_direction(Direction::Low)?;
-        db7.set_direction(Direction::Low)?;
-        r.set_direction(Direction::Low)?; // Default to red on; green and blue off
-                                          // g.set_direction(Direction::High)?;
-                                          // b.set_direction(Direction::High)?;
-
-        let mut lcd = HD44780::new_4bit(rs, en, db4, db5, db6, db7, &mut Delay)?;
-
-        lcd.reset(&mut Delay)?;
-        lcd.clear(&mut Delay)?;
-
-        lcd.set_display_mode(
-            DisplayMode {
-                display: HD44780DisplaySetting::On,
-                cursor_visibility: Cursor::Invisible,
-                cursor_blink: CursorBlink::Off,
-            },
-            &mut Delay,
-        )?;
+    pub fn new(wiring: &LcdWiring, playlist: Vec<Screen>) -> Result<Self, Error> {
+        let (bus, brightness_pwm) = connect_lcd(wiring)?;
+        let view = View {
+            layout: Layout::Text20x4,
+            playlist,
+        };
 
         Ok(LCD20x4Display {
-            lcd,
-            brightness_pwm: pwm0,
+            panel: LcdPanel::new(bus, view),
+            brightness_pwm,
+            pwm_channel: wiring.pwm_channel,
         })
     }
 
@@ -498,63 +427,117 @@ impl LCD20x4Display {
 
 #[cfg(feature = "rpi-hw")]
 impl Display for LCD20x4Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        current_state_index: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let (weather_desc, temp_str) = console_weather_and_temp_str(weather, 3, 14);
-        let (high_temp_str, low_temp_str) = high_low_strs(weather);
-
-        // time is always 5 chars, date is always 10 chars
-        let first_row = format!("{} {:>14}", console_time_str(time), weather_desc);
-        let second_row = format!("{} {:>9}", console_date_str(time), temp_str);
-        let third_row = "";
-
-        let fourth_row = match current_state_index {
-            0 => format!("{:<20}", rain_forecast_str(weather)),
-            1 => format!("{:<20}", high_temp_str),
-            2 => format!("{:<20}", low_temp_str),
-            _ => panic!("Invalid state index"),
-        };
+    fn name(&self) -> &'static str {
+        "lcd-20x4"
+    }
+
+    // LCDs each have a PWM channel of their own
+    fn label(&self) -> String {
+        format!("{}@pwm={}", self.name(), self.pwm_channel)
+    }
 
-        // Move to beginning of first row.
-        self.lcd.reset(&mut Delay)?;
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.panel.view().clone()],
+            dimmable: true,
+            hardware: true,
+        }
+    }
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(&first_row), &mut Delay)?;
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.panel.print(ctx)?;
 
-        // Move to line 2
-        self.lcd.set_cursor_pos(0x40, &mut Delay)?;
+        let min_brightness = 0.01;
+        let light = ctx.light.max(min_brightness);
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(&second_row), &mut Delay)?;
+        self.set_brightness(light)?;
 
-        // Move to line 3
-        self.lcd.set_cursor_pos(0x14, &mut Delay)?;
+        Ok(())
+    }
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(third_row), &mut Delay)?;
+    fn init(&mut self) -> Result<(), Error> {
+        self.brightness_pwm.enable()?;
+        self.panel.init()
+    }
 
-        // Move to line 4
-        self.lcd.set_cursor_pos(0x54, &mut Delay)?;
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.panel.shutdown()?;
+        self.brightness_pwm.disable()?;
 
-        self.lcd
-            .write_bytes(&str_to_lcd_bytes(&fourth_row), &mut Delay)?;
+        Ok(())
+    }
+}
 
-        let min_brightness = 0.01;
-        let light = light.max(min_brightness);
+#[cfg(feature = "rpi-hw")]
+type LcdBus = FourBitBus<Pin, Pin, Pin, Pin, Pin, Pin>;
 
-        self.set_brightness(light)?;
+// The LCDs are driven a byte at a time, the same way as HD44780Emulator. The bus waits after each
+// byte for long enough for the slowest instructions, clear and home, to finish.
+#[cfg(feature = "rpi-hw")]
+impl CharacterLcd for LcdBus {
+    fn command(&mut self, byte: u8) -> Result<(), Error> {
+        DataBus::write(self, byte, false, &mut Delay)?;
+        Ok(())
+    }
 
+    fn data(&mut self, byte: u8) -> Result<(), Error> {
+        DataBus::write(self, byte, true, &mut Delay)?;
         Ok(())
     }
+
+    fn wait(&mut self, duration: std::time::Duration) {
+        std::thread::sleep(duration);
+    }
 }
 
+// Sets up the pins of an LCD and its backlight. The LCD itself is set up by init().
 #[cfg(feature = "rpi-hw")]
-fn str_to_lcd_bytes(s: &str) -> Vec<u8> {
+fn connect_lcd(wiring: &LcdWiring) -> Result<(LcdBus, Pwm), Error> {
+    let default_brightness = 1.0;
+    let channel = match wiring.pwm_channel {
+        0 => Channel::Pwm0,
+        _ => Channel::Pwm1,
+    };
+    let pwm = Pwm::with_frequency(
+        channel,
+        20000.0,
+        default_brightness,
+        Polarity::Normal,
+        false,
+    )?;
+
+    let output = |number: u64, direction: Direction| -> Result<Pin, Error> {
+        let pin = Pin::new(number);
+        pin.export()?;
+        pin.set_direction(direction)?;
+        Ok(pin)
+    };
+
+    let rs = output(wiring.rs, Direction::Low)?;
+    let en = output(wiring.en, Direction::Low)?;
+    let db4 = output(wiring.d4, Direction::Low)?;
+    let db5 = output(wiring.d5, Direction::Low)?;
+    let db6 = output(wiring.d6, Direction::Low)?;
+    let db7 = output(wiring.d7, Direction::Low)?;
+
+    // Default to red on; green and blue off
+    for (pin, direction) in [
+        (wiring.red, Direction::Low),
+        (wiring.green, Direction::High),
+        (wiring.blue, Direction::High),
+    ] {
+        if let Some(pin) = pin {
+            output(pin, direction)?;
+        }
+    }
+
+    let bus = FourBitBus::from_pins(rs, en, db4, db5, db6, db7);
+
+    Ok((bus, pwm))
+}
+
+// The bytes that show the text on an HD44780, whose character ROM has ° at 0xDF.
+pub fn str_to_lcd_bytes(s: &str) -> Vec<u8> {
     s.replace('°', "#") // Pick a character that we know won't appear in the string elsewhere
         .as_bytes()
         .iter()
@@ -564,224 +547,119 @@ fn str_to_lcd_bytes(s: &str) -> Vec<u8> {
 
 #[cfg(feature = "rpi-hw")]
 pub struct AlphaNum4Display {
-    ht16k33: HT16K33<I2c>,
+    panel: SegmentPanel<I2c>,
+    device: I2cDevice,
 }
 
 #[cfg(feature = "rpi-hw")]
 impl AlphaNum4Display {
-    pub fn new() -> Result<Self, Error> {
-        // The I2C device address.
-        let address = 0x71;
-
-        // Create an I2C device.
-        let mut i2c = I2c::new()?;
-        i2c.set_slave_address(address as u16)?;
+    pub fn new(device: &I2cDevice, playlist: Vec<Screen>) -> Result<Self, Error> {
+        let view = View {
+            layout: Layout::AlphaNum4,
+            playlist,
+        };
 
-        let mut ht16k33 = HT16K33::new(i2c, address);
-        ht16k33.initialize()?;
+        Ok(AlphaNum4Display {
+            panel: SegmentPanel::new(connect_i2c(device)?, device.address, view),
+            device: device.clone(),
+        })
+    }
+}
 
-        ht16k33.set_display(ht16k33::Display::ON)?;
+#[cfg(feature = "rpi-hw")]
+impl Display for AlphaNum4Display {
+    fn name(&self) -> &'static str {
+        "alphanum4"
+    }
 
-        Ok(AlphaNum4Display { ht16k33 })
+    fn label(&self) -> String {
+        i2c_label(self.name(), &self.device)
     }
 
-    fn set_brightness(&mut self, light: f32) -> Result<(), Error> {
-        let level = (light * 15.0).round() as u8;
-        let dimming = ht16k33::Dimming::from_u8(level)?;
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.panel.view().clone()],
+            dimmable: true,
+            hardware: true,
+        }
+    }
 
-        debug!(
-            "Current light level: {}, dimming level: {}/16",
-            light, level
-        );
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.panel.print(ctx)
+    }
 
-        self.ht16k33.set_dimming(dimming)?;
+    fn init(&mut self) -> Result<(), Error> {
+        self.panel.init()
+    }
 
-        Ok(())
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.panel.shutdown()
     }
 }
 
 #[cfg(feature = "rpi-hw")]
-impl Display for AlphaNum4Display {
-    fn print(
-        &mut self,
-        _: &DateTime<Local>,
-        _: u32,
-        weather: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let [d1, d2, d3] = match weather {
-            Some(w) => {
-                let chars = format!("{:>3}", w.current.temp.round())
-                    .chars()
-                    .collect::<Vec<char>>();
-                [chars[0], chars[1], chars[2]]
-            }
-            None => ['E', 'R', 'R'],
-        };
-
-        let d4 = match weather {
-            Some(_) => UNIT_CHAR,
-            None => ' ',
-        };
-        adafruit_alphanum4::AlphaNum4::update_buffer_with_char(
-            &mut self.ht16k33,
-            adafruit_alphanum4::Index::One,
-            adafruit_alphanum4::AsciiChar::new(d1),
-        );
-        adafruit_alphanum4::AlphaNum4::update_buffer_with_char(
-            &mut self.ht16k33,
-            adafruit_alphanum4::Index::Two,
-            adafruit_alphanum4::AsciiChar::new(d2),
-        );
-        adafruit_alphanum4::AlphaNum4::update_buffer_with_char(
-            &mut self.ht16k33,
-            adafruit_alphanum4::Index::Three,
-            adafruit_alphanum4::AsciiChar::new(d3),
-        );
-        adafruit_alphanum4::AlphaNum4::update_buffer_with_char(
-            &mut self.ht16k33,
-            adafruit_alphanum4::Index::Four,
-            adafruit_alphanum4::AsciiChar::new(d4),
-        );
-
-        self.ht16k33.write_display_buffer()?;
+fn i2c_label(name: &str, device: &I2cDevice) -> String {
+    format!(
+        "{}@bus={},address={:#04x}",
+        name, device.bus, device.address
+    )
+}
 
-        self.set_brightness(light)?;
+#[cfg(feature = "rpi-hw")]
+fn connect_i2c(device: &I2cDevice) -> Result<I2c, Error> {
+    let mut i2c = I2c::with_bus(device.bus)?;
+    i2c.set_slave_address(device.address as u16)?;
 
-        Ok(())
-    }
+    Ok(i2c)
 }
 
 #[cfg(feature = "rpi-hw")]
 pub struct SevenSegment4Display {
-    ht16k33: HT16K33<I2c>,
+    panel: SegmentPanel<I2c>,
+    device: I2cDevice,
 }
 
 #[cfg(feature = "rpi-hw")]
 impl SevenSegment4Display {
-    pub fn new() -> Result<Self, Error> {
-        // The I2C device address.
-        let address = 0x70;
-
-        // Create an I2C device.
-        let mut i2c = I2c::new()?;
-        i2c.set_slave_address(address as u16)?;
-
-        let mut ht16k33 = HT16K33::new(i2c, address);
-        ht16k33.initialize()?;
-
-        ht16k33.set_display(ht16k33::Display::ON)?;
-
-        Ok(SevenSegment4Display { ht16k33 })
-    }
-
-    fn set_brightness(&mut self, brightness: f32) -> Result<(), Error> {
-        let level = (brightness * 15.0).round() as u8;
-        let dimming = ht16k33::Dimming::from_u8(level)?;
-
-        debug!("Brightness: {}, dimming level: {}/16", brightness, level);
-
-        self.ht16k33.set_dimming(dimming)?;
-
-        Ok(())
+    pub fn new(device: &I2cDevice) -> Result<Self, Error> {
+        Ok(SevenSegment4Display {
+            panel: SegmentPanel::new(
+                connect_i2c(device)?,
+                device.address,
+                Layout::SevenSegment4.into(),
+            ),
+            device: device.clone(),
+        })
     }
 }
 
 #[cfg(feature = "rpi-hw")]
 impl Display for SevenSegment4Display {
-    fn print(
-        &mut self,
-        time: &DateTime<Local>,
-        _: u32,
-        _: &Option<OpenWeather>,
-        light: f32,
-    ) -> Result<(), Error> {
-        let [d1, d2, d3, d4] = split_time(time);
-        adafruit_7segment::SevenSegment::update_buffer_with_digit(
-            &mut self.ht16k33,
-            adafruit_7segment::Index::One,
-            d1,
-        );
-        adafruit_7segment::SevenSegment::update_buffer_with_digit(
-            &mut self.ht16k33,
-            adafruit_7segment::Index::Two,
-            d2,
-        );
-        adafruit_7segment::SevenSegment::update_buffer_with_digit(
-            &mut self.ht16k33,
-            adafruit_7segment::Index::Three,
-            d3,
-        );
-        adafruit_7segment::SevenSegment::update_buffer_with_digit(
-            &mut self.ht16k33,
-            adafruit_7segment::Index::Four,
-            d4,
-        );
-        adafruit_7segment::SevenSegment::update_buffer_with_colon(&mut self.ht16k33, true);
-        self.ht16k33.write_display_buffer()?;
-
-        self.set_brightness(light)?;
-
-        Ok(())
+    fn name(&self) -> &'static str {
+        "seven_segment4"
     }
-}
-
-fn split_time(t: &DateTime<Local>) -> [u8; 4] {
-    let hour = t.hour();
-    let minute = t.minute();
-
-    let d4 = (minute % 10) as u8;
-    let d3 = (minute / 10) as u8 % 10;
-
-    let d2 = (hour % 10) as u8;
-    let d1 = (hour / 10) as u8 % 10;
-
-    [d1, d2, d3, d4]
-}
 
-fn truncate_to_characters(s: &str, length: usize) -> String {
-    if s.len() <= length {
-        return s.to_owned();
+    fn label(&self) -> String {
+        i2c_label(self.name(), &self.device)
     }
 
-    format!("{}'{}", &s[0..1], &s[s.len() - length + 2..s.len()])
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.panel.view().clone()],
+            dimmable: true,
+            hardware: true,
+        }
+    }
 
-    #[test]
-    fn test_truncate_to_characters() {
-        assert_eq!(truncate_to_characters("", 3), "");
-        assert_eq!(truncate_to_characters("a", 3), "a");
-        assert_eq!(truncate_to_characters("ab", 3), "ab");
-        assert_eq!(truncate_to_characters("abc", 3), "abc");
-        assert_eq!(truncate_to_characters("abcd", 3), "a'd");
-        assert_eq!(truncate_to_characters("abcdefg", 5), "a'efg");
-        assert_eq!(truncate_to_characters("Tornado", 7), "Tornado");
-        assert_eq!(truncate_to_characters("Thunderstorm", 7), "T'storm");
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.panel.print(ctx)
     }
 
-    #[test]
-    fn test_split_time() -> Result<(), Box<dyn std::error::Error>> {
-        assert_eq!(
-            split_time(&Local::now().with_hour(1).unwrap().with_minute(3).unwrap()),
-            [0, 1, 0, 3]
-        );
-        assert_eq!(
-            split_time(&Local::now().with_hour(0).unwrap().with_minute(0).unwrap()),
-            [0, 0, 0, 0]
-        );
-        assert_eq!(
-            split_time(&Local::now().with_hour(12).unwrap().with_minute(34).unwrap()),
-            [1, 2, 3, 4]
-        );
-        assert_eq!(
-            split_time(&Local::now().with_hour(23).unwrap().with_minute(59).unwrap()),
-            [2, 3, 5, 9]
-        );
+    fn init(&mut self) -> Result<(), Error> {
+        self.panel.init()
+    }
 
-        Ok(())
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.panel.shutdown()
     }
 }
@@ -0,0 +1,94 @@
+// Drives the HT16K33 LED controllers on Adafruit's segment display backpacks with the ht16k33 and
+// Adafruit driver crates, over any I2C bus they can use: a real one, or HT16K33Emulator.
+
+use super::{Error, Frame, RenderContext, View};
+
+use adafruit_7segment::SevenSegment;
+use adafruit_alphanum4::{AlphaNum4, AsciiChar};
+use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+use ht16k33::HT16K33;
+use log::debug;
+
+// The highest of the 16 dimming levels, at which the display is fully lit.
+const MAX_DIMMING: u8 = 15;
+
+// What's shown on a segment display with an HT16K33 backpack, however it's connected: the frames
+// of a view, dimmed with the light level.
+pub struct SegmentPanel<I2C> {
+    ht16k33: HT16K33<I2C>,
+    view: View,
+}
+
+impl<I2C, E> SegmentPanel<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    Error: From<E>,
+{
+    pub fn new(i2c: I2C, address: u8, view: View) -> Self {
+        SegmentPanel {
+            ht16k33: HT16K33::new(i2c, address),
+            view,
+        }
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    // Gives back the bus, e.g. to see what an emulator was sent.
+    pub fn into_i2c(self) -> I2C {
+        self.ht16k33.destroy()
+    }
+
+    // Sets the controller up with a blank display at full brightness.
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.ht16k33.initialize()?;
+        self.ht16k33.set_display(ht16k33::Display::ON)?;
+        Ok(())
+    }
+
+    pub fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        match self.view.frame(ctx) {
+            Frame::Text(_) => {}
+            Frame::AlphaNum4 { chars, dots } => {
+                for (i, (&ch, &dot)) in chars.iter().zip(&dots).enumerate() {
+                    let index = adafruit_alphanum4::Index::from(i as u8);
+                    // Characters without a glyph are left blank
+                    let ascii = AsciiChar::from_ascii(ch).unwrap_or(AsciiChar::Space);
+                    AlphaNum4::update_buffer_with_char(&mut self.ht16k33, index, ascii);
+                    AlphaNum4::update_buffer_with_dot(&mut self.ht16k33, index, dot);
+                }
+                self.ht16k33.write_display_buffer()?;
+            }
+            Frame::SevenSegment4 { digits, colon } => {
+                for (i, &d) in digits.iter().enumerate() {
+                    let index = adafruit_7segment::Index::from(i as u8);
+                    SevenSegment::update_buffer_with_digit(&mut self.ht16k33, index, d);
+                }
+                SevenSegment::update_buffer_with_colon(&mut self.ht16k33, colon);
+                self.ht16k33.write_display_buffer()?;
+            }
+        }
+
+        let level = dimming_level(ctx.light);
+        debug!(
+            "Current light level: {}, dimming level: {}/16",
+            ctx.light,
+            level + 1
+        );
+        self.ht16k33
+            .set_dimming(ht16k33::Dimming::from_u8(level)?)?;
+        Ok(())
+    }
+
+    // Turns the display off, keeping what's in display RAM.
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        self.ht16k33.set_display(ht16k33::Display::OFF)?;
+        Ok(())
+    }
+}
+
+// The HT16K33 supports 16 dimming levels, the lowest of which is still visible.
+fn dimming_level(light: f32) -> u8 {
+    (light.clamp(0.0, 1.0) * MAX_DIMMING as f32).round() as u8
+}
@@ -0,0 +1,190 @@
+// Several displays printed to as one, so that the clock can drive any mix of built-in displays
+// and displays from other crates.
+
+use super::{Capabilities, Display, Error, Frame, RenderContext};
+
+use log::warn;
+use std::iter::FromIterator;
+
+#[derive(Default)]
+pub struct CompositeDisplay {
+    displays: Vec<Box<dyn Display>>,
+}
+
+impl CompositeDisplay {
+    pub fn new(displays: Vec<Box<dyn Display>>) -> Self {
+        CompositeDisplay { displays }
+    }
+
+    pub fn push<D: Display + 'static>(&mut self, display: D) {
+        self.displays.push(Box::new(display));
+    }
+
+    // The displays, so that each can be looked after separately.
+    pub fn displays_mut(&mut self) -> &mut [Box<dyn Display>] {
+        &mut self.displays
+    }
+
+    // Calls `f` on every display, so that one failing doesn't stop the others. Returns the first
+    // error, and logs the rest.
+    fn for_each<F>(&mut self, action: &str, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut dyn Display) -> Result<(), Error>,
+    {
+        let mut result = Ok(());
+        for d in self.displays.iter_mut() {
+            match f(d.as_mut()).map_err(|e| e.with_display(d.label())) {
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(e) => warn!("Error {} {} display: {}", action, d.label(), e),
+                Ok(()) => {}
+            }
+        }
+        result
+    }
+}
+
+impl<D: Display + 'static> FromIterator<D> for CompositeDisplay {
+    fn from_iter<I: IntoIterator<Item = D>>(displays: I) -> Self {
+        let mut composite = CompositeDisplay::default();
+        for display in displays {
+            composite.push(display);
+        }
+        composite
+    }
+}
+
+impl Display for CompositeDisplay {
+    fn name(&self) -> &'static str {
+        "composite"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.displays.iter().map(|d| d.capabilities()).fold(
+            Capabilities::default(),
+            |mut all, c| {
+                all.views.extend(c.views);
+                all.dimmable |= c.dimmable;
+                all.hardware |= c.hardware;
+                all
+            },
+        )
+    }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.for_each("initializing", |d| d.init())
+    }
+
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.for_each("printing to", |d| d.print(ctx))
+    }
+
+    fn reinit(&mut self) -> Result<(), Error> {
+        self.for_each("reinitializing", |d| d.reinit())
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.for_each("shutting down", |d| d.shutdown())
+    }
+
+    fn frames(&self, ctx: &RenderContext) -> Vec<(&'static str, Frame)> {
+        self.displays.iter().flat_map(|d| d.frames(ctx)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{Layout, SnapshotDisplay, TemperatureUnit};
+    use crate::weather::FetchStatus;
+    use chrono::{Local, TimeZone};
+    use std::cell::Cell;
+    use std::fs;
+    use std::rc::Rc;
+
+    // A display from outside the clock, which counts what it's asked to do.
+    struct CountingDisplay {
+        prints: Rc<Cell<u32>>,
+        shutdowns: Rc<Cell<u32>>,
+    }
+
+    impl Display for CountingDisplay {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                views: vec![Layout::AlphaNum4.into()],
+                ..Default::default()
+            }
+        }
+
+        fn print(&mut self, _: &RenderContext) -> Result<(), Error> {
+            self.prints.set(self.prints.get() + 1);
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<(), Error> {
+            self.shutdowns.set(self.shutdowns.get() + 1);
+            Err(Error::other("already off"))
+        }
+    }
+
+    #[test]
+    fn test_composite_isolates_failures() {
+        let dir = std::env::temp_dir().join(format!("pi_clock_composite_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let working = dir.join("working.png");
+        let _ = fs::remove_file(&working);
+
+        let (prints, shutdowns) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let mut composite = CompositeDisplay::new(vec![
+            Box::new(SnapshotDisplay::new(
+                Layout::Text16x2,
+                dir.join("missing").join("broken.png"),
+            )),
+            Box::new(SnapshotDisplay::new(Layout::Text16x2, &working)),
+        ]);
+        composite.push(CountingDisplay {
+            prints: Rc::clone(&prints),
+            shutdowns: Rc::clone(&shutdowns),
+        });
+
+        let fetch_status = FetchStatus::default();
+        let ctx = RenderContext {
+            time: Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap(),
+            current_state_index: 0,
+            scroll_step: 0,
+            weather: &None,
+            light: 1.0,
+            fetch_status: &fetch_status,
+            unit: TemperatureUnit::Fahrenheit,
+            message: None,
+        };
+
+        assert!(composite.init().is_ok());
+        let err = composite.print(&ctx).unwrap_err();
+        let broken = format!(
+            "snapshot@{}",
+            dir.join("missing").join("broken.png").display()
+        );
+        assert_eq!(err.display(), Some(broken.as_str()));
+        assert!(working.exists());
+        assert_eq!(prints.get(), 1);
+
+        let names = composite
+            .frames(&ctx)
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["snapshot", "snapshot", "counting"]);
+        assert_eq!(composite.capabilities().views.len(), 3);
+        assert_eq!(composite.displays_mut().len(), 3);
+
+        let err = composite.shutdown().unwrap_err();
+        assert_eq!(err.display(), Some("counting"));
+        assert_eq!(shutdowns.get(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,6 +3,9 @@ use std::fmt;
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
+
+    // The label of the display the error came from, when known.
+    display: Option<String>,
 }
 
 impl std::error::Error for Error {}
@@ -12,12 +15,57 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// Return the label of the display that the error came from.
+    pub fn display(&self) -> Option<&str> {
+        self.display.as_deref()
+    }
+
+    /// Return an error for a display outside the clock, e.g. one that's driven over a bus that
+    /// the clock doesn't know about.
+    pub fn other<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> Self {
+        Error {
+            kind: ErrorKind::Other(err.into()),
+            display: None,
+        }
+    }
+
+    // Records the display that the error came from, unless a more specific one is already known.
+    pub(crate) fn with_display(mut self, display: String) -> Self {
+        self.display.get_or_insert(display);
+        self
+    }
 }
 
 /// The kind of an error that can occur.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum ErrorKind {
+    Io(std::io::Error),
+
+    Png(png::EncodingError),
+
+    Bind(Box<dyn std::error::Error + Send + Sync>),
+
+    // An error from a display outside the clock.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+
+    // A display type that doesn't exist, and the ones that do.
+    UnknownType {
+        name: String,
+        expected: Vec<String>,
+    },
+
+    InvalidParameter {
+        spec: String,
+        reason: String,
+    },
+
+    // A hardware display, when built without the rpi-hw feature.
+    Unsupported(String),
+
+    HT16K33(ht16k33::ValidationError),
+
     #[cfg(feature = "rpi-hw")]
     I2C(rppal::i2c::Error),
 
@@ -30,24 +78,44 @@ pub enum ErrorKind {
     #[cfg(feature = "rpi-hw")]
     Gpio(linux_embedded_hal::sysfs_gpio::Error),
 
-    #[cfg(feature = "rpi-hw")]
-    HT16K33(ht16k33::ValidationError),
-
     #[cfg(feature = "rpi-hw")]
     HD44780(hd44780_driver::error::Error),
 }
 
-#[cfg(not(feature = "rpi-hw"))]
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-#[cfg(feature = "rpi-hw")]
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
+            ErrorKind::Io(ref err) => err.fmt(f),
+
+            ErrorKind::Png(ref err) => err.fmt(f),
+
+            ErrorKind::Bind(ref err) => write!(f, "failed to start web display: {}", err),
+
+            ErrorKind::Other(ref err) => err.fmt(f),
+
+            ErrorKind::UnknownType {
+                ref name,
+                ref expected,
+            } => write!(
+                f,
+                "unknown display type {:?}, expected one of: {}",
+                name,
+                expected.join(", ")
+            ),
+
+            ErrorKind::InvalidParameter {
+                ref spec,
+                ref reason,
+            } => write!(f, "invalid display {:?}: {}", spec, reason),
+
+            ErrorKind::Unsupported(ref name) => write!(
+                f,
+                "{} displays need the clock to be built with the rpi-hw feature",
+                name
+            ),
+
+            ErrorKind::HT16K33(ref err) => err.fmt(f),
+
             #[cfg(feature = "rpi-hw")]
             ErrorKind::I2C(ref err) => err.fmt(f),
 
@@ -60,20 +128,80 @@ impl fmt::Display for Error {
             #[cfg(feature = "rpi-hw")]
             ErrorKind::Gpio(ref err) => err.fmt(f),
 
-            #[cfg(feature = "rpi-hw")]
-            ErrorKind::HT16K33(ref err) => err.fmt(f),
-
             #[cfg(feature = "rpi-hw")]
             ErrorKind::HD44780(ref err) => write!(f, "{:?}", err),
         }
     }
 }
 
+pub fn unknown_type(name: &str, expected: &[String]) -> Error {
+    Error {
+        kind: ErrorKind::UnknownType {
+            name: name.to_string(),
+            expected: expected.to_vec(),
+        },
+        display: None,
+    }
+}
+
+pub fn invalid_parameter(spec: &str, reason: String) -> Error {
+    Error {
+        kind: ErrorKind::InvalidParameter {
+            spec: spec.to_string(),
+            reason,
+        },
+        display: None,
+    }
+}
+
+pub fn bind(err: Box<dyn std::error::Error + Send + Sync>) -> Error {
+    Error {
+        kind: ErrorKind::Bind(err),
+        display: None,
+    }
+}
+
+#[cfg_attr(feature = "rpi-hw", allow(dead_code))]
+pub fn unsupported(name: &str) -> Error {
+    Error {
+        kind: ErrorKind::Unsupported(name.to_string()),
+        display: None,
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error {
+            kind: ErrorKind::Io(e),
+            display: None,
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Error {
+            kind: ErrorKind::Png(e),
+            display: None,
+        }
+    }
+}
+
+impl From<ht16k33::ValidationError> for Error {
+    fn from(e: ht16k33::ValidationError) -> Self {
+        Error {
+            kind: ErrorKind::HT16K33(e),
+            display: None,
+        }
+    }
+}
+
 #[cfg(feature = "rpi-hw")]
 impl From<rppal::i2c::Error> for Error {
     fn from(e: rppal::i2c::Error) -> Self {
         Error {
             kind: ErrorKind::I2C(e),
+            display: None,
         }
     }
 }
@@ -83,6 +211,7 @@ impl From<linux_embedded_hal::sysfs_gpio::Error> for Error {
     fn from(e: linux_embedded_hal::sysfs_gpio::Error) -> Self {
         Error {
             kind: ErrorKind::Gpio(e),
+            display: None,
         }
     }
 }
@@ -92,6 +221,7 @@ impl From<rppal::pwm::Error> for Error {
     fn from(e: rppal::pwm::Error) -> Self {
         Error {
             kind: ErrorKind::Pwm(e),
+            display: None,
         }
     }
 }
@@ -101,15 +231,7 @@ impl From<rppal::spi::Error> for Error {
     fn from(e: rppal::spi::Error) -> Self {
         Error {
             kind: ErrorKind::Spi(e),
-        }
-    }
-}
-
-#[cfg(feature = "rpi-hw")]
-impl From<ht16k33::ValidationError> for Error {
-    fn from(e: ht16k33::ValidationError) -> Self {
-        Error {
-            kind: ErrorKind::HT16K33(e),
+            display: None,
         }
     }
 }
@@ -119,6 +241,7 @@ impl From<hd44780_driver::error::Error> for Error {
     fn from(e: hd44780_driver::error::Error) -> Self {
         Error {
             kind: ErrorKind::HD44780(e),
+            display: None,
         }
     }
 }
@@ -0,0 +1,309 @@
+// Character and segment fonts for the displays we drive, used to render what the hardware
+// would show without the hardware being present.
+
+// HD44780 character ROM (A00, the Japanese variant found on most modules) for codes 0x20-0x7F.
+// Each glyph is 7 rows of 5 pixels, most significant bit on the left. The 8th row of each
+// character cell is reserved for the cursor and is always blank.
+#[rustfmt::skip]
+const HD44780_ROM: [[u8; 7]; 96] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // space
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00100], // !
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // "
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010], // #
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100], // $
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011], // %
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101], // &
+    [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000], // '
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010], // (
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000], // )
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000], // *
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000], // +
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000], // ,
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // -
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100], // .
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // /
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 1
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // 2
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // 3
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // 4
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // 5
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // 6
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // 7
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // 8
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // 9
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000], // :
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000], // ;
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010], // <
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // =
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000], // >
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // ?
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110], // @
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001], // A
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // B
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // C
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // D
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // E
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // F
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // G
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // H
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // I
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // J
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // L
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // M
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // N
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // O
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // P
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // Q
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // R
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // T
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // U
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // V
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // W
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // X
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // Y
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // Z
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110], // [
+    [0b10001, 0b01010, 0b11111, 0b00100, 0b11111, 0b00100, 0b00100], // ¥ (0x5C)
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110], // ]
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000], // ^
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111], // _
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // `
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111], // a
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110], // b
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110], // c
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111], // d
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110], // e
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000], // f
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // g
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // h
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // i
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100], // j
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // k
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // l
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001], // m
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // n
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110], // o
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000], // p
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001], // q
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000], // r
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110], // s
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110], // t
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101], // u
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // v
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010], // w
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // x
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // y
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111], // z
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010], // {
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // |
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000], // }
+    [0b00000, 0b00100, 0b00010, 0b11111, 0b00010, 0b00100, 0b00000], // → (0x7E)
+    [0b00000, 0b00100, 0b01000, 0b11111, 0b01000, 0b00100, 0b00000], // ← (0x7F)
+];
+
+#[rustfmt::skip]
+const HD44780_DEGREE: [u8; 7] = [0b11100, 0b10100, 0b11100, 0b00000, 0b00000, 0b00000, 0b00000];
+const HD44780_BLOCK: [u8; 7] = [0b11111; 7];
+
+// Returns the 5x8 glyph the HD44780 displays for the provided character code.
+// Codes outside the ROM range we model are displayed as blank.
+pub fn hd44780_glyph(code: u8) -> [u8; 8] {
+    let rows = match code {
+        0x20..=0x7F => HD44780_ROM[(code - 0x20) as usize],
+        0xDF => HD44780_DEGREE,
+        0xFF => HD44780_BLOCK,
+        _ => [0; 7],
+    };
+
+    let mut glyph = [0; 8];
+    glyph[..7].copy_from_slice(&rows);
+    glyph
+}
+
+// Weather icons and the bars of sparklines, which the HD44780's ROM doesn't have, so they're
+// loaded into its CGRAM. Each is shown elsewhere as the Unicode character it's listed with.
+#[rustfmt::skip]
+pub(super) const CUSTOM_GLYPHS: [(char, [u8; 8]); 14] = [
+    ('☀', [0b00000, 0b10101, 0b01110, 0b11111, 0b01110, 0b10101, 0b00000, 0b00000]), // sun
+    ('☾', [0b00110, 0b01100, 0b11000, 0b11000, 0b11000, 0b01100, 0b00110, 0b00000]), // moon
+    ('☁', [0b00000, 0b00000, 0b01100, 0b11110, 0b11111, 0b11111, 0b00000, 0b00000]), // cloud
+    ('☂', [0b01100, 0b11110, 0b11111, 0b00000, 0b10101, 0b00000, 0b01010, 0b00000]), // rain
+    ('❄', [0b00100, 0b10101, 0b01110, 0b00100, 0b01110, 0b10101, 0b00100, 0b00000]), // snow
+    ('☇', [0b00010, 0b00100, 0b01000, 0b11111, 0b00010, 0b00100, 0b01000, 0b00000]), // thunder
+    ('≡', [0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]), // fog
+    // Bars filling the bottom eighths of the cell, including the cursor row
+    ('▁', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('▂', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b11111]),
+    ('▃', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b11111, 0b11111]),
+    ('▄', [0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b11111, 0b11111, 0b11111]),
+    ('▅', [0b00000, 0b00000, 0b00000, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111]),
+    ('▆', [0b00000, 0b00000, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111]),
+    ('▇', [0b00000, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111]),
+];
+
+// Returns the 5x8 glyph to load into CGRAM for a character that the HD44780's ROM doesn't have,
+// or None for characters that it does, or that can't be shown.
+pub fn custom_glyph(c: char) -> Option<[u8; 8]> {
+    CUSTOM_GLYPHS
+        .iter()
+        .find(|(custom, _)| *custom == c)
+        .map(|(_, glyph)| *glyph)
+}
+
+// 14-segment font for ASCII 0x20-0x7F, matching the table used by the adafruit-alphanum4 crate.
+// Bits 0-5 are segments A-F, bits 6 and 7 are the left and right halves of the middle bar (G1, G2),
+// bits 8-13 are the inner segments H, J, K, L, M and N and bit 14 is the decimal point.
+const ALPHANUM_FONT: [u16; 96] = [
+    0b0000000000000000, // space
+    0b0000000000000110, // !
+    0b0000001000100000, // "
+    0b0001001011001110, // #
+    0b0001001011101101, // $
+    0b0000110000100100, // %
+    0b0010001101011101, // &
+    0b0000010000000000, // '
+    0b0010010000000000, // (
+    0b0000100100000000, // )
+    0b0011111111000000, // *
+    0b0001001011000000, // +
+    0b0000100000000000, // ,
+    0b0000000011000000, // -
+    0b0000000000000000, // .
+    0b0000110000000000, // /
+    0b0000110000111111, // 0
+    0b0000000000000110, // 1
+    0b0000000011011011, // 2
+    0b0000000010001111, // 3
+    0b0000000011100110, // 4
+    0b0010000001101001, // 5
+    0b0000000011111101, // 6
+    0b0000000000000111, // 7
+    0b0000000011111111, // 8
+    0b0000000011101111, // 9
+    0b0001001000000000, // :
+    0b0000101000000000, // ;
+    0b0010010000000000, // <
+    0b0000000011001000, // =
+    0b0000100100000000, // >
+    0b0001000010000011, // ?
+    0b0000001010111011, // @
+    0b0000000011110111, // A
+    0b0001001010001111, // B
+    0b0000000000111001, // C
+    0b0001001000001111, // D
+    0b0000000011111001, // E
+    0b0000000001110001, // F
+    0b0000000010111101, // G
+    0b0000000011110110, // H
+    0b0001001000000000, // I
+    0b0000000000011110, // J
+    0b0010010001110000, // K
+    0b0000000000111000, // L
+    0b0000010100110110, // M
+    0b0010000100110110, // N
+    0b0000000000111111, // O
+    0b0000000011110011, // P
+    0b0010000000111111, // Q
+    0b0010000011110011, // R
+    0b0000000011101101, // S
+    0b0001001000000001, // T
+    0b0000000000111110, // U
+    0b0000110000110000, // V
+    0b0010100000110110, // W
+    0b0010110100000000, // X
+    0b0001010100000000, // Y
+    0b0000110000001001, // Z
+    0b0000000000111001, // [
+    0b0010000100000000, // \\
+    0b0000000000001111, // ]
+    0b0000110000000011, // ^
+    0b0000000000001000, // _
+    0b0000000100000000, // `
+    0b0001000001011000, // a
+    0b0010000001111000, // b
+    0b0000000011011000, // c
+    0b0000100010001110, // d
+    0b0000100001011000, // e
+    0b0000000001110001, // f
+    0b0000010010001110, // g
+    0b0001000001110000, // h
+    0b0001000000000000, // i
+    0b0000000000001110, // j
+    0b0011011000000000, // k
+    0b0000000000110000, // l
+    0b0001000011010100, // m
+    0b0001000001010000, // n
+    0b0000000011011100, // o
+    0b0000000101110000, // p
+    0b0000010010000110, // q
+    0b0000000001010000, // r
+    0b0010000010001000, // s
+    0b0000000001111000, // t
+    0b0000000000011100, // u
+    0b0010000000000100, // v
+    0b0010100000010100, // w
+    0b0010100011000000, // x
+    0b0010000000001100, // y
+    0b0000100001001000, // z
+    0b0000100101001001, // {
+    0b0001001000000000, // |
+    0b0010010010001001, // }
+    0b0000010100100000, // ~
+    0b0011111111111111, // DEL (all segments)
+];
+
+pub const ALPHANUM_DOT: u16 = 1 << 14;
+
+pub fn alphanum_segments(c: char) -> u16 {
+    match c as u32 {
+        code @ 0x20..=0x7F => ALPHANUM_FONT[(code - 0x20) as usize],
+        _ => 0,
+    }
+}
+
+// 7-segment font for the decimal digits. Bits 0-6 are segments A-G and bit 7 is the decimal point.
+const SEVEN_SEGMENT_DIGITS: [u8; 10] = [0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F];
+
+pub fn seven_segment_digit(digit: u8) -> u8 {
+    SEVEN_SEGMENT_DIGITS[digit as usize % 10]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hd44780_glyph() {
+        assert_eq!(
+            hd44780_glyph(b'A'),
+            [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b00000]
+        );
+        assert_eq!(hd44780_glyph(b' '), [0; 8]);
+        assert_eq!(hd44780_glyph(0xDF)[0], 0b11100);
+        assert_eq!(hd44780_glyph(0x80), [0; 8]);
+    }
+
+    #[test]
+    fn test_custom_glyph() {
+        assert_eq!(custom_glyph('☀').unwrap()[3], 0b11111);
+        assert_eq!(custom_glyph('A'), None);
+        assert_eq!(custom_glyph('°'), None);
+
+        // Each icon looks different
+        for (i, (_, glyph)) in CUSTOM_GLYPHS.iter().enumerate() {
+            assert!(CUSTOM_GLYPHS[i + 1..]
+                .iter()
+                .all(|(_, other)| other != glyph));
+        }
+    }
+
+    #[test]
+    fn test_alphanum_segments() {
+        assert_eq!(alphanum_segments('1'), 0b0000000000000110);
+        assert_eq!(alphanum_segments('F'), 0b0000000001110001);
+        assert_eq!(alphanum_segments('°'), 0);
+    }
+}
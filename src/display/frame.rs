@@ -0,0 +1,1180 @@
+// Pure layout functions: what each kind of display shows for a given time, rotation state and
+// weather, independent of the hardware (or terminal) that shows it.
+
+use super::font::{alphanum_segments, seven_segment_digit, ALPHANUM_DOT};
+use super::RenderContext;
+use crate::weather::{
+    high_low_temp, next_precipitation_change, open_weather_types::Weather, Main, OpenWeather,
+    PrecipitationChange,
+};
+
+use chrono::{DateTime, Datelike, Local, Month, Timelike};
+use num_traits::cast::FromPrimitive;
+use std::str::FromStr;
+
+const SEVEN_SEGMENT_COLON: u16 = 1 << 8;
+
+// The bars of sparklines, lowest first.
+const SPARKLINE_BARS: [char; 8] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+const SPARKLINE_HOURS: usize = 20;
+
+// The unit temperatures are shown in. Forecasts are fetched in Fahrenheit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    #[default]
+    Fahrenheit,
+    Celsius,
+}
+
+impl TemperatureUnit {
+    pub fn toggled(&self) -> Self {
+        match self {
+            TemperatureUnit::Fahrenheit => TemperatureUnit::Celsius,
+            TemperatureUnit::Celsius => TemperatureUnit::Fahrenheit,
+        }
+    }
+
+    fn convert(&self, fahrenheit: f32) -> f32 {
+        match self {
+            TemperatureUnit::Fahrenheit => fahrenheit,
+            TemperatureUnit::Celsius => (fahrenheit - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            TemperatureUnit::Fahrenheit => 'F',
+            TemperatureUnit::Celsius => 'C',
+        }
+    }
+}
+
+// The layouts that displays can show. Console and hardware displays of the same size share a
+// layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Text16x2,
+    Text20x4,
+    AlphaNum4,
+    SevenSegment4,
+}
+
+impl Layout {
+    // The frame for the context, rotating through the layout's default playlist.
+    pub fn frame(&self, ctx: &RenderContext) -> Frame {
+        View::new(*self).frame(ctx)
+    }
+
+    fn frame_showing(&self, ctx: &RenderContext, screen: Option<Screen>) -> Frame {
+        let (time, weather, unit) = (&ctx.time, ctx.weather, ctx.unit);
+
+        match (self, ctx.message) {
+            // Messages take the place of the rows that rotate, or would otherwise be least missed
+            (Layout::Text16x2, Some(message)) => {
+                let mut rows = text_16x2_rows(time, weather, unit);
+                rows.splice(1.., wrap_message(message.text, 16, 1));
+                Frame::Text(rows)
+            }
+            (Layout::Text20x4, Some(message)) => {
+                let mut rows = text_20x4_rows(time, None, weather, unit);
+                rows.splice(2.., wrap_message(message.text, 20, 2));
+                Frame::Text(rows)
+            }
+            (Layout::AlphaNum4, Some(message)) => marquee(message.text, ctx.scroll_step),
+
+            (Layout::Text16x2, None) => Frame::Text(text_16x2_rows(time, weather, unit)),
+            (Layout::Text20x4, None) => Frame::Text(text_20x4_rows(time, screen, weather, unit)),
+            (Layout::AlphaNum4, None) => match (screen, weather) {
+                (Some(Screen::Condition), Some(w)) => {
+                    marquee(&w.current.weather[0].main.to_string(), ctx.scroll_step)
+                }
+                _ => Frame::AlphaNum4 {
+                    chars: alphanum4_chars(time, screen, weather, unit),
+                    dots: [false; 4],
+                },
+            },
+            (Layout::SevenSegment4, _) => Frame::SevenSegment4 {
+                digits: split_time(time),
+                colon: true,
+            },
+        }
+    }
+
+    // The screens that the layout can rotate through. Layouts that show everything at once have
+    // none.
+    pub fn screens(&self) -> Vec<Screen> {
+        match self {
+            Layout::Text20x4 => vec![Screen::Precipitation, Screen::High, Screen::Low],
+            Layout::AlphaNum4 => vec![
+                Screen::Temperature,
+                Screen::Condition,
+                Screen::High,
+                Screen::Low,
+                Screen::Time,
+            ],
+            Layout::Text16x2 | Layout::SevenSegment4 => vec![],
+        }
+    }
+
+    pub fn default_playlist(&self) -> Vec<Screen> {
+        match self {
+            Layout::AlphaNum4 => vec![Screen::Temperature, Screen::Condition],
+            _ => self.screens(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Text16x2 => "16x2",
+            Layout::Text20x4 => "20x4",
+            Layout::AlphaNum4 => "alphanum4",
+            Layout::SevenSegment4 => "seven_segment4",
+        }
+    }
+
+    // The number of characters in each row of text layouts.
+    pub fn columns(&self) -> usize {
+        match self {
+            Layout::Text16x2 => 16,
+            Layout::Text20x4 => 20,
+            Layout::AlphaNum4 | Layout::SevenSegment4 => 4,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        match self {
+            Layout::Text16x2 => 2,
+            Layout::Text20x4 => 4,
+            Layout::AlphaNum4 | Layout::SevenSegment4 => 1,
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    // Parses the name of a layout, e.g. "20x4".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Layout::Text16x2,
+            Layout::Text20x4,
+            Layout::AlphaNum4,
+            Layout::SevenSegment4,
+        ]
+        .iter()
+        .find(|layout| layout.name() == s)
+        .copied()
+        .ok_or_else(|| format!("unknown layout {:?}", s))
+    }
+}
+
+// What a display shows for one step of the rotation: a row of the 20x4 display, or the whole
+// of the alphanumeric display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Screen {
+    Temperature,
+
+    // The current conditions, e.g. rain, scrolling on displays too small to show them at once.
+    Condition,
+
+    // The forecast high or low over the next 24 hours.
+    High,
+    Low,
+
+    // When rain or snow is next forecast to start or stop.
+    Precipitation,
+
+    Time,
+}
+
+impl Screen {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Screen::Temperature => "temperature",
+            Screen::Condition => "condition",
+            Screen::High => "high",
+            Screen::Low => "low",
+            Screen::Precipitation => "precipitation",
+            Screen::Time => "time",
+        }
+    }
+}
+
+impl FromStr for Screen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Screen::Temperature,
+            Screen::Condition,
+            Screen::High,
+            Screen::Low,
+            Screen::Precipitation,
+            Screen::Time,
+        ]
+        .iter()
+        .find(|screen| screen.name() == s)
+        .copied()
+        .ok_or_else(|| format!("unknown screen {:?}", s))
+    }
+}
+
+// A layout and the screens that it rotates through, one per step of the rotation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct View {
+    pub layout: Layout,
+    pub playlist: Vec<Screen>,
+}
+
+impl View {
+    pub fn new(layout: Layout) -> Self {
+        View {
+            layout,
+            playlist: layout.default_playlist(),
+        }
+    }
+
+    pub fn frame(&self, ctx: &RenderContext) -> Frame {
+        let screen = match self.playlist.len() {
+            0 => None,
+            len => Some(self.playlist[ctx.current_state_index as usize % len]),
+        };
+
+        self.layout.frame_showing(ctx, screen)
+    }
+}
+
+impl From<Layout> for View {
+    fn from(layout: Layout) -> Self {
+        View::new(layout)
+    }
+}
+
+// What a display shows for a single iteration of the main loop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    // One string per row of a character display.
+    Text(Vec<String>),
+
+    // The characters of a 4-character 14-segment display, and which of their decimal points are lit.
+    AlphaNum4 { chars: [char; 4], dots: [bool; 4] },
+
+    // The digits of a 4-digit 7-segment display, and whether the colon is lit.
+    SevenSegment4 { digits: [u8; 4], colon: bool },
+}
+
+impl Frame {
+    // The frame as lines of text. Segment displays are shown as a single line.
+    pub fn rows(&self) -> Vec<String> {
+        match self {
+            Frame::Text(rows) => rows.clone(),
+            Frame::AlphaNum4 { chars, dots } => vec![chars
+                .iter()
+                .zip(dots)
+                .flat_map(|(&c, &dot)| {
+                    Some(c)
+                        .into_iter()
+                        .chain(if dot { Some('.') } else { None })
+                })
+                .collect()],
+            Frame::SevenSegment4 { digits, colon } => vec![format!(
+                "{}{}{}{}{}",
+                digits[0],
+                digits[1],
+                if *colon { ':' } else { ' ' },
+                digits[2],
+                digits[3]
+            )],
+        }
+    }
+
+    // The frame as text in a box `columns` wide, so that rows of text that are too long stick out
+    // of it. Segment frames can't overflow, so their box fits them.
+    pub fn boxed(&self, columns: usize) -> String {
+        let rows = self.rows();
+        let columns = match self {
+            Frame::Text(_) => columns,
+            _ => rows
+                .iter()
+                .map(|row| row.chars().count())
+                .max()
+                .unwrap_or(0),
+        };
+        let border = format!("-{}-\n", "-".repeat(columns));
+
+        let mut boxed = border.clone();
+        for row in rows {
+            boxed.push_str(&format!("|{}|\n", row));
+        }
+        boxed.push_str(&border);
+        boxed
+    }
+
+    // Returns the segment bits lit for each digit of segment frames, in the bit order of the
+    // HT16K33 backpacks. The 7-segment colon is returned as bit 8 of every digit.
+    pub fn segments(&self) -> Option<[u16; 4]> {
+        match self {
+            Frame::Text(_) => None,
+            Frame::AlphaNum4 { chars, dots } => Some([0, 1, 2, 3].map(|i| {
+                let dot = if dots[i] { ALPHANUM_DOT } else { 0 };
+                alphanum_segments(chars[i]) | dot
+            })),
+            Frame::SevenSegment4 { digits, colon } => Some(digits.map(|d| {
+                let colon_bit = if *colon { SEVEN_SEGMENT_COLON } else { 0 };
+                seven_segment_digit(d) as u16 | colon_bit
+            })),
+        }
+    }
+}
+
+// Rows shown on 16x2 character displays.
+fn text_16x2_rows(
+    time: &DateTime<Local>,
+    weather: &Option<OpenWeather>,
+    unit: TemperatureUnit,
+) -> Vec<String> {
+    let (weather_desc, temp_str) = console_weather_and_temp_str(weather, unit, 3, 10);
+
+    // time is always 5 chars, date is always 10 chars
+    vec![
+        format!("{} {:>10}", console_time_str(time), weather_desc),
+        format!("{} {}", console_date_str(time), temp_str),
+    ]
+}
+
+// Rows shown on 20x4 character displays. The fourth row shows the screen that the rotation is on.
+fn text_20x4_rows(
+    time: &DateTime<Local>,
+    screen: Option<Screen>,
+    weather: &Option<OpenWeather>,
+    unit: TemperatureUnit,
+) -> Vec<String> {
+    let (weather_desc, temp_str) = console_weather_and_temp_str(weather, unit, 3, 14);
+
+    let (high_temp_str, low_temp_str) = high_low_strs(time, weather, unit);
+
+    // time is always 5 chars, date is always 10 chars
+    let first_row = format!("{} {:>14}", console_time_str(time), weather_desc);
+    let second_row = format!("{} {:>9}", console_date_str(time), temp_str);
+
+    let third_row = format!("{:<20}", hourly_sparkline(time, screen, weather));
+
+    let fourth_row = match screen {
+        Some(Screen::High) => format!("{:<20}", high_temp_str),
+        Some(Screen::Low) => format!("{:<20}", low_temp_str),
+        // The temperature and time are already shown on the second row
+        _ => format!("{:<20}", rain_forecast_str(time, weather)),
+    };
+
+    vec![first_row, second_row, third_row, fourth_row]
+}
+
+// A bar for each of the next 20 hours: the chance of precipitation on the precipitation screen,
+// and otherwise the temperature, between the low and the high.
+fn hourly_sparkline(
+    time: &DateTime<Local>,
+    screen: Option<Screen>,
+    weather: &Option<OpenWeather>,
+) -> String {
+    let Some(w) = weather else {
+        return String::new();
+    };
+    let hours = w
+        .hourly
+        .iter()
+        .filter(|h| h.dt >= time.timestamp())
+        .take(SPARKLINE_HOURS);
+
+    match screen {
+        Some(Screen::Precipitation) => hours.map(|h| sparkline_bar(h.pop, 0.0, 1.0)).collect(),
+        _ if w.hourly.is_empty() => String::new(),
+        _ => {
+            let ((_, high), (_, low)) = high_low_temp(w, time);
+            hours.map(|h| sparkline_bar(h.temp, low, high)).collect()
+        }
+    }
+}
+
+// The bar for a value between `low` and `high`. When they're the same, it's shown half way.
+fn sparkline_bar(value: f32, low: f32, high: f32) -> char {
+    let max = (SPARKLINE_BARS.len() - 1) as f32;
+    let level = if high > low {
+        ((value - low) / (high - low) * max).round().clamp(0.0, max)
+    } else {
+        (max / 2.0).round()
+    };
+    SPARKLINE_BARS[level as usize]
+}
+
+// Characters shown on the 4-character alphanumeric display: by default, the current temperature
+// and unit.
+fn alphanum4_chars(
+    time: &DateTime<Local>,
+    screen: Option<Screen>,
+    weather: &Option<OpenWeather>,
+    unit: TemperatureUnit,
+) -> [char; 4] {
+    if screen == Some(Screen::Time) {
+        return split_time(time).map(|digit| char::from(b'0' + digit));
+    }
+
+    let Some(w) = weather else {
+        return ['E', 'R', 'R', ' '];
+    };
+
+    let (prefix, temp) = match screen {
+        Some(Screen::High) => ('H', high_low_temp(w, time).0 .1),
+        Some(Screen::Low) => ('L', high_low_temp(w, time).1 .1),
+        _ => (unit.symbol(), w.current.temp),
+    };
+
+    let chars = format!("{:>3}", unit.convert(temp).round())
+        .chars()
+        .collect::<Vec<char>>();
+    match screen {
+        Some(Screen::High | Screen::Low) => [prefix, chars[0], chars[1], chars[2]],
+        _ => [chars[0], chars[1], chars[2], prefix],
+    }
+}
+
+fn console_date_str(time: &DateTime<Local>) -> String {
+    format!(
+        "{} {} {:<2}",
+        &time.weekday().to_string()[0..3],
+        &mmm_from_time(time),
+        time.day()
+    )
+}
+
+fn console_time_str(time: &DateTime<Local>) -> String {
+    let st = split_time(time);
+    format!("{}{}:{}{}", st[0], st[1], st[2], st[3])
+}
+
+fn console_weather_and_temp_str(
+    weather: &Option<OpenWeather>,
+    unit: TemperatureUnit,
+    temp_digits: usize,
+    weather_chars: usize,
+) -> (String, String) {
+    match weather {
+        Some(w) => (
+            format!(
+                "{:>width$}",
+                format!(
+                    "{} {}",
+                    weather_icon(&w.current.weather[0]),
+                    truncate_to_characters(
+                        &w.current.weather[0].main.to_string(),
+                        weather_chars - 2
+                    )
+                ),
+                width = weather_chars
+            ),
+            format!(
+                "{:>width$}°{}",
+                unit.convert(w.current.temp).round(),
+                unit.symbol(),
+                width = temp_digits
+            ),
+        ),
+        None => (
+            format!("{:>width$}", "WEATHER", width = weather_chars),
+            format!("{:>width$}", "ERR", width = temp_digits + 2),
+        ),
+    }
+}
+
+// The icon for a condition. Character LCDs show these with custom characters.
+fn weather_icon(weather: &Weather) -> char {
+    match weather.main {
+        // OpenWeather's icons for the night end in 'n'
+        Main::Clear if weather.icon.ends_with('n') => '☾',
+        Main::Clear => '☀',
+        Main::Clouds => '☁',
+        Main::Drizzle | Main::Rain | Main::Squall => '☂',
+        Main::Snow => '❄',
+        Main::Thunderstorm | Main::Tornado => '☇',
+        Main::Mist | Main::Smoke | Main::Haze | Main::Dust | Main::Fog | Main::Sand | Main::Ash => {
+            '≡'
+        }
+    }
+}
+
+fn rain_forecast_str(time: &DateTime<Local>, weather: &Option<OpenWeather>) -> String {
+    match weather {
+        Some(w) => match next_precipitation_change(w, time) {
+            PrecipitationChange::Start(ts, p) => {
+                format!("{} starts at {:02}:00", printable_rain_type(p), ts.hour())
+            }
+            PrecipitationChange::Stop(ts, p) => {
+                format!("{} stops at {:02}:00", printable_rain_type(p), ts.hour())
+            }
+            PrecipitationChange::NoChange(maybe_p) => match maybe_p {
+                Some(p) => {
+                    format!("{} for next 24h", printable_rain_type(p))
+                }
+                None => "No rain for next 24h".to_string(),
+            },
+        },
+        None => "".to_string(),
+    }
+}
+
+fn printable_rain_type(p: Main) -> Main {
+    match p {
+        Main::Drizzle | Main::Thunderstorm => Main::Rain,
+        x => x,
+    }
+}
+
+fn high_low_strs(
+    time: &DateTime<Local>,
+    weather: &Option<OpenWeather>,
+    unit: TemperatureUnit,
+) -> (String, String) {
+    match weather {
+        Some(w) => {
+            let ((high_time, high_temp), (low_time, low_temp)) = high_low_temp(w, time);
+            (
+                format!(
+                    "High: {}°{} at {:02}:00",
+                    unit.convert(high_temp).round(),
+                    unit.symbol(),
+                    high_time.hour()
+                ),
+                format!(
+                    "Low: {}°{} at {:02}:00",
+                    unit.convert(low_temp).round(),
+                    unit.symbol(),
+                    low_time.hour()
+                ),
+            )
+        }
+        None => ("".to_string(), "".to_string()),
+    }
+}
+
+// Word-wraps a message onto rows of exactly `width` characters, dropping whatever doesn't fit.
+fn wrap_message(message: &str, width: usize, rows: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for word in printable(message).split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.extend(
+                word.as_bytes()
+                    .chunks(width)
+                    .map(|chunk| String::from_utf8_lossy(chunk).into_owned()),
+            ),
+        }
+    }
+
+    lines.resize(rows, String::new());
+    lines
+        .iter()
+        .map(|line| format!("{:<width$}", line))
+        .collect()
+}
+
+// The 4 characters of text that has scrolled `step` places. A full stop is shown as the decimal
+// point of the character before it rather than taking a character of its own. Text too long to
+// show at once scrolls from right to left, leaving a gap before it starts again.
+fn marquee(text: &str, step: u32) -> Frame {
+    let mut cells: Vec<(char, bool)> = vec![];
+    for c in printable(text).chars() {
+        match cells.last_mut() {
+            Some((previous, dot)) if c == '.' && *previous != '.' && !*dot => *dot = true,
+            _ => cells.push((c, false)),
+        }
+    }
+
+    let start = if cells.len() > 4 {
+        cells.extend([(' ', false); 4]);
+        step as usize % cells.len()
+    } else {
+        cells.resize(4, (' ', false));
+        0
+    };
+    let cell = |i: usize| cells[(start + i) % cells.len()];
+
+    Frame::AlphaNum4 {
+        chars: [0, 1, 2, 3].map(|i| cell(i).0),
+        dots: [0, 1, 2, 3].map(|i| cell(i).1),
+    }
+}
+
+// Replaces the characters that the displays can't show with '?'.
+fn printable(message: &str) -> String {
+    message
+        .chars()
+        .map(|c| {
+            if c == ' ' || c.is_ascii_graphic() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+fn mmm_from_time(time: &DateTime<Local>) -> String {
+    Month::from_u32(time.month())
+        .expect("failed to parse month from datetime provided by operating system")
+        .name()[0..3]
+        .to_owned()
+}
+
+fn split_time(t: &DateTime<Local>) -> [u8; 4] {
+    let hour = t.hour();
+    let minute = t.minute();
+
+    let d4 = (minute % 10) as u8;
+    let d3 = (minute / 10) as u8 % 10;
+
+    let d2 = (hour % 10) as u8;
+    let d1 = (hour / 10) as u8 % 10;
+
+    [d1, d2, d3, d4]
+}
+
+fn truncate_to_characters(s: &str, length: usize) -> String {
+    if s.len() <= length {
+        return s.to_owned();
+    }
+
+    format!("{}'{}", &s[0..1], &s[s.len() - length + 2..s.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Message;
+    use crate::weather::open_weather_types::{Hourly, Weather};
+    use crate::weather::FetchStatus;
+    use chrono::TimeZone;
+
+    const LAYOUTS: [Layout; 4] = [
+        Layout::Text16x2,
+        Layout::Text20x4,
+        Layout::AlphaNum4,
+        Layout::SevenSegment4,
+    ];
+
+    const STATES: [u32; 3] = [0, 1, 2];
+
+    fn fixture_time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap()
+    }
+
+    fn frame(layout: Layout, current_state_index: u32, weather: &Option<OpenWeather>) -> Frame {
+        layout.frame(&RenderContext {
+            time: fixture_time(),
+            current_state_index,
+            scroll_step: 0,
+            weather,
+            light: 1.0,
+            fetch_status: &FetchStatus::default(),
+            unit: TemperatureUnit::Fahrenheit,
+            message: None,
+        })
+    }
+
+    fn weather(main: Main) -> Vec<Weather> {
+        vec![Weather {
+            main,
+            description: main.to_string(),
+            ..Default::default()
+        }]
+    }
+
+    // Builds a forecast with the current conditions and one entry per hour following the
+    // fixture time.
+    fn fixture(current: (f32, Main), hourly: &[(f32, Main)]) -> Option<OpenWeather> {
+        let time = fixture_time();
+
+        let mut w: OpenWeather = Default::default();
+        w.current.dt = time.timestamp();
+        w.current.temp = current.0;
+        w.current.weather = weather(current.1);
+        w.hourly = hourly
+            .iter()
+            .enumerate()
+            .map(|(i, &(temp, main))| Hourly {
+                dt: (time + chrono::TimeDelta::try_hours(i as i64 + 1).unwrap()).timestamp(),
+                temp,
+                weather: weather(main),
+                ..Default::default()
+            })
+            .collect();
+
+        Some(w)
+    }
+
+    fn fixtures() -> Vec<(&'static str, Option<OpenWeather>)> {
+        vec![
+            ("no weather", None),
+            (
+                "clear",
+                fixture(
+                    (68.4, Main::Clear),
+                    &[
+                        (70.0, Main::Clear),
+                        (74.6, Main::Clouds),
+                        (65.0, Main::Clear),
+                    ],
+                ),
+            ),
+            (
+                "rain stopping",
+                fixture(
+                    (51.0, Main::Drizzle),
+                    &[
+                        (50.0, Main::Rain),
+                        (49.0, Main::Clouds),
+                        (52.0, Main::Clear),
+                    ],
+                ),
+            ),
+            (
+                "snow starting below zero",
+                fixture(
+                    (-9.6, Main::Clouds),
+                    &[
+                        (-12.0, Main::Clouds),
+                        (-10.0, Main::Snow),
+                        (-4.0, Main::Snow),
+                    ],
+                ),
+            ),
+            (
+                "thunderstorm heat",
+                fixture(
+                    (101.0, Main::Thunderstorm),
+                    &[(104.0, Main::Thunderstorm), (99.0, Main::Thunderstorm)],
+                ),
+            ),
+        ]
+    }
+
+    fn render(frame: &Frame) -> String {
+        match frame {
+            Frame::Text(rows) => rows.iter().map(|r| format!("|{}|\n", r)).collect(),
+            Frame::AlphaNum4 { .. } => format!("[{}]\n", frame.rows()[0]),
+            Frame::SevenSegment4 { digits, colon } => format!(
+                "[{}{}{}{}{}]\n",
+                digits[0],
+                digits[1],
+                if *colon { ':' } else { ' ' },
+                digits[2],
+                digits[3]
+            ),
+        }
+    }
+
+    // Renders every layout in every rotation state, skipping states that don't change the frame.
+    fn render_all(weather: &Option<OpenWeather>) -> String {
+        let mut out = String::new();
+        for layout in LAYOUTS {
+            let mut previous = None;
+            for state in STATES {
+                let frame = frame(layout, state, weather);
+                if previous.as_ref() != Some(&frame) {
+                    out.push_str(&render(&frame));
+                }
+                previous = Some(frame);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_frames_fit_layouts() {
+        for (name, weather) in fixtures() {
+            for layout in [Layout::Text16x2, Layout::Text20x4] {
+                for state in STATES {
+                    let rows = match frame(layout, state, &weather) {
+                        Frame::Text(rows) => rows,
+                        f => panic!("unexpected frame for text layout: {:?}", f),
+                    };
+
+                    assert_eq!(rows.len(), layout.rows(), "{}: {:?}", name, layout);
+                    for row in rows {
+                        assert_eq!(
+                            row.chars().count(),
+                            layout.columns(),
+                            "{}: {:?} row {:?}",
+                            name,
+                            layout,
+                            row
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_weather_icons() {
+        let icon = |main, icon: &str| {
+            weather_icon(&Weather {
+                main,
+                icon: icon.to_string(),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(icon(Main::Clear, "01d"), '☀');
+        assert_eq!(icon(Main::Clear, "01n"), '☾');
+        assert_eq!(icon(Main::Clouds, "04n"), '☁');
+        assert_eq!(icon(Main::Haze, "50d"), '≡');
+        assert_eq!(icon(Main::Tornado, "50d"), '☇');
+    }
+
+    #[test]
+    fn test_hourly_sparkline() {
+        let time = fixture_time();
+        let hourly = |temp: fn(i64) -> f32| {
+            Some(OpenWeather {
+                hourly: (0..24)
+                    .map(|i| Hourly {
+                        dt: time.timestamp() + (i + 1) * 3600,
+                        temp: temp(i),
+                        pop: (i as f32 / 7.0).min(1.0),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+        };
+        let sparkline =
+            |screen, weather: &Option<OpenWeather>| hourly_sparkline(&time, Some(screen), weather);
+
+        // The next 20 hours, scaled between the low and the high
+        let alternating = hourly(|i| if i % 2 == 0 { 40.0 } else { 60.0 });
+        assert_eq!(sparkline(Screen::High, &alternating), " ▇".repeat(10));
+        assert_eq!(
+            sparkline(Screen::Precipitation, &alternating),
+            format!(" ▁▂▃▄▅▆{}", "▇".repeat(13))
+        );
+        assert_eq!(sparkline(Screen::Low, &hourly(|_| 50.0)), "▄".repeat(20));
+
+        assert_eq!(sparkline(Screen::High, &Some(Default::default())), "");
+        assert_eq!(sparkline(Screen::High, &None), "");
+    }
+
+    #[test]
+    fn test_golden_frames() {
+        let expected = [
+            "\
+|09:05    WEATHER|
+|Mon Jan 15   ERR|
+|09:05        WEATHER|
+|Mon Jan 15       ERR|
+|                    |
+|                    |
+[ERR ]
+[09:05]
+",
+            "\
+|09:05    ☀ Clear|
+|Mon Jan 15  68°F|
+|09:05        ☀ Clear|
+|Mon Jan 15      68°F|
+|                    |
+|No rain for next 24h|
+|09:05        ☀ Clear|
+|Mon Jan 15      68°F|
+|▄▇                  |
+|High: 75°F at 11:00 |
+|09:05        ☀ Clear|
+|Mon Jan 15      68°F|
+|▄▇                  |
+|Low: 65°F at 12:00  |
+[ 68F]
+[Clea]
+[ 68F]
+[09:05]
+",
+            "\
+|09:05  ☂ Drizzle|
+|Mon Jan 15  51°F|
+|09:05      ☂ Drizzle|
+|Mon Jan 15      51°F|
+|                    |
+|Rain stops at 11:00 |
+|09:05      ☂ Drizzle|
+|Mon Jan 15      51°F|
+|▂ ▇                 |
+|High: 52°F at 12:00 |
+|09:05      ☂ Drizzle|
+|Mon Jan 15      51°F|
+|▂ ▇                 |
+|Low: 49°F at 11:00  |
+[ 51F]
+[Driz]
+[ 51F]
+[09:05]
+",
+            "\
+|09:05   ☁ Clouds|
+|Mon Jan 15 -10°F|
+|09:05       ☁ Clouds|
+|Mon Jan 15     -10°F|
+|                    |
+|Snow starts at 11:00|
+|09:05       ☁ Clouds|
+|Mon Jan 15     -10°F|
+| ▂▇                 |
+|High: -4°F at 12:00 |
+|09:05       ☁ Clouds|
+|Mon Jan 15     -10°F|
+| ▂▇                 |
+|Low: -12°F at 10:00 |
+[-10F]
+[Clou]
+[-10F]
+[09:05]
+",
+            "\
+|09:05 ☇ T'rstorm|
+|Mon Jan 15 101°F|
+|09:05 ☇ Thunderstorm|
+|Mon Jan 15     101°F|
+|                    |
+|Rain for next 24h   |
+|09:05 ☇ Thunderstorm|
+|Mon Jan 15     101°F|
+|▇                   |
+|High: 104°F at 10:00|
+|09:05 ☇ Thunderstorm|
+|Mon Jan 15     101°F|
+|▇                   |
+|Low: 99°F at 11:00  |
+[101F]
+[Thun]
+[101F]
+[09:05]
+",
+        ];
+
+        for ((name, weather), expected) in fixtures().iter().zip(expected) {
+            assert_eq!(render_all(weather), expected, "fixture: {}", name);
+        }
+    }
+
+    #[test]
+    fn test_celsius() {
+        let (_, weather) = &fixtures()[1];
+        let fetch_status = FetchStatus::default();
+        let ctx = |current_state_index| RenderContext {
+            time: fixture_time(),
+            current_state_index,
+            scroll_step: 0,
+            weather,
+            light: 1.0,
+            fetch_status: &fetch_status,
+            unit: TemperatureUnit::Celsius,
+            message: None,
+        };
+
+        assert_eq!(
+            render(&Layout::Text20x4.frame(&ctx(1))),
+            "\
+|09:05        ☀ Clear|
+|Mon Jan 15      20°C|
+|▄▇                  |
+|High: 24°C at 11:00 |
+"
+        );
+        assert_eq!(
+            render(&Layout::Text16x2.frame(&ctx(2))),
+            "|09:05    ☀ Clear|\n|Mon Jan 15  20°C|\n"
+        );
+        assert_eq!(render(&Layout::AlphaNum4.frame(&ctx(2))), "[ 20C]\n");
+        assert_eq!(
+            TemperatureUnit::Celsius.toggled(),
+            TemperatureUnit::Fahrenheit
+        );
+    }
+
+    #[test]
+    fn test_playlists() {
+        let (_, weather) = &fixtures()[1];
+        let fetch_status = FetchStatus::default();
+        let ctx = |current_state_index| RenderContext {
+            time: fixture_time(),
+            current_state_index,
+            scroll_step: 0,
+            weather,
+            light: 1.0,
+            fetch_status: &fetch_status,
+            unit: TemperatureUnit::Fahrenheit,
+            message: None,
+        };
+
+        let view = View {
+            layout: Layout::AlphaNum4,
+            playlist: vec![Screen::High, Screen::Low, Screen::Time, Screen::Temperature],
+        };
+        let frames = (0..5)
+            .map(|i| render(&view.frame(&ctx(i))))
+            .collect::<String>();
+        assert_eq!(frames, "[H 75]\n[L 65]\n[0905]\n[ 68F]\n[H 75]\n");
+
+        // Each step of the rotation shows the next screen, whatever the length of the playlist
+        let view = View {
+            layout: Layout::Text20x4,
+            playlist: vec![Screen::Low],
+        };
+        assert_eq!(view.frame(&ctx(7)), view.frame(&ctx(0)));
+        assert!(render(&view.frame(&ctx(7))).contains("|Low: 65°F at 12:00  |"));
+
+        assert_eq!("high".parse(), Ok(Screen::High));
+        assert!("humidity".parse::<Screen>().is_err());
+        assert_eq!(
+            Layout::AlphaNum4.default_playlist(),
+            vec![Screen::Temperature, Screen::Condition]
+        );
+        assert!(Layout::SevenSegment4.screens().is_empty());
+    }
+
+    #[test]
+    fn test_message() {
+        let fetch_status = FetchStatus::default();
+        let message_frame = |layout: Layout| {
+            layout.frame(&RenderContext {
+                time: fixture_time(),
+                current_state_index: 1,
+                scroll_step: 0,
+                weather: &None,
+                light: 1.0,
+                fetch_status: &fetch_status,
+                unit: TemperatureUnit::Fahrenheit,
+                message: Some(Message {
+                    text: "Laundry done, take it out before it wrinkles",
+                }),
+            })
+        };
+
+        assert_eq!(
+            render(&message_frame(Layout::Text20x4)),
+            "\
+|09:05        WEATHER|
+|Mon Jan 15       ERR|
+|Laundry done, take  |
+|it out before it    |
+"
+        );
+        assert_eq!(
+            render(&message_frame(Layout::Text16x2)),
+            "|09:05    WEATHER|\n|Laundry done,   |\n"
+        );
+        assert_eq!(render(&message_frame(Layout::AlphaNum4)), "[Laun]\n");
+        assert_eq!(render(&message_frame(Layout::SevenSegment4)), "[09:05]\n");
+    }
+
+    #[test]
+    fn test_marquee() {
+        let scroll = |message, steps| marquee(message, steps).rows()[0].clone();
+
+        assert_eq!(scroll("Hi", 0), "Hi  ");
+        assert_eq!(scroll("Hi", 3), "Hi  ");
+        assert_eq!(
+            (0..10).map(|s| scroll("Done!", s)).collect::<Vec<_>>(),
+            vec!["Done", "one!", "ne! ", "e!  ", "!   ", "    ", "   D", "  Do", " Don", "Done"]
+        );
+
+        // Full stops light the decimal point of the character before them
+        assert_eq!(scroll("68.4F", 5), "68.4F");
+        assert_eq!(
+            marquee("1.5", 0),
+            Frame::AlphaNum4 {
+                chars: ['1', '5', ' ', ' '],
+                dots: [true, false, false, false]
+            }
+        );
+        assert_eq!(scroll("...", 0), "... ");
+        assert_eq!(
+            (0..3).map(|s| scroll("Go. Now.", s)).collect::<Vec<_>>(),
+            vec!["Go. N", "o. No", " Now."]
+        );
+    }
+
+    #[test]
+    fn test_wrap_message() {
+        assert_eq!(wrap_message("", 4, 1), vec!["    "]);
+        assert_eq!(wrap_message("Hi °C", 4, 2), vec!["Hi  ", "?C  "]);
+        assert_eq!(
+            wrap_message("Supercalifragilistic", 8, 2),
+            vec!["Supercal", "ifragili"]
+        );
+    }
+
+    #[test]
+    fn test_boxed() {
+        assert_eq!(
+            Frame::Text(vec!["12:00".to_string(), "-10°F too long".to_string()]).boxed(5),
+            "-------\n|12:00|\n|-10°F too long|\n-------\n"
+        );
+        assert_eq!(
+            "seven_segment4".parse::<Layout>(),
+            Ok(Layout::SevenSegment4)
+        );
+        assert!("40x2".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(frame(Layout::Text16x2, 0, &None).segments(), None);
+        assert_eq!(
+            frame(Layout::AlphaNum4, 0, &None).segments(),
+            Some([
+                alphanum_segments('E'),
+                alphanum_segments('R'),
+                alphanum_segments('R'),
+                0
+            ])
+        );
+        assert_eq!(
+            frame(Layout::SevenSegment4, 0, &None).segments(),
+            Some([0x3F | 0x100, 0x6F | 0x100, 0x3F | 0x100, 0x6D | 0x100])
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_characters() {
+        assert_eq!(truncate_to_characters("", 3), "");
+        assert_eq!(truncate_to_characters("a", 3), "a");
+        assert_eq!(truncate_to_characters("ab", 3), "ab");
+        assert_eq!(truncate_to_characters("abc", 3), "abc");
+        assert_eq!(truncate_to_characters("abcd", 3), "a'd");
+        assert_eq!(truncate_to_characters("abcdefg", 5), "a'efg");
+        assert_eq!(truncate_to_characters("Tornado", 7), "Tornado");
+        assert_eq!(truncate_to_characters("Thunderstorm", 7), "T'storm");
+    }
+
+    #[test]
+    fn test_split_time() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            split_time(&Local::now().with_hour(1).unwrap().with_minute(3).unwrap()),
+            [0, 1, 0, 3]
+        );
+        assert_eq!(
+            split_time(&Local::now().with_hour(0).unwrap().with_minute(0).unwrap()),
+            [0, 0, 0, 0]
+        );
+        assert_eq!(
+            split_time(&Local::now().with_hour(12).unwrap().with_minute(34).unwrap()),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            split_time(&Local::now().with_hour(23).unwrap().with_minute(59).unwrap()),
+            [2, 3, 5, 9]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+// An emulated HD44780 character LCD controller, which takes the same instructions and data as the
+// real one, so that what the LCD displays would show can be checked without one.
+
+use super::font::{hd44780_glyph, CUSTOM_GLYPHS};
+use super::lcd::{CharacterLcd, LCD_ROW_OFFSETS};
+use super::Error;
+
+// The two lines of DDRAM are 40 bytes long, at 0x00 and 0x40.
+const DDRAM_LINE_LENGTH: u8 = 40;
+const DDRAM_SECOND_LINE: u8 = 0x40;
+
+const CGRAM_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ram {
+    Ddram,
+    Cgram,
+}
+
+#[derive(Clone, Debug)]
+pub struct HD44780Emulator {
+    columns: usize,
+    rows: usize,
+
+    // Character codes, addressed like the real DDRAM, where only 0x00-0x27 and 0x40-0x67 exist.
+    ddram: [u8; 0x80],
+
+    // The glyphs of the 8 custom characters, 8 rows of 5 pixels each.
+    cgram: [u8; CGRAM_SIZE],
+
+    // The address counter, and which RAM data is written to.
+    address: u8,
+    ram: Ram,
+
+    // Whether the address counter counts up or down after each write.
+    increment: bool,
+
+    display_on: bool,
+}
+
+impl HD44780Emulator {
+    // An LCD of the given size, as it is after power-on.
+    pub fn new(columns: usize, rows: usize) -> Self {
+        HD44780Emulator {
+            columns,
+            rows,
+            ddram: [b' '; 0x80],
+            cgram: [0; CGRAM_SIZE],
+            address: 0,
+            ram: Ram::Ddram,
+            increment: true,
+            display_on: false,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.display_on
+    }
+
+    // The DDRAM address of the cursor, or None when it's in CGRAM.
+    pub fn cursor(&self) -> Option<u8> {
+        match self.ram {
+            Ram::Ddram => Some(self.address),
+            Ram::Cgram => None,
+        }
+    }
+
+    // The character code shown at a row and column.
+    pub fn code_at(&self, row: usize, column: usize) -> u8 {
+        self.ddram[LCD_ROW_OFFSETS[row] as usize + column]
+    }
+
+    // What each row shows, with characters from the A00 character ROM. Custom characters are shown
+    // as the character whose glyph CGRAM holds for them, or the replacement character when it's
+    // not one of the clock's.
+    pub fn text(&self) -> Vec<String> {
+        (0..self.rows)
+            .map(|row| {
+                (0..self.columns)
+                    .map(|column| match self.code_at(row, column) {
+                        0x00..=0x0F => custom_char(&self.glyph_at(row, column)),
+                        code => rom_char(code),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The 5x8 pixels shown at a row and column, from CGRAM for custom characters.
+    pub fn glyph_at(&self, row: usize, column: usize) -> [u8; 8] {
+        match self.code_at(row, column) {
+            code @ 0x00..=0x0F => {
+                let start = (code as usize & 0x07) * 8;
+                let mut glyph = [0; 8];
+                glyph.copy_from_slice(&self.cgram[start..start + 8]);
+                glyph
+            }
+            code => hd44780_glyph(code),
+        }
+    }
+
+    fn advance(&mut self) {
+        self.address = match (self.ram, self.increment) {
+            (Ram::Cgram, true) => (self.address + 1) % CGRAM_SIZE as u8,
+            (Ram::Cgram, false) => self.address.checked_sub(1).unwrap_or(CGRAM_SIZE as u8 - 1),
+
+            // The end of the first line is followed by the start of the second, and the other way
+            // around. So are the addresses past the end of each line that the cursor can be set to.
+            (Ram::Ddram, true) => match self.address + 1 {
+                a if a >= DDRAM_SECOND_LINE + DDRAM_LINE_LENGTH => 0,
+                a if (DDRAM_LINE_LENGTH..DDRAM_SECOND_LINE).contains(&a) => DDRAM_SECOND_LINE,
+                a => a,
+            },
+            (Ram::Ddram, false) => match self.address {
+                0 => DDRAM_SECOND_LINE + DDRAM_LINE_LENGTH - 1,
+                DDRAM_SECOND_LINE => DDRAM_LINE_LENGTH - 1,
+                a => a - 1,
+            },
+        };
+    }
+}
+
+impl CharacterLcd for HD44780Emulator {
+    fn command(&mut self, byte: u8) -> Result<(), Error> {
+        match byte {
+            0x80..=0xFF => {
+                self.ram = Ram::Ddram;
+                self.address = byte & 0x7F;
+            }
+            0x40..=0x7F => {
+                self.ram = Ram::Cgram;
+                self.address = byte & 0x3F;
+            }
+            // Function set: the bus width, lines and font don't change what's stored
+            0x20..=0x3F => {}
+            // Cursor or display shift. Only moving the cursor is emulated.
+            0x10..=0x1F => {
+                if byte & 0x08 == 0 {
+                    let increment = self.increment;
+                    self.increment = byte & 0x04 != 0;
+                    self.advance();
+                    self.increment = increment;
+                }
+            }
+            0x08..=0x0F => self.display_on = byte & 0x04 != 0,
+            0x04..=0x07 => self.increment = byte & 0x02 != 0,
+            // Return home
+            0x02..=0x03 => {
+                self.ram = Ram::Ddram;
+                self.address = 0;
+            }
+            // Clear display
+            0x01 => {
+                self.ddram = [b' '; 0x80];
+                self.ram = Ram::Ddram;
+                self.address = 0;
+                self.increment = true;
+            }
+            0x00 => {}
+        }
+
+        Ok(())
+    }
+
+    fn data(&mut self, byte: u8) -> Result<(), Error> {
+        match self.ram {
+            Ram::Ddram => self.ddram[self.address as usize] = byte,
+            Ram::Cgram => self.cgram[self.address as usize] = byte & 0x1F,
+        }
+        self.advance();
+
+        Ok(())
+    }
+}
+
+// The character that the A00 ROM shows for a code, as near as Unicode has it.
+fn rom_char(code: u8) -> char {
+    match code {
+        b'\\' => '¥',
+        0x20..=0x7D => code as char,
+        0x7E => '→',
+        0x7F => '←',
+        0xDF => '°',
+        0xFF => '█',
+        _ => ' ',
+    }
+}
+
+fn custom_char(glyph: &[u8; 8]) -> char {
+    CUSTOM_GLYPHS
+        .iter()
+        .find(|(_, custom)| custom == glyph)
+        .map_or('\u{FFFD}', |(c, _)| *c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::lcd::LcdPanel;
+    use crate::display::{Layout, RenderContext, TemperatureUnit, View};
+    use crate::weather::open_weather_types::{Hourly, Main, Weather};
+    use crate::weather::{FetchStatus, OpenWeather};
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn test_addressing() {
+        let mut lcd = HD44780Emulator::new(20, 4);
+        lcd.init().unwrap();
+        assert!(lcd.is_on());
+
+        // The first row runs on into the third, and the second into the fourth
+        lcd.set_cursor(0x00).unwrap();
+        lcd.write(&[b'a'; 40]).unwrap();
+        lcd.set_cursor(0x40).unwrap();
+        lcd.write(&[b'b'; 40]).unwrap();
+        assert_eq!(
+            lcd.text(),
+            vec![
+                "a".repeat(20),
+                "b".repeat(20),
+                "a".repeat(20),
+                "b".repeat(20)
+            ]
+        );
+
+        // The end of DDRAM's first line is followed by its second line
+        lcd.set_cursor(0x27).unwrap();
+        lcd.write(b"xy").unwrap();
+        assert_eq!(lcd.cursor(), Some(0x41));
+        assert_eq!(lcd.code_at(1, 0), b'y');
+
+        // Addresses past the end of a line run on into the next line
+        lcd.set_cursor(0x7F).unwrap();
+        lcd.write(b"z").unwrap();
+        assert_eq!(lcd.cursor(), Some(0x00));
+        lcd.set_cursor(0x68).unwrap();
+        lcd.write(b"z").unwrap();
+        assert_eq!(lcd.cursor(), Some(0x00));
+        lcd.set_cursor(0x30).unwrap();
+        lcd.write(b"z").unwrap();
+        assert_eq!(lcd.cursor(), Some(0x40));
+        assert_eq!(lcd.text()[0], "a".repeat(20));
+
+        lcd.set_cursor(0x14).unwrap();
+        lcd.write(b"12\\~\xDF\xFF").unwrap();
+        assert_eq!(lcd.text()[2], format!("12¥→°█{}x", "a".repeat(13)));
+
+        lcd.clear().unwrap();
+        assert_eq!(lcd.text(), vec![" ".repeat(20); 4]);
+        assert_eq!(lcd.cursor(), Some(0));
+    }
+
+    #[test]
+    fn test_cgram() {
+        let mut lcd = HD44780Emulator::new(16, 2);
+        let glyph = [0b00100, 0b01110, 0b11111, 0, 0, 0, 0, 0];
+
+        // Custom characters are stored at 8 bytes each, and the cursor goes back to DDRAM once it's
+        // set again
+        lcd.command(0x40 | (2 * 8)).unwrap();
+        lcd.write(&glyph).unwrap();
+        assert_eq!(lcd.cursor(), None);
+        lcd.set_cursor(0x41).unwrap();
+        lcd.write(&[2, b'A']).unwrap();
+
+        assert_eq!(lcd.glyph_at(1, 1), glyph);
+        assert_eq!(lcd.glyph_at(1, 2), hd44780_glyph(b'A'));
+        assert_eq!(lcd.text()[1], " \u{FFFD}A             ");
+    }
+
+    #[test]
+    fn test_lcd_displays() {
+        let time = Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap();
+        let conditions = vec![Weather {
+            main: Main::Clouds,
+            ..Default::default()
+        }];
+        let mut weather: OpenWeather = Default::default();
+        weather.current.dt = time.timestamp();
+        weather.current.temp = 45.0;
+        weather.current.weather = conditions.clone();
+        weather.hourly = vec![Hourly {
+            dt: time.timestamp() + 3600,
+            temp: 48.0,
+            weather: conditions,
+            ..Default::default()
+        }];
+
+        let fetch_status = FetchStatus::default();
+        let ctx = RenderContext {
+            time,
+            current_state_index: 1,
+            scroll_step: 0,
+            weather: &Some(weather),
+            light: 1.0,
+            fetch_status: &fetch_status,
+            unit: TemperatureUnit::Fahrenheit,
+            message: None,
+        };
+
+        // What the LCDs show is what the frame says, with ° from the character ROM
+        for layout in [Layout::Text16x2, Layout::Text20x4] {
+            let lcd = HD44780Emulator::new(layout.columns(), layout.rows());
+            let mut panel = LcdPanel::new(lcd, View::new(layout));
+            panel.init().unwrap();
+            panel.print(&ctx).unwrap();
+
+            let rows = layout.frame(&ctx).rows();
+            assert!(rows[1].contains("45°"), "{:?}", rows);
+            assert_eq!(panel.lcd().text(), rows);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// An emulated HT16K33 LED controller on an I2C bus, which takes the same transfers as the real one
+// and records what they did, so that what a segment display would show can be checked without one.
+
+use super::font::ALPHANUM_DOT;
+use super::{alphanum_segments, seven_segment_digit, Error};
+
+use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+use ht16k33::{Dimming, Display, Oscillator, ROWS_SIZE};
+
+// Display RAM as words, one for each of the controller's 8 commons. The backpacks wire one digit
+// to each, with the 16 bits of its segments stored low byte first.
+const DISPLAY_RAM_WORDS: usize = ROWS_SIZE / 2;
+
+// Commands have their settings in the low bits.
+const SYSTEM_SETUP: u8 = Oscillator::COMMAND.bits();
+const DISPLAY_SETUP: u8 = Display::COMMAND.bits();
+const DIMMING_SET: u8 = Dimming::COMMAND.bits();
+
+// The 7-segment backpack has its colon wired to the middle digit's second segment.
+const SEVEN_SEGMENT_COLON_DIGIT: usize = 2;
+const SEVEN_SEGMENT_COLON: u16 = 0x02;
+
+#[derive(Clone, Debug, Default)]
+pub struct HT16K33Emulator {
+    ram: [u8; ROWS_SIZE],
+
+    oscillator_on: bool,
+    display_on: bool,
+    dimming: Option<u8>,
+
+    // What was written to display RAM and the dimming levels set, oldest first.
+    ram_writes: Vec<[u16; DISPLAY_RAM_WORDS]>,
+    dimming_writes: Vec<u8>,
+}
+
+impl HT16K33Emulator {
+    // A controller as it is after power-on.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Whether the display is lit, which needs the oscillator running as well.
+    pub fn is_on(&self) -> bool {
+        self.oscillator_on && self.display_on
+    }
+
+    // The dimming level set last, from 0 to 15.
+    pub fn dimming(&self) -> Option<u8> {
+        self.dimming
+    }
+
+    // Display RAM, a word for each digit.
+    pub fn ram(&self) -> [u16; DISPLAY_RAM_WORDS] {
+        let mut words = [0; DISPLAY_RAM_WORDS];
+        for (word, bytes) in words.iter_mut().zip(self.ram.chunks(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        words
+    }
+
+    // Display RAM as it was after each write to it.
+    pub fn ram_writes(&self) -> &[[u16; DISPLAY_RAM_WORDS]] {
+        &self.ram_writes
+    }
+
+    pub fn dimming_writes(&self) -> &[u8] {
+        &self.dimming_writes
+    }
+
+    // What a 4-character 14-segment backpack shows, with decimal points as '.' after their
+    // character. Segments that aren't a character are shown as '?'. Some characters look the same,
+    // e.g. 'I' and ':', so letters and digits are preferred.
+    pub fn alphanum_text(&self) -> String {
+        let mut text = String::new();
+        for &word in &self.ram()[..4] {
+            let segments = word & !ALPHANUM_DOT;
+            let c = ('A'..='Z')
+                .chain('0'..='9')
+                .chain(' '..='~')
+                .find(|&c| alphanum_segments(c) == segments)
+                .unwrap_or('?');
+            text.push(c);
+            if word & ALPHANUM_DOT != 0 {
+                text.push('.');
+            }
+        }
+        text
+    }
+
+    // What a 4-digit 7-segment backpack shows, written the way `Frame::rows` writes it: the digits
+    // with ':' or ' ' for the colon between them. Unlit digits are shown as ' ' and segments that
+    // aren't a digit as '?'.
+    pub fn seven_segment_text(&self) -> String {
+        let ram = self.ram();
+        let digit = |word: u16| match word as u8 & 0x7F {
+            0 => ' ',
+            segments => (0..10)
+                .find(|&d| seven_segment_digit(d) == segments)
+                .map_or('?', |d| (b'0' + d) as char),
+        };
+        let colon = if ram[SEVEN_SEGMENT_COLON_DIGIT] & SEVEN_SEGMENT_COLON != 0 {
+            ':'
+        } else {
+            ' '
+        };
+
+        [
+            digit(ram[0]),
+            digit(ram[1]),
+            colon,
+            digit(ram[3]),
+            digit(ram[4]),
+        ]
+        .iter()
+        .collect()
+    }
+}
+
+impl Write for HT16K33Emulator {
+    type Error = Error;
+
+    // Every command is a single write whose first byte says what it is. The bus has no other
+    // devices, so the address isn't checked.
+    fn write(&mut self, _address: u8, bytes: &[u8]) -> Result<(), Error> {
+        let (&command, data) = match bytes.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+
+        match command & 0xF0 {
+            // Display RAM, written from the address onwards, wrapping at its end
+            0x00 => {
+                for (i, &byte) in data.iter().enumerate() {
+                    self.ram[(command as usize + i) % ROWS_SIZE] = byte;
+                }
+                self.ram_writes.push(self.ram());
+            }
+            SYSTEM_SETUP => self.oscillator_on = command & 0x01 != 0,
+            // Blinking isn't emulated
+            DISPLAY_SETUP => self.display_on = command & 0x01 != 0,
+            DIMMING_SET => {
+                let level = command & 0x0F;
+                self.dimming = Some(level);
+                self.dimming_writes.push(level);
+            }
+            // Key scanning and the ROW/INT pin don't change what's shown
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteRead for HT16K33Emulator {
+    type Error = Error;
+
+    // Reads display RAM from the address written, wrapping at its end.
+    fn write_read(&mut self, _address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        let start = bytes.first().map_or(0, |&b| b as usize & 0x0F);
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.ram[(start + i) % ROWS_SIZE];
+        }
+        Ok(())
+    }
+}
+
+// So that a panel can borrow the emulator and it can be looked at in between.
+impl Write for &mut HT16K33Emulator {
+    type Error = Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        (**self).write(address, bytes)
+    }
+}
+
+impl WriteRead for &mut HT16K33Emulator {
+    type Error = Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        (**self).write_read(address, bytes, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::backpack::SegmentPanel;
+    use crate::display::{Layout, Message, RenderContext, TemperatureUnit, View};
+    use crate::weather::{FetchStatus, OpenWeather};
+    use chrono::{Local, TimeZone};
+    use ht16k33::HT16K33;
+
+    const ADDRESS: u8 = 0x70;
+
+    // A panel on the emulator. Panels only keep what they last showed, so the clock's ticks can
+    // each use a new one and the emulator can be looked at in between.
+    fn panel(backpack: &mut HT16K33Emulator, view: View) -> SegmentPanel<&mut HT16K33Emulator> {
+        SegmentPanel::new(backpack, ADDRESS, view)
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut backpack = HT16K33Emulator::new();
+        let mut ht16k33 = HT16K33::new(&mut backpack, ADDRESS);
+        ht16k33.initialize().unwrap();
+        ht16k33.set_display(Display::ON).unwrap();
+        assert!(backpack.is_on());
+        assert_eq!(backpack.dimming(), Some(15));
+        assert_eq!(backpack.alphanum_text(), "    ");
+        assert_eq!(backpack.seven_segment_text(), "     ");
+
+        // Writes can start part of the way through display RAM
+        backpack.write(ADDRESS, &[0x02, 0xF7, 0x00]).unwrap();
+        backpack
+            .write(
+                ADDRESS,
+                &[0x06, (alphanum_segments('Z') | ALPHANUM_DOT) as u8, 0x4C],
+            )
+            .unwrap();
+        assert_eq!(backpack.alphanum_text(), " A Z.");
+        assert_eq!(backpack.ram_writes().len(), 3);
+
+        // What's in display RAM can be read back
+        let mut ht16k33 = HT16K33::new(&mut backpack, ADDRESS);
+        ht16k33.read_display_buffer().unwrap();
+        assert_eq!(ht16k33.display_buffer()[2].bits(), 0xF7);
+        ht16k33.set_display(Display::OFF).unwrap();
+        assert!(!backpack.is_on());
+        assert_eq!(backpack.alphanum_text(), " A Z.");
+    }
+
+    #[test]
+    fn test_segment_displays() {
+        let time = Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap();
+        let mut weather: OpenWeather = Default::default();
+        weather.current.temp = 45.0;
+        let weather = Some(weather);
+        let fetch_status = FetchStatus::default();
+
+        let mut ctx = RenderContext {
+            time,
+            current_state_index: 0,
+            scroll_step: 0,
+            weather: &weather,
+            light: 0.0,
+            fetch_status: &fetch_status,
+            unit: TemperatureUnit::Fahrenheit,
+            message: None,
+        };
+
+        let mut seven_segment = HT16K33Emulator::new();
+        let mut panel_7 = panel(&mut seven_segment, Layout::SevenSegment4.into());
+        panel_7.init().unwrap();
+        panel_7.print(&ctx).unwrap();
+        assert_eq!(seven_segment.seven_segment_text(), "09:05");
+
+        // The temperature, then the time; and what's shown when there's no weather
+        let view = View {
+            layout: Layout::AlphaNum4,
+            playlist: Layout::AlphaNum4.screens(),
+        };
+        let mut alphanum = HT16K33Emulator::new();
+        panel(&mut alphanum, view.clone()).init().unwrap();
+        panel(&mut alphanum, view.clone()).print(&ctx).unwrap();
+        assert_eq!(alphanum.alphanum_text(), " 45F");
+        ctx.current_state_index = 4;
+        panel(&mut alphanum, view.clone()).print(&ctx).unwrap();
+        assert_eq!(alphanum.alphanum_text(), "0905");
+        ctx.current_state_index = 0;
+        ctx.weather = &None;
+        panel(&mut alphanum, view.clone()).print(&ctx).unwrap();
+        assert_eq!(alphanum.alphanum_text(), "ERR ");
+
+        ctx.message = Some(Message { text: "HI" });
+        panel(&mut alphanum, view.clone()).print(&ctx).unwrap();
+        assert_eq!(alphanum.alphanum_text(), "HI  ");
+
+        // Dimming follows the light level, and the display is turned off rather than blanked
+        for light in [0.5, 1.0, 2.0] {
+            ctx.light = light;
+            panel(&mut alphanum, view.clone()).print(&ctx).unwrap();
+        }
+        assert_eq!(alphanum.dimming_writes(), [15, 0, 0, 0, 0, 8, 15, 15]);
+        panel(&mut alphanum, view).shutdown().unwrap();
+        assert!(!alphanum.is_on());
+        assert_eq!(alphanum.alphanum_text(), "HI  ");
+    }
+}
@@ -0,0 +1,531 @@
+// Updates HD44780 character LCDs by writing only the cells that have changed since the last
+// frame, rather than rewriting every row, which flickers and keeps the bus busy.
+
+use super::{custom_glyph, str_to_lcd_bytes, Error, Frame, RenderContext, View};
+use std::time::Duration;
+
+// DDRAM addresses of the start of each row. Rows 3 and 4 continue rows 1 and 2 respectively.
+pub(super) const LCD_ROW_OFFSETS: [u8; 4] = [0x00, 0x40, 0x14, 0x54];
+
+// Updates after which every cell is written again, in case a glitch on the bus left the display
+// showing something other than what was written.
+const FULL_REFRESH_EVERY: u32 = 60;
+
+// Custom characters that CGRAM holds, as codes 0-7.
+pub(super) const CGRAM_SLOTS: usize = 8;
+
+// HD44780 instructions.
+const CLEAR_DISPLAY: u8 = 0x01;
+// Entry mode: the cursor moves right after each character, and the display doesn't shift
+const ENTRY_MODE_INCREMENT: u8 = 0x06;
+// Display control: the display on, and the cursor off and not blinking
+const DISPLAY_ON: u8 = 0x0C;
+// Function set: a 4-bit bus, 2 lines of DDRAM and 5x8 characters
+const FUNCTION_SET_4BIT: u8 = 0x28;
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+
+// How long the controller needs to power on, and to take the function sets that reset the bus,
+// which is longer than the bus waits after each nibble.
+const POWER_ON_WAIT: Duration = Duration::from_millis(15);
+const FIRST_RESET_WAIT: Duration = Duration::from_micros(4100);
+const RESET_WAIT: Duration = Duration::from_micros(100);
+
+// The bus of an HD44780 controller, which takes instructions and the data to store at the address
+// they set. It's implemented by real LCDs and by HD44780Emulator.
+pub trait CharacterLcd {
+    // Sends an instruction, with RS low.
+    fn command(&mut self, byte: u8) -> Result<(), Error>;
+
+    // Stores a byte in DDRAM or CGRAM at the address counter, which then moves on, with RS high.
+    fn data(&mut self, byte: u8) -> Result<(), Error>;
+
+    // Waits for the controller to finish an instruction. Emulated controllers don't need to.
+    fn wait(&mut self, _duration: Duration) {}
+
+    // Sets the controller up from any state, including the middle of a 4-bit transfer, with the
+    // display on and cleared.
+    fn init(&mut self) -> Result<(), Error> {
+        // Resynchronises a 4-bit bus: each nibble is taken as an 8-bit function set until 0x2
+        // switches it to 4 bits
+        self.wait(POWER_ON_WAIT);
+        self.command(0x33)?;
+        self.wait(FIRST_RESET_WAIT);
+        self.command(0x32)?;
+        self.wait(RESET_WAIT);
+
+        self.command(FUNCTION_SET_4BIT)?;
+        self.command(DISPLAY_ON)?;
+        self.command(ENTRY_MODE_INCREMENT)?;
+        self.clear()
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        self.command(CLEAR_DISPLAY)
+    }
+
+    // Moves the cursor to a DDRAM address.
+    fn set_cursor(&mut self, address: u8) -> Result<(), Error> {
+        self.command(SET_DDRAM_ADDRESS | (address & 0x7F))
+    }
+
+    // Moves the cursor to a CGRAM address, where the glyphs of custom characters are written 8
+    // rows at a time.
+    fn set_cgram_address(&mut self, address: u8) -> Result<(), Error> {
+        self.command(SET_CGRAM_ADDRESS | (address & 0x3F))
+    }
+
+    // Writes bytes from the cursor onwards.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        bytes.iter().try_for_each(|&b| self.data(b))
+    }
+}
+
+// What's shown on an HD44780 character LCD, however it's connected: the frames of a view, written
+// to the LCD as they change.
+pub struct LcdPanel<L> {
+    lcd: L,
+    shadow: ShadowBuffer,
+    view: View,
+}
+
+impl<L: CharacterLcd> LcdPanel<L> {
+    pub fn new(lcd: L, view: View) -> Self {
+        LcdPanel {
+            lcd,
+            shadow: ShadowBuffer::new(),
+            view,
+        }
+    }
+
+    pub fn lcd(&self) -> &L {
+        &self.lcd
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.shadow.invalidate();
+        self.lcd.init()
+    }
+
+    pub fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        self.shadow.update(&mut self.lcd, &self.view.frame(ctx))
+    }
+
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        self.shadow.invalidate();
+        self.lcd.clear()
+    }
+}
+
+// A copy of what's on the display, to work out which cells need writing.
+#[derive(Debug, Default)]
+pub struct ShadowBuffer {
+    // The bytes of each row, or None when what's on the display isn't known.
+    rows: Option<Vec<Vec<u8>>>,
+
+    // The DDRAM address that the cursor is at, when it's known.
+    cursor: Option<u8>,
+
+    // The custom character loaded into each CGRAM slot, when it's known.
+    cgram: [Option<char>; CGRAM_SLOTS],
+
+    // Updates since every cell was written.
+    updates: u32,
+}
+
+impl ShadowBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Forgets what's on the display, e.g. after it's been set up again, so that every cell is
+    // written next time.
+    pub fn invalidate(&mut self) {
+        self.rows = None;
+        self.cursor = None;
+        self.cgram = [None; CGRAM_SLOTS];
+    }
+
+    // Writes the cells of the frame that differ from what's on the display.
+    pub fn update<L: CharacterLcd + ?Sized>(
+        &mut self,
+        lcd: &mut L,
+        frame: &Frame,
+    ) -> Result<(), Error> {
+        let Frame::Text(rows) = frame else {
+            return Ok(());
+        };
+
+        if self.updates >= FULL_REFRESH_EVERY {
+            self.invalidate();
+        }
+        if self.rows.is_none() {
+            self.updates = 0;
+        }
+        self.updates += 1;
+
+        let result = self.load_custom_chars(lcd, rows).and_then(|()| {
+            let rows = rows
+                .iter()
+                .map(|row| self.lcd_bytes(row))
+                .collect::<Vec<_>>();
+            self.write_changes(lcd, &rows).map(|()| rows)
+        });
+        match result {
+            Ok(rows) => {
+                self.rows = Some(rows);
+                Ok(())
+            }
+            // The write may have got part of the way, so what's on the display isn't known
+            Err(e) => {
+                self.invalidate();
+                Err(e)
+            }
+        }
+    }
+
+    // Loads the custom characters in the rows that CGRAM doesn't already have, into slots that the
+    // rows don't use. Any beyond the 8 that fit are left out.
+    fn load_custom_chars<L: CharacterLcd + ?Sized>(
+        &mut self,
+        lcd: &mut L,
+        rows: &[String],
+    ) -> Result<(), Error> {
+        let mut wanted = vec![];
+        for c in rows.iter().flat_map(|row| row.chars()) {
+            if custom_glyph(c).is_some() && !wanted.contains(&c) {
+                wanted.push(c);
+            }
+        }
+
+        for &c in &wanted {
+            if self.cgram.contains(&Some(c)) {
+                continue;
+            }
+            let free = self
+                .cgram
+                .iter()
+                .position(|loaded| !loaded.is_some_and(|loaded| wanted.contains(&loaded)));
+            let (Some(slot), Some(glyph)) = (free, custom_glyph(c)) else {
+                break;
+            };
+
+            // Writing to CGRAM moves the cursor there, and until the write succeeds, what's in the
+            // slot isn't known
+            self.cursor = None;
+            self.cgram[slot] = None;
+            lcd.set_cgram_address(slot as u8 * 8)?;
+            lcd.write(&glyph)?;
+            self.cgram[slot] = Some(c);
+        }
+
+        Ok(())
+    }
+
+    // The bytes that show a row, with custom characters as the codes of their CGRAM slots.
+    fn lcd_bytes(&self, row: &str) -> Vec<u8> {
+        let mut bytes = vec![];
+        for c in row.chars() {
+            if custom_glyph(c).is_some() {
+                let slot = self.cgram.iter().position(|&loaded| loaded == Some(c));
+                bytes.push(slot.map_or(b' ', |slot| slot as u8));
+            } else {
+                bytes.extend(str_to_lcd_bytes(c.encode_utf8(&mut [0; 4])));
+            }
+        }
+        bytes
+    }
+
+    fn write_changes<L: CharacterLcd + ?Sized>(
+        &mut self,
+        lcd: &mut L,
+        rows: &[Vec<u8>],
+    ) -> Result<(), Error> {
+        for (i, (row, offset)) in rows.iter().zip(LCD_ROW_OFFSETS).enumerate() {
+            let shown = self.rows.as_ref().and_then(|rows| rows.get(i));
+
+            for (start, end) in changed_runs(shown.map(|r| r.as_slice()), row) {
+                let address = offset + start as u8;
+                if self.cursor != Some(address) {
+                    lcd.set_cursor(address)?;
+                }
+                // Until the write succeeds, where the cursor is isn't known
+                self.cursor = None;
+                lcd.write(&row[start..end])?;
+                self.cursor = Some(address + (end - start) as u8);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The ranges of cells that differ between what's shown and the new row, or the whole row when
+// what's shown isn't known.
+fn changed_runs(shown: Option<&[u8]>, row: &[u8]) -> Vec<(usize, usize)> {
+    let changed = |i: usize| shown.and_then(|shown| shown.get(i)) != Some(&row[i]);
+
+    let mut runs = vec![];
+    let mut start = None;
+    for i in 0..row.len() {
+        match (start, changed(i)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                runs.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push((s, row.len()));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::HD44780Emulator;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Op {
+        SetCursor(u8),
+        SetCgramAddress(u8),
+        Write(Vec<u8>),
+        Wait(Duration),
+    }
+
+    // Records what's sent to an emulated LCD.
+    struct MockLcd {
+        lcd: HD44780Emulator,
+        ops: Vec<Op>,
+        fail: bool,
+    }
+
+    impl MockLcd {
+        fn new() -> Self {
+            MockLcd {
+                lcd: HD44780Emulator::new(20, 4),
+                ops: vec![],
+                fail: false,
+            }
+        }
+
+        fn row(&self, row: usize, columns: usize) -> String {
+            self.lcd.text()[row].chars().take(columns).collect()
+        }
+    }
+
+    impl CharacterLcd for MockLcd {
+        fn command(&mut self, byte: u8) -> Result<(), Error> {
+            if byte & SET_DDRAM_ADDRESS != 0 {
+                self.ops.push(Op::SetCursor(byte & 0x7F));
+            } else if byte & SET_CGRAM_ADDRESS != 0 {
+                self.ops.push(Op::SetCgramAddress(byte & 0x3F));
+            }
+            self.lcd.command(byte)
+        }
+
+        fn data(&mut self, byte: u8) -> Result<(), Error> {
+            if self.fail {
+                return Err(Error::other("bus glitch"));
+            }
+            match self.ops.last_mut() {
+                Some(Op::Write(bytes)) => bytes.push(byte),
+                _ => self.ops.push(Op::Write(vec![byte])),
+            }
+            self.lcd.data(byte)
+        }
+
+        fn wait(&mut self, duration: Duration) {
+            self.ops.push(Op::Wait(duration));
+        }
+    }
+
+    fn frame(rows: &[&str]) -> Frame {
+        Frame::Text(rows.iter().map(|r| r.to_string()).collect())
+    }
+
+    #[test]
+    fn test_init_waits() {
+        let mut lcd = MockLcd::new();
+        lcd.init().unwrap();
+        assert_eq!(
+            lcd.ops,
+            vec![
+                Op::Wait(Duration::from_millis(15)),
+                Op::Wait(Duration::from_micros(4100)),
+                Op::Wait(Duration::from_micros(100)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writes_only_changes() {
+        let mut lcd = MockLcd::new();
+        let mut shadow = ShadowBuffer::new();
+
+        shadow
+            .update(&mut lcd, &frame(&["09:05      Clear", "Mon Jan 15   45°"]))
+            .unwrap();
+        assert_eq!(
+            lcd.ops,
+            vec![
+                Op::SetCursor(0x00),
+                Op::Write(b"09:05      Clear".to_vec()),
+                Op::SetCursor(0x40),
+                Op::Write(b"Mon Jan 15   45\xDF".to_vec()),
+            ]
+        );
+
+        // Nothing changed, so nothing is written
+        lcd.ops.clear();
+        shadow
+            .update(&mut lcd, &frame(&["09:05      Clear", "Mon Jan 15   45°"]))
+            .unwrap();
+        assert!(lcd.ops.is_empty());
+
+        lcd.ops.clear();
+        shadow
+            .update(&mut lcd, &frame(&["09:06      Clear", "Mon Jan 15   46°"]))
+            .unwrap();
+        assert_eq!(
+            lcd.ops,
+            vec![
+                Op::SetCursor(0x04),
+                Op::Write(b"6".to_vec()),
+                Op::SetCursor(0x4E),
+                Op::Write(b"6".to_vec()),
+            ]
+        );
+        assert_eq!(lcd.row(0, 16), "09:06      Clear");
+        assert_eq!(lcd.row(1, 16), "Mon Jan 15   46°");
+    }
+
+    #[test]
+    fn test_tracks_cursor() {
+        let mut lcd = MockLcd::new();
+        let mut shadow = ShadowBuffer::new();
+        let rows = [
+            "a".repeat(20),
+            "b".repeat(20),
+            "c".repeat(20),
+            "d".repeat(20),
+        ];
+        let rows = rows.iter().map(|r| r.as_str()).collect::<Vec<_>>();
+        shadow.update(&mut lcd, &frame(&rows)).unwrap();
+
+        // The end of the first row is followed by the start of the third in DDRAM, so the cursor
+        // doesn't need moving between them
+        let mut changed = rows.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        changed[0].replace_range(19.., "x");
+        changed[2].replace_range(..1, "y");
+        lcd.ops.clear();
+        shadow
+            .update(&mut lcd, &Frame::Text(changed.clone()))
+            .unwrap();
+        assert_eq!(
+            lcd.ops,
+            vec![Op::SetCursor(0x13), Op::Write(b"xy".to_vec()),]
+        );
+        for (i, row) in changed.iter().enumerate() {
+            assert_eq!(&lcd.row(i, 20), row);
+        }
+    }
+
+    #[test]
+    fn test_full_refresh() {
+        let mut lcd = MockLcd::new();
+        let mut shadow = ShadowBuffer::new();
+        let rows = frame(&["09:05      Clear", "Mon Jan 15   45°"]);
+
+        let writes = (0..FULL_REFRESH_EVERY + 1)
+            .map(|_| {
+                lcd.ops.clear();
+                shadow.update(&mut lcd, &rows).unwrap();
+                lcd.ops.len()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(writes[0], 4);
+        assert!(writes[1..FULL_REFRESH_EVERY as usize]
+            .iter()
+            .all(|&n| n == 0));
+        assert_eq!(writes[FULL_REFRESH_EVERY as usize], 4);
+
+        // After a failed write, everything is written again
+        lcd.fail = true;
+        let changed = frame(&["09:06      Clear", "Mon Jan 15   45°"]);
+        assert!(shadow.update(&mut lcd, &changed).is_err());
+        lcd.fail = false;
+        lcd.ops.clear();
+        shadow.update(&mut lcd, &rows).unwrap();
+        assert_eq!(lcd.ops.len(), 4);
+
+        // And after the display is set up again
+        shadow.invalidate();
+        lcd.ops.clear();
+        shadow.update(&mut lcd, &rows).unwrap();
+        assert_eq!(lcd.ops.len(), 4);
+    }
+
+    #[test]
+    fn test_custom_characters() {
+        let mut lcd = MockLcd::new();
+        let mut shadow = ShadowBuffer::new();
+        let sun = custom_glyph('☀').unwrap();
+        let cloud = custom_glyph('☁').unwrap();
+
+        shadow
+            .update(&mut lcd, &frame(&["09:05    ☀ Clear", "☀☀"]))
+            .unwrap();
+        assert_eq!(
+            lcd.ops[..3],
+            [
+                Op::SetCgramAddress(0),
+                Op::Write(sun.to_vec()),
+                Op::SetCursor(0x00)
+            ]
+        );
+        assert_eq!(lcd.lcd.glyph_at(0, 9), sun);
+        assert_eq!(lcd.row(0, 16), "09:05    ☀ Clear");
+        assert_eq!(lcd.row(1, 2), "☀☀");
+
+        // Slots that are no longer needed are reused, which changes the cells showing them
+        lcd.ops.clear();
+        shadow
+            .update(&mut lcd, &frame(&["09:05    ☁ Clear", "☀☀"]))
+            .unwrap();
+        assert_eq!(
+            lcd.ops,
+            vec![
+                Op::SetCgramAddress(8),
+                Op::Write(cloud.to_vec()),
+                Op::SetCursor(0x09),
+                Op::Write(vec![1]),
+            ]
+        );
+        lcd.ops.clear();
+        shadow
+            .update(&mut lcd, &frame(&["09:05   ☁ Clouds", "  "]))
+            .unwrap();
+        shadow
+            .update(&mut lcd, &frame(&["09:05  ☂ Drizzle", "  "]))
+            .unwrap();
+        assert_eq!(lcd.lcd.glyph_at(0, 7), custom_glyph('☂').unwrap());
+        assert_eq!(lcd.row(0, 16), "09:05  ☂ Drizzle");
+        assert!(lcd.ops.contains(&Op::SetCgramAddress(0)));
+
+        // An icon and every bar of a sparkline fit at once
+        let mut shadow = ShadowBuffer::new();
+        shadow
+            .update(&mut lcd, &frame(&["09:05    ☾ Clear", " ▁▂▃▄▅▆▇▇▆"]))
+            .unwrap();
+        assert_eq!(lcd.row(0, 16), "09:05    ☾ Clear");
+        assert_eq!(lcd.row(1, 10), " ▁▂▃▄▅▆▇▇▆");
+    }
+}
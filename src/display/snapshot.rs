@@ -0,0 +1,494 @@
+// Renders what the hardware displays would show into PNG images, so that layouts can be
+// checked (and screenshotted) without a Raspberry Pi.
+
+use super::font::{custom_glyph, hd44780_glyph, ALPHANUM_DOT};
+use super::{str_to_lcd_bytes, Capabilities, Display, Error, Frame, RenderContext, View};
+
+use std::fs;
+use std::path::PathBuf;
+
+type Rgb = [u8; 3];
+
+// The LCDs are RGB negative modules with the red backlight enabled.
+const LCD_BACKGROUND: Rgb = [12, 10, 10];
+const LCD_PIXEL: Rgb = [255, 40, 30];
+const SEGMENT_BACKGROUND: Rgb = [20, 20, 20];
+const SEGMENT_LIT: Rgb = [255, 30, 20];
+
+// Fraction of the lit colour shown by pixels and segments that are off.
+const UNLIT_FACTOR: f32 = 0.08;
+
+// LCD geometry, in image pixels.
+const LCD_DOT_SIZE: u32 = 3;
+const LCD_DOT_PITCH: u32 = 4;
+const LCD_CHAR_PITCH: u32 = 6 * LCD_DOT_PITCH;
+const LCD_ROW_PITCH: u32 = 9 * LCD_DOT_PITCH;
+const LCD_MARGIN: u32 = 4 * LCD_DOT_PITCH;
+
+// Segment display geometry, in image pixels.
+const DIGIT_WIDTH: u32 = 40;
+const DIGIT_HEIGHT: u32 = 64;
+const DIGIT_PITCH: u32 = 56;
+const SEGMENT_THICKNESS: u32 = 6;
+const SEGMENT_MARGIN: u32 = 16;
+
+pub struct SnapshotDisplay {
+    view: View,
+    path: PathBuf,
+}
+
+impl SnapshotDisplay {
+    pub fn new<V: Into<View>, P: Into<PathBuf>>(view: V, path: P) -> SnapshotDisplay {
+        SnapshotDisplay {
+            view: view.into(),
+            path: path.into(),
+        }
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    // Returns the PNG-encoded image of what the display would show.
+    pub fn render(&self, ctx: &RenderContext) -> Result<Vec<u8>, Error> {
+        render_png(&self.view.frame(ctx), ctx.light)
+    }
+}
+
+pub(super) fn render_png(frame: &Frame, light: f32) -> Result<Vec<u8>, Error> {
+    draw_frame(frame, light).encode_png()
+}
+
+impl Display for SnapshotDisplay {
+    fn name(&self) -> &'static str {
+        "snapshot"
+    }
+
+    // Snapshots are told apart by where they're written
+    fn label(&self) -> String {
+        format!("{}@{}", self.name(), self.path.display())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: vec![self.view.clone()],
+            dimmable: true,
+            hardware: false,
+        }
+    }
+
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        let png = self.render(ctx)?;
+        fs::write(&self.path, png)?;
+
+        Ok(())
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Rgb) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: background.repeat((width * height) as usize),
+        }
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, color: Rgb) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+
+        let offset = ((y as u32 * self.width + x as u32) * 3) as usize;
+        self.pixels[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb) {
+        for py in y..y + height {
+            for px in x..x + width {
+                self.set_pixel(px as i32, py as i32, color);
+            }
+        }
+    }
+
+    // Draws a line by stamping a square brush along it.
+    fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), thickness: i32, color: Rgb) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).max(1);
+        let half = thickness / 2;
+
+        for step in 0..=steps {
+            let x = from.0 + (to.0 - from.0) * step / steps;
+            let y = from.1 + (to.1 - from.1) * step / steps;
+
+            for dy in -half..thickness - half {
+                for dx in -half..thickness - half {
+                    self.set_pixel(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut buf, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+
+        Ok(buf)
+    }
+}
+
+fn scale(color: Rgb, factor: f32) -> Rgb {
+    color.map(|c| (c as f32 * factor.clamp(0.0, 1.0)).round() as u8)
+}
+
+// The LCD backlight is driven by PWM with the duty cycle set from the light level.
+fn lcd_colors(light: f32) -> (Rgb, Rgb) {
+    let lit = scale(LCD_PIXEL, light.max(0.01));
+    (lit, scale(lit, UNLIT_FACTOR))
+}
+
+// The HT16K33 supports 16 dimming levels, the lowest of which is still visible.
+fn segment_colors(light: f32) -> (Rgb, Rgb) {
+    let level = (light.clamp(0.0, 1.0) * 15.0).round();
+    let lit = scale(SEGMENT_LIT, (level + 1.0) / 16.0);
+    (lit, scale(SEGMENT_LIT, UNLIT_FACTOR))
+}
+
+fn draw_frame(frame: &Frame, light: f32) -> Canvas {
+    match (frame, frame.segments()) {
+        (Frame::AlphaNum4 { .. }, Some(segments)) => draw_alphanum4(segments, light),
+        (Frame::SevenSegment4 { colon, .. }, Some(segments)) => {
+            draw_seven_segment4(segments, *colon, light)
+        }
+        (Frame::Text(rows), _) => draw_character_lcd(rows, light),
+        (_, None) => unreachable!("segment frames always have segments"),
+    }
+}
+
+fn draw_character_lcd(rows: &[String], light: f32) -> Canvas {
+    let columns = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0) as u32;
+
+    let mut canvas = Canvas::new(
+        2 * LCD_MARGIN + columns * LCD_CHAR_PITCH - LCD_DOT_PITCH,
+        2 * LCD_MARGIN + rows.len() as u32 * LCD_ROW_PITCH - LCD_DOT_PITCH,
+        LCD_BACKGROUND,
+    );
+
+    let (lit, unlit) = lcd_colors(light);
+
+    for (row_index, row) in rows.iter().enumerate() {
+        // Custom characters are drawn as they'd be loaded into CGRAM
+        let mut glyphs = vec![];
+        for c in row.chars() {
+            match custom_glyph(c) {
+                Some(glyph) => glyphs.push(glyph),
+                None => glyphs.extend(
+                    str_to_lcd_bytes(c.encode_utf8(&mut [0; 4]))
+                        .into_iter()
+                        .map(hd44780_glyph),
+                ),
+            }
+        }
+        glyphs.resize(columns as usize, hd44780_glyph(b' '));
+
+        for (column_index, glyph) in glyphs.iter().enumerate() {
+            let cell_x = LCD_MARGIN + column_index as u32 * LCD_CHAR_PITCH;
+            let cell_y = LCD_MARGIN + row_index as u32 * LCD_ROW_PITCH;
+
+            for (dot_y, glyph_row) in glyph.iter().enumerate() {
+                for dot_x in 0..5 {
+                    let on = glyph_row & (0b10000 >> dot_x) != 0;
+
+                    canvas.fill_rect(
+                        cell_x + dot_x * LCD_DOT_PITCH,
+                        cell_y + dot_y as u32 * LCD_DOT_PITCH,
+                        LCD_DOT_SIZE,
+                        LCD_DOT_SIZE,
+                        if on { lit } else { unlit },
+                    );
+                }
+            }
+        }
+    }
+
+    canvas
+}
+
+fn segment_display_canvas() -> Canvas {
+    Canvas::new(
+        2 * SEGMENT_MARGIN + 3 * DIGIT_PITCH + DIGIT_WIDTH + SEGMENT_THICKNESS,
+        2 * SEGMENT_MARGIN + DIGIT_HEIGHT,
+        SEGMENT_BACKGROUND,
+    )
+}
+
+fn digit_origin(index: u32) -> (u32, u32) {
+    (SEGMENT_MARGIN + index * DIGIT_PITCH, SEGMENT_MARGIN)
+}
+
+// Draws the segments shared by 7- and 14-segment digits: A-F, the middle bar and the decimal
+// point, using the 7-segment bit order (A-G, DP).
+fn draw_outer_segments(canvas: &mut Canvas, index: u32, bits: u8, lit: Rgb, unlit: Rgb) {
+    let (x, y) = digit_origin(index);
+    let t = SEGMENT_THICKNESS;
+    let (w, h) = (DIGIT_WIDTH, DIGIT_HEIGHT);
+    let half = (h - t) / 2;
+
+    let color = |bit: u8| if bits & (1 << bit) != 0 { lit } else { unlit };
+
+    canvas.fill_rect(x + t, y, w - 2 * t, t, color(0)); // A
+    canvas.fill_rect(x + w - t, y + t, t, half - t, color(1)); // B
+    canvas.fill_rect(x + w - t, y + half + t, t, half - t, color(2)); // C
+    canvas.fill_rect(x + t, y + h - t, w - 2 * t, t, color(3)); // D
+    canvas.fill_rect(x, y + half + t, t, half - t, color(4)); // E
+    canvas.fill_rect(x, y + t, t, half - t, color(5)); // F
+    canvas.fill_rect(x + t, y + half, w - 2 * t, t, color(6)); // G
+    canvas.fill_rect(x + w + 2, y + h - t, t, t, color(7)); // DP
+}
+
+fn draw_alphanum4(digits: [u16; 4], light: f32) -> Canvas {
+    let mut canvas = segment_display_canvas();
+    let (lit, unlit) = segment_colors(light);
+
+    for (index, &segments) in digits.iter().enumerate() {
+        let index = index as u32;
+
+        let (x, y) = digit_origin(index);
+        let t = SEGMENT_THICKNESS;
+        let (w, h) = (DIGIT_WIDTH, DIGIT_HEIGHT);
+        let half = (h - t) / 2;
+        let color = |bit: u16| {
+            if segments & (1 << bit) != 0 {
+                lit
+            } else {
+                unlit
+            }
+        };
+
+        // The outer segments match the 7-segment layout, apart from the split middle bar.
+        let outer = (segments & 0b11_1111) as u8 | (((segments & ALPHANUM_DOT) >> 7) as u8);
+        draw_outer_segments(&mut canvas, index, outer, lit, unlit);
+
+        canvas.fill_rect(x + t, y + half, w / 2 - t - 1, t, color(6)); // G1
+        canvas.fill_rect(x + w / 2 + 1, y + half, w / 2 - t - 1, t, color(7)); // G2
+        canvas.fill_rect(x + (w - t) / 2, y + t + 2, t, half - t - 2, color(9)); // J
+        canvas.fill_rect(x + (w - t) / 2, y + half + t, t, half - t - 2, color(12)); // M
+
+        let (left, right) = (x as i32 + t as i32 + 3, (x + w) as i32 - t as i32 - 3);
+        let (top, bottom) = (y as i32 + t as i32 + 3, (y + h) as i32 - t as i32 - 3);
+        let (centre_x, upper_middle, lower_middle) = (
+            (x + w / 2) as i32,
+            (y + half) as i32 - 3,
+            (y + half + t) as i32 + 3,
+        );
+        let thickness = t as i32 / 2 + 1;
+
+        // Diagonals H, K, L and N
+        let upper_left = (centre_x - 3, upper_middle);
+        let upper_right = (centre_x + 3, upper_middle);
+        let lower_left = (centre_x - 3, lower_middle);
+        let lower_right = (centre_x + 3, lower_middle);
+        canvas.draw_line((left, top), upper_left, thickness, color(8));
+        canvas.draw_line((right, top), upper_right, thickness, color(10));
+        canvas.draw_line((left, bottom), lower_left, thickness, color(11));
+        canvas.draw_line((right, bottom), lower_right, thickness, color(13));
+    }
+
+    canvas
+}
+
+fn draw_seven_segment4(digits: [u16; 4], colon: bool, light: f32) -> Canvas {
+    let mut canvas = segment_display_canvas();
+    let (lit, unlit) = segment_colors(light);
+
+    for (index, &segments) in digits.iter().enumerate() {
+        draw_outer_segments(&mut canvas, index as u32, segments as u8, lit, unlit);
+    }
+
+    // The colon sits between the second and third digits.
+    let colon_color = if colon { lit } else { unlit };
+    let colon_x = SEGMENT_MARGIN + DIGIT_PITCH + DIGIT_WIDTH + (DIGIT_PITCH - DIGIT_WIDTH) / 2
+        - SEGMENT_THICKNESS / 2;
+    for colon_y in [DIGIT_HEIGHT / 3, 2 * DIGIT_HEIGHT / 3] {
+        canvas.fill_rect(
+            colon_x,
+            SEGMENT_MARGIN + colon_y - SEGMENT_THICKNESS / 2,
+            SEGMENT_THICKNESS,
+            SEGMENT_THICKNESS,
+            colon_color,
+        );
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{Layout, TemperatureUnit};
+    use crate::weather::open_weather_types::{Hourly, Weather};
+    use crate::weather::{FetchStatus, Main, OpenWeather};
+    use chrono::{Local, TimeZone};
+
+    fn decode(png_bytes: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let decoder = png::Decoder::new(png_bytes);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        (info, buf)
+    }
+
+    // FNV-1a, which is enough to tell images apart, and unlike std's hashers is the same in every
+    // Rust release.
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    // The image drawn for every layout, bright and dimmed, as its size and a hash of its pixels.
+    // Pixels are compared rather than PNG files, which can be compressed differently by other
+    // versions of the png crate. If a change to drawing is meant, update the hashes from the
+    // failure messages after checking the images by eye with the render subcommand.
+    #[test]
+    fn test_golden_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+        let time = Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap();
+        let clear = vec![Weather {
+            main: Main::Clear,
+            description: Main::Clear.to_string(),
+            ..Default::default()
+        }];
+        let mut weather: OpenWeather = Default::default();
+        weather.current.dt = time.timestamp();
+        weather.current.temp = 68.4;
+        weather.current.weather = clear.clone();
+        weather.hourly = [70.0, 74.6, 65.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &temp)| Hourly {
+                dt: time.timestamp() + 3600 * (i as i64 + 1),
+                temp,
+                weather: clear.clone(),
+                ..Default::default()
+            })
+            .collect();
+        let weather = Some(weather);
+        let fetch_status = FetchStatus::default();
+
+        let expected = [
+            (Layout::Text16x2, 1.0, (412, 100, 0xe7442e7c7095d317)),
+            (Layout::Text16x2, 0.25, (412, 100, 0xe4d4f3e21b24f972)),
+            (Layout::Text20x4, 1.0, (508, 172, 0x02e6586e2b815aed)),
+            (Layout::Text20x4, 0.25, (508, 172, 0x73715bc446dccdc1)),
+            (Layout::AlphaNum4, 1.0, (246, 96, 0xb3daf7bbd0163feb)),
+            (Layout::AlphaNum4, 0.25, (246, 96, 0xc660e7f1ff26396b)),
+            (Layout::SevenSegment4, 1.0, (246, 96, 0xb99cc4f58bebb243)),
+            (Layout::SevenSegment4, 0.25, (246, 96, 0xc930ed4666625a83)),
+        ];
+        for (layout, light, (width, height, hash)) in expected {
+            let ctx = RenderContext {
+                time,
+                current_state_index: 0,
+                scroll_step: 0,
+                weather: &weather,
+                light,
+                fetch_status: &fetch_status,
+                unit: TemperatureUnit::Fahrenheit,
+                message: None,
+            };
+
+            let display = SnapshotDisplay::new(layout, "unused.png");
+            let (info, pixels) = decode(&display.render(&ctx)?);
+            assert_eq!(
+                (info.width, info.height, fnv1a(&pixels)),
+                (width, height, hash),
+                "{:?} at light level {}",
+                layout,
+                light
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_lcd_dimensions() -> Result<(), Box<dyn std::error::Error>> {
+        let ctx = RenderContext {
+            time: Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap(),
+            current_state_index: 0,
+            scroll_step: 0,
+            weather: &None,
+            light: 1.0,
+            fetch_status: &Default::default(),
+            unit: Default::default(),
+            message: None,
+        };
+
+        let display = SnapshotDisplay::new(Layout::Text16x2, "unused.png");
+        let (info, _) = decode(&display.render(&ctx)?);
+        assert_eq!(
+            info.width,
+            2 * LCD_MARGIN + 16 * LCD_CHAR_PITCH - LCD_DOT_PITCH
+        );
+        assert_eq!(
+            info.height,
+            2 * LCD_MARGIN + 2 * LCD_ROW_PITCH - LCD_DOT_PITCH
+        );
+
+        let display = SnapshotDisplay::new(Layout::Text20x4, "unused.png");
+        let (info, _) = decode(&display.render(&ctx)?);
+        assert_eq!(
+            info.width,
+            2 * LCD_MARGIN + 20 * LCD_CHAR_PITCH - LCD_DOT_PITCH
+        );
+        assert_eq!(
+            info.height,
+            2 * LCD_MARGIN + 4 * LCD_ROW_PITCH - LCD_DOT_PITCH
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw_character_lcd_pixels() {
+        let canvas = draw_character_lcd(&["A".to_string()], 1.0);
+        let (lit, unlit) = lcd_colors(1.0);
+
+        let pixel = |dot_x: u32, dot_y: u32| {
+            let x = LCD_MARGIN + dot_x * LCD_DOT_PITCH;
+            let y = LCD_MARGIN + dot_y * LCD_DOT_PITCH;
+            let offset = ((y * canvas.width + x) * 3) as usize;
+            [
+                canvas.pixels[offset],
+                canvas.pixels[offset + 1],
+                canvas.pixels[offset + 2],
+            ]
+        };
+
+        // Top row of 'A' is .###.
+        assert_eq!(pixel(0, 0), unlit);
+        assert_eq!(pixel(1, 0), lit);
+        assert_eq!(pixel(4, 0), unlit);
+        // The cursor row is blank
+        assert_eq!(pixel(0, 7), unlit);
+    }
+
+    #[test]
+    fn test_segment_colors_follow_dimming_levels() {
+        assert_eq!(segment_colors(1.0).0, SEGMENT_LIT);
+        assert_eq!(segment_colors(0.0).0, scale(SEGMENT_LIT, 1.0 / 16.0));
+        assert_eq!(segment_colors(0.5).0, scale(SEGMENT_LIT, 9.0 / 16.0));
+    }
+}
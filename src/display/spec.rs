@@ -0,0 +1,528 @@
+// Displays as they're chosen on the command line, e.g. "lcd-20x4" or "alphanum4@0x71,screens=high",
+// and building them.
+
+use super::error::{self, Error};
+use super::wiring::{I2cDevice, LcdWiring, Wiring};
+#[cfg(feature = "rpi-hw")]
+use super::{AlphaNum4Display, LCD16x2Display, LCD20x4Display, SevenSegment4Display};
+use super::{
+    Console16x2Display, Console20x4Display, DisplayType, Layout, Screen, SnapshotDisplay,
+    TerminalDisplay, View, WebDisplay,
+};
+
+use crate::parameters;
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const LAYOUTS: [Layout; 4] = [
+    Layout::Text16x2,
+    Layout::Text20x4,
+    Layout::AlphaNum4,
+    Layout::SevenSegment4,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayKind {
+    // Prints text layouts to standard output, or with --tui, draws them in place.
+    Console(Layout),
+
+    // Renders what the hardware display would show to a PNG file.
+    Snapshot(Layout),
+
+    // Shows what the hardware display would show on a web page.
+    Web(Layout),
+
+    // The display itself, attached to a Raspberry Pi.
+    Hardware(Layout),
+}
+
+impl DisplayKind {
+    // Every kind of display, hardware included, whether or not it can be built.
+    pub fn all() -> Vec<DisplayKind> {
+        let console = [Layout::Text16x2, Layout::Text20x4]
+            .iter()
+            .map(|&layout| DisplayKind::Console(layout));
+        let others = LAYOUTS.iter().flat_map(|&layout| {
+            vec![
+                DisplayKind::Snapshot(layout),
+                DisplayKind::Web(layout),
+                DisplayKind::Hardware(layout),
+            ]
+        });
+
+        let mut kinds = console.chain(others).collect::<Vec<_>>();
+        // Grouped by kind, e.g. all the snapshot displays together
+        kinds.sort_by_key(|kind| match kind {
+            DisplayKind::Console(_) => 0,
+            DisplayKind::Snapshot(_) => 1,
+            DisplayKind::Web(_) => 2,
+            DisplayKind::Hardware(_) => 3,
+        });
+        kinds
+    }
+
+    pub fn layout(&self) -> Layout {
+        match *self {
+            DisplayKind::Console(layout)
+            | DisplayKind::Snapshot(layout)
+            | DisplayKind::Web(layout)
+            | DisplayKind::Hardware(layout) => layout,
+        }
+    }
+
+    // Whether this kind of display can be built with the features this was compiled with.
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, DisplayKind::Hardware(_)) || cfg!(feature = "rpi-hw")
+    }
+}
+
+impl fmt::Display for DisplayKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hardware_name = |layout: Layout| match layout {
+            Layout::Text16x2 => "lcd-16x2",
+            Layout::Text20x4 => "lcd-20x4",
+            Layout::AlphaNum4 => "alphanum4",
+            Layout::SevenSegment4 => "seven_segment4",
+        };
+
+        match *self {
+            DisplayKind::Console(layout) => write!(f, "console-{}", layout.name()),
+            DisplayKind::Snapshot(layout) => write!(f, "snapshot-{}", hardware_name(layout)),
+            DisplayKind::Web(layout) => write!(f, "web-{}", hardware_name(layout)),
+            DisplayKind::Hardware(layout) => write!(f, "{}", hardware_name(layout)),
+        }
+    }
+}
+
+// A display and its parameters, parsed from e.g. "alphanum4@0x71" or "lcd-16x2@rs=4,pwm=1".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisplaySpec {
+    pub kind: DisplayKind,
+
+    // How hardware displays are connected: LCDs by their pins and segment displays over I2C.
+    pub wiring: Wiring,
+
+    // The screens that the display rotates through, e.g. "screens=temperature+high".
+    pub playlist: Vec<Screen>,
+}
+
+impl DisplaySpec {
+    // The display, wired the way the clock's own displays are.
+    pub fn new(kind: DisplayKind) -> Self {
+        let wiring = match kind {
+            DisplayKind::Hardware(Layout::Text16x2) => Wiring::Lcd(LcdWiring::default_16x2()),
+            DisplayKind::Hardware(Layout::Text20x4) => Wiring::Lcd(LcdWiring::default_20x4()),
+            DisplayKind::Hardware(Layout::AlphaNum4) => Wiring::I2c(I2cDevice::default_alphanum4()),
+            DisplayKind::Hardware(Layout::SevenSegment4) => {
+                Wiring::I2c(I2cDevice::default_seven_segment4())
+            }
+            _ => Wiring::None,
+        };
+
+        DisplaySpec {
+            kind,
+            wiring,
+            playlist: kind.layout().default_playlist(),
+        }
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            layout: self.kind.layout(),
+            playlist: self.playlist.clone(),
+        }
+    }
+
+    fn set_playlist(&mut self, screens: Option<&str>) -> Result<(), String> {
+        let layout = self.kind.layout();
+        let screens = screens.ok_or_else(|| "expected a value for screens".to_string())?;
+
+        self.playlist = screens
+            .split('+')
+            .map(|name| {
+                let screen = name.parse::<Screen>()?;
+                if layout.screens().contains(&screen) {
+                    Ok(screen)
+                } else {
+                    let names = layout
+                        .screens()
+                        .iter()
+                        .map(|s| s.name())
+                        .collect::<Vec<_>>();
+                    Err(match names.as_slice() {
+                        [] => format!(
+                            "the {} layout doesn't rotate through screens",
+                            layout.name()
+                        ),
+                        names => format!(
+                            "the {} layout can't show {}, expected one of {}",
+                            layout.name(),
+                            name,
+                            names.join(", ")
+                        ),
+                    })
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(())
+    }
+}
+
+impl FromStr for DisplaySpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameters) = parameters::split(s);
+
+        let kind = DisplayKind::all()
+            .into_iter()
+            .find(|kind| kind.to_string() == name)
+            .ok_or_else(|| {
+                let names = DisplayKind::all()
+                    .iter()
+                    .filter(|kind| kind.is_supported())
+                    .map(|kind| kind.to_string())
+                    .collect::<Vec<_>>();
+                error::unknown_type(name, &names)
+            })?;
+
+        let mut spec = DisplaySpec::new(kind);
+        for (key, value) in parameters {
+            match key {
+                "screens" => spec.set_playlist(value),
+                _ => spec.wiring.set(key, value),
+            }
+            .map_err(|reason| error::invalid_parameter(s, reason))?;
+        }
+        spec.wiring
+            .validate()
+            .map_err(|reason| error::invalid_parameter(s, reason))?;
+
+        Ok(spec)
+    }
+}
+
+// The spec as it's given on the command line, with parameters that aren't the default.
+impl fmt::Display for DisplaySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let default = DisplaySpec::new(self.kind);
+        let mut parameters = self.wiring.parameters(&default.wiring);
+        if self.playlist != default.playlist {
+            let screens = self.playlist.iter().map(|s| s.name()).collect::<Vec<_>>();
+            parameters.push(format!("screens={}", screens.join("+")));
+        }
+
+        write!(f, "{}", self.kind)?;
+        if !parameters.is_empty() {
+            write!(f, "@{}", parameters.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// What's needed to build displays besides their specs.
+#[derive(Clone, Debug)]
+pub struct DisplayOptions {
+    // Where snapshot displays write their PNG files, named after the display.
+    pub snapshot_dir: PathBuf,
+
+    // Where web displays are served.
+    pub web_bind: String,
+
+    // Whether console displays are drawn side by side and redrawn in place.
+    pub tui: bool,
+}
+
+impl DisplayOptions {
+    // Whether the console display is drawn in place, and takes keyboard controls.
+    pub fn is_tui(&self, spec: &DisplaySpec) -> bool {
+        self.tui && matches!(spec.kind, DisplayKind::Console(_))
+    }
+}
+
+impl DisplayType {
+    pub fn from_spec(spec: &DisplaySpec, options: &DisplayOptions) -> Result<Self, Error> {
+        Self::from_spec_instance(spec, options, 1)
+    }
+
+    // Builds the nth display of its kind, which for snapshot displays after the first is written
+    // to e.g. "snapshot-alphanum4-2.png".
+    fn from_spec_instance(
+        spec: &DisplaySpec,
+        options: &DisplayOptions,
+        instance: usize,
+    ) -> Result<Self, Error> {
+        let display = match spec.kind {
+            DisplayKind::Console(_) if options.tui => {
+                DisplayType::Terminal(TerminalDisplay::new(vec![spec.view()]))
+            }
+            DisplayKind::Console(Layout::Text20x4) => {
+                DisplayType::Console20x4(Console20x4Display::with_playlist(spec.playlist.clone()))
+            }
+            DisplayKind::Console(_) => DisplayType::Console16x2(Console16x2Display::new()),
+
+            DisplayKind::Snapshot(_) => {
+                let file_name = match instance {
+                    1 => format!("{}.png", spec.kind),
+                    n => format!("{}-{}.png", spec.kind, n),
+                };
+                DisplayType::Snapshot(SnapshotDisplay::new(
+                    spec.view(),
+                    options.snapshot_dir.join(file_name),
+                ))
+            }
+
+            DisplayKind::Web(_) => {
+                DisplayType::Web(WebDisplay::new(&options.web_bind, vec![spec.view()])?)
+            }
+
+            #[cfg(feature = "rpi-hw")]
+            DisplayKind::Hardware(layout) => match (layout, &spec.wiring) {
+                (Layout::Text16x2, Wiring::Lcd(lcd)) => {
+                    DisplayType::LCD16x2(LCD16x2Display::new(lcd)?)
+                }
+                (Layout::Text20x4, Wiring::Lcd(lcd)) => {
+                    DisplayType::LCD20x4(LCD20x4Display::new(lcd, spec.playlist.clone())?)
+                }
+                (Layout::AlphaNum4, Wiring::I2c(device)) => {
+                    DisplayType::AlphaNum4(AlphaNum4Display::new(device, spec.playlist.clone())?)
+                }
+                (Layout::SevenSegment4, Wiring::I2c(device)) => {
+                    DisplayType::SevenSegment4(SevenSegment4Display::new(device)?)
+                }
+                _ => {
+                    return Err(error::invalid_parameter(
+                        &spec.kind.to_string(),
+                        "wired like a different kind of display".to_string(),
+                    ))
+                }
+            },
+            #[cfg(not(feature = "rpi-hw"))]
+            DisplayKind::Hardware(_) => return Err(error::unsupported(&spec.kind.to_string())),
+        };
+
+        Ok(display)
+    }
+
+    // Builds the displays for the specs, in order, except that console displays drawn in place
+    // share one terminal, and web displays share one page, added after the others. There can be
+    // any number of displays of each kind, as long as hardware displays aren't connected to the
+    // same pins or I2C address.
+    pub fn from_specs(specs: &[DisplaySpec], options: &DisplayOptions) -> Result<Vec<Self>, Error> {
+        for (i, spec) in specs.iter().enumerate() {
+            for earlier in &specs[..i] {
+                spec.wiring
+                    .check_conflict(&earlier.wiring)
+                    .map_err(|reason| {
+                        error::invalid_parameter(
+                            &spec.to_string(),
+                            format!("{} by {}", reason, earlier),
+                        )
+                    })?;
+            }
+        }
+
+        let (tui_specs, specs): (Vec<_>, Vec<_>) =
+            specs.iter().partition(|spec| options.is_tui(spec));
+        let (web_specs, specs): (Vec<_>, Vec<_>) = specs
+            .into_iter()
+            .partition(|spec| matches!(spec.kind, DisplayKind::Web(_)));
+
+        let mut displays = specs
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let instance = 1 + specs[..i].iter().filter(|s| s.kind == spec.kind).count();
+                Self::from_spec_instance(spec, options, instance)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !tui_specs.is_empty() {
+            let views = tui_specs.iter().map(|spec| spec.view()).collect();
+            displays.push(DisplayType::Terminal(TerminalDisplay::new(views)));
+        }
+
+        if !web_specs.is_empty() {
+            let views = web_specs.iter().map(|spec| spec.view()).collect();
+            displays.push(DisplayType::Web(WebDisplay::new(&options.web_bind, views)?));
+        }
+
+        Ok(displays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::error::ErrorKind;
+    use crate::display::Display;
+
+    #[test]
+    fn test_parse_display_spec() {
+        for kind in DisplayKind::all() {
+            let spec: DisplaySpec = kind.to_string().parse().unwrap();
+            assert_eq!(spec, DisplaySpec::new(kind));
+            assert_eq!(spec.to_string(), kind.to_string());
+        }
+
+        // Specs are written the way they're given, less the parameters that don't change anything
+        for s in [
+            "lcd-20x4@rs=4,red=none,screens=high",
+            "alphanum4@bus=3,address=0x72,screens=high+low",
+        ] {
+            assert_eq!(s.parse::<DisplaySpec>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "seven_segment4@0x70"
+                .parse::<DisplaySpec>()
+                .unwrap()
+                .to_string(),
+            "seven_segment4"
+        );
+
+        let spec: DisplaySpec = "alphanum4@0x72".parse().unwrap();
+        assert_eq!(spec.kind, DisplayKind::Hardware(Layout::AlphaNum4));
+        assert_eq!(
+            spec.wiring,
+            Wiring::I2c(I2cDevice {
+                bus: 1,
+                address: 0x72
+            })
+        );
+
+        let spec: DisplaySpec = "lcd-16x2@d4=4,blue=none,pwm=1".parse().unwrap();
+        assert_eq!(
+            spec.wiring,
+            Wiring::Lcd(LcdWiring {
+                d4: 4,
+                blue: None,
+                pwm_channel: 1,
+                ..LcdWiring::default_16x2()
+            })
+        );
+
+        // The default 16x2 wiring has its blue backlight on the pin of PWM channel 1
+        assert!(matches!(
+            "lcd-16x2@pwm=1".parse::<DisplaySpec>().unwrap_err().kind(),
+            ErrorKind::InvalidParameter { .. }
+        ));
+
+        assert!(matches!(
+            "lcd-40x2".parse::<DisplaySpec>().unwrap_err().kind(),
+            ErrorKind::UnknownType { .. }
+        ));
+        assert!(matches!(
+            "alphanum4@0x1ff".parse::<DisplaySpec>().unwrap_err().kind(),
+            ErrorKind::InvalidParameter { .. }
+        ));
+        assert!(matches!(
+            "console-16x2@0x70"
+                .parse::<DisplaySpec>()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidParameter { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let spec: DisplaySpec = "alphanum4@0x72,screens=high+low".parse().unwrap();
+        assert_eq!(spec.playlist, vec![Screen::High, Screen::Low]);
+        assert_eq!(
+            spec.wiring,
+            Wiring::I2c(I2cDevice {
+                bus: 1,
+                address: 0x72
+            })
+        );
+
+        let spec: DisplaySpec = "console-20x4@screens=low".parse().unwrap();
+        assert_eq!(spec.view().playlist, vec![Screen::Low]);
+
+        for s in [
+            "alphanum4@screens=precipitation",
+            "web-seven_segment4@screens=time",
+            "console-20x4@screens=humidity",
+            "console-20x4@screens",
+        ] {
+            assert!(
+                matches!(
+                    s.parse::<DisplaySpec>().unwrap_err().kind(),
+                    ErrorKind::InvalidParameter { .. }
+                ),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_specs() {
+        let options = DisplayOptions {
+            snapshot_dir: PathBuf::from("/tmp"),
+            web_bind: "127.0.0.1:0".to_string(),
+            tui: false,
+        };
+        let specs = [
+            "snapshot-alphanum4",
+            "console-20x4",
+            "web-lcd-16x2",
+            "web-lcd-20x4",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect::<Vec<_>>();
+
+        let displays = DisplayType::from_specs(&specs, &options).unwrap();
+        let names = displays.iter().map(|d| d.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["snapshot", "console-20x4", "web"]);
+
+        // Two of the same display, each with its own playlist
+        let specs = ["snapshot-alphanum4", "snapshot-alphanum4@screens=high"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<_>>();
+        let displays = DisplayType::from_specs(&specs, &options).unwrap();
+        let playlists = displays
+            .iter()
+            .map(|d| match d {
+                DisplayType::Snapshot(display) => display.view().playlist.clone(),
+                _ => panic!("expected a snapshot display"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            playlists,
+            vec![
+                vec![Screen::Temperature, Screen::Condition],
+                vec![Screen::High]
+            ]
+        );
+
+        // Hardware displays can't share an I2C address, pins or a PWM channel
+        for specs in [
+            ["alphanum4@0x70", "seven_segment4"],
+            ["lcd-16x2", "lcd-20x4@d4=4,pwm=1"],
+            [
+                "lcd-16x2@blue=none",
+                "lcd-20x4@rs=4,en=27,d4=22,d5=23,d6=24,d7=25,red=12",
+            ],
+        ] {
+            let specs = specs.iter().map(|s| s.parse().unwrap()).collect::<Vec<_>>();
+            let err = DisplayType::from_specs(&specs, &options).err().unwrap();
+            assert!(
+                matches!(err.kind(), ErrorKind::InvalidParameter { .. }),
+                "{}",
+                err
+            );
+        }
+        let specs = ["alphanum4@0x70", "seven_segment4@bus=3"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<_>>();
+        let err = DisplayType::from_specs(&specs, &options).err().unwrap();
+        assert!(!matches!(err.kind(), ErrorKind::InvalidParameter { .. }));
+    }
+}
@@ -0,0 +1,222 @@
+// Draws one or more emulated displays side by side in the terminal, redrawing them in place
+// rather than printing a new box every loop iteration.
+
+use super::{Capabilities, Display, Error, Frame, RenderContext, View};
+use crate::keyboard::KEYBOARD_HELP;
+use crate::weather::FetchStatus;
+
+use chrono::{DateTime, Local};
+use crossterm::cursor::MoveTo;
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+
+// Columns between neighbouring displays.
+const PANEL_GAP: usize = 2;
+
+// Grey levels of text at no light and full light. Text is never fully black, so that the
+// displays stay readable in a dark terminal.
+const MIN_GREY: f32 = 60.0;
+const MAX_GREY: f32 = 255.0;
+
+pub struct TerminalDisplay {
+    views: Vec<View>,
+}
+
+impl TerminalDisplay {
+    pub fn new(views: Vec<View>) -> Self {
+        TerminalDisplay { views }
+    }
+}
+
+impl Display for TerminalDisplay {
+    fn name(&self) -> &'static str {
+        "terminal"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: self.views.clone(),
+            dimmable: true,
+            hardware: false,
+        }
+    }
+
+    fn init(&mut self) -> Result<(), Error> {
+        execute!(io::stdout(), Clear(ClearType::All))?;
+
+        Ok(())
+    }
+
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        let panels = self
+            .views
+            .iter()
+            .map(|v| panel(&v.frame(ctx)))
+            .collect::<Vec<_>>();
+
+        let mut stdout = io::stdout().lock();
+
+        queue!(
+            stdout,
+            MoveTo(0, 0),
+            SetForegroundColor(text_color(ctx.light))
+        )?;
+        for line in side_by_side(&panels) {
+            queue!(
+                stdout,
+                Print(line),
+                Clear(ClearType::UntilNewLine),
+                Print("\r\n")
+            )?;
+        }
+        queue!(
+            stdout,
+            ResetColor,
+            Print(status_line(ctx.fetch_status, ctx.light)),
+            Clear(ClearType::UntilNewLine),
+            Print("\r\n"),
+            Print(KEYBOARD_HELP),
+            Clear(ClearType::UntilNewLine),
+            Print("\r\n"),
+            Clear(ClearType::FromCursorDown),
+        )?;
+
+        stdout.flush()?;
+
+        Ok(())
+    }
+}
+
+// Text colour dimmed in proportion to the light, in the same way as the backlights.
+fn text_color(light: f32) -> Color {
+    let grey = (MIN_GREY + (MAX_GREY - MIN_GREY) * light.clamp(0.0, 1.0)).round() as u8;
+
+    Color::Rgb {
+        r: grey,
+        g: grey,
+        b: grey,
+    }
+}
+
+// A frame drawn in a box, one string per line.
+fn panel(frame: &Frame) -> Vec<String> {
+    let rows = frame.rows();
+
+    let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+    let border = format!("-{}-", "-".repeat(width));
+
+    let mut lines = vec![border.clone()];
+    lines.extend(rows.iter().map(|r| format!("|{:<width$}|", r)));
+    lines.push(border);
+    lines
+}
+
+// Joins panels left to right, padding shorter and narrower panels with spaces.
+fn side_by_side(panels: &[Vec<String>]) -> Vec<String> {
+    let height = panels.iter().map(Vec::len).max().unwrap_or(0);
+    let gap = " ".repeat(PANEL_GAP);
+
+    (0..height)
+        .map(|i| {
+            panels
+                .iter()
+                .map(|p| {
+                    let width = p.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+                    format!("{:<width$}", p.get(i).map(String::as_str).unwrap_or(""))
+                })
+                .collect::<Vec<_>>()
+                .join(&gap)
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+fn status_line(status: &FetchStatus, light: f32) -> String {
+    let weather = match (&status.last_error, status.last_success) {
+        (None, Some(success)) => format!("weather ok at {}", status_time(&success)),
+        (Some(err), Some(success)) => format!(
+            "weather failed ({}), last ok at {}",
+            err,
+            status_time(&success)
+        ),
+        (Some(err), None) => format!("weather failed ({})", err),
+        (None, None) => "weather not fetched yet".to_string(),
+    };
+
+    format!("{} | light {:.2}", weather, light)
+}
+
+fn status_time(time: &DateTime<Local>) -> String {
+    time.format("%H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_panels_side_by_side() {
+        let text = panel(&Frame::Text(vec!["12:34".to_string(), "-5°F".to_string()]));
+        let segments = panel(&Frame::SevenSegment4 {
+            digits: [1, 2, 3, 4],
+            colon: true,
+        });
+
+        assert_eq!(
+            side_by_side(&[text, segments]),
+            vec![
+                "-------  -------",
+                "|12:34|  |12:34|",
+                "|-5°F |  -------",
+                "-------",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_color() {
+        assert_eq!(
+            text_color(0.0),
+            Color::Rgb {
+                r: 60,
+                g: 60,
+                b: 60
+            }
+        );
+        assert_eq!(
+            text_color(1.0),
+            Color::Rgb {
+                r: 255,
+                g: 255,
+                b: 255
+            }
+        );
+        assert_eq!(text_color(2.0), text_color(1.0));
+    }
+
+    #[test]
+    fn test_status_line() {
+        let time = Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap();
+        let mut status = FetchStatus::default();
+        assert_eq!(
+            status_line(&status, 0.5),
+            "weather not fetched yet | light 0.50"
+        );
+
+        status.last_success = Some(time);
+        assert_eq!(
+            status_line(&status, 0.5),
+            "weather ok at 09:05:00 | light 0.50"
+        );
+
+        status.last_error = Some("timed out".to_string());
+        assert_eq!(
+            status_line(&status, 1.0),
+            "weather failed (timed out), last ok at 09:05:00 | light 1.00"
+        );
+    }
+}
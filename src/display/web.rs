@@ -0,0 +1,246 @@
+// Serves a live web page showing what the hardware displays would show, so that the clock can
+// be watched from another room or an old tablet can be used as a display. Displays are drawn by
+// the snapshot renderer and pushed to the page as PNGs using server-sent events.
+
+use super::error::{self, Error};
+use super::snapshot::render_png;
+use super::{Capabilities, Display, Frame, RenderContext, View};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, info, warn};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use tiny_http::{Header, Request, Response, Server};
+
+const PAGE: &str = include_str!("web.html");
+
+// Events queued for a client that isn't keeping up. Beyond this the client is dropped, and the
+// page reconnects when it can.
+const CLIENT_QUEUE_LENGTH: usize = 16;
+
+#[derive(Default)]
+struct Clients {
+    senders: Vec<SyncSender<String>>,
+
+    // The latest event for each display, sent to clients as soon as they connect.
+    latest: Vec<String>,
+}
+
+pub struct WebDisplay {
+    views: Vec<View>,
+
+    // The frame and light level last sent for each view, so that PNGs are only drawn when they
+    // change.
+    shown: Vec<Option<(Frame, f32)>>,
+
+    server: Arc<Server>,
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl WebDisplay {
+    pub fn new(address: &str, views: Vec<View>) -> Result<Self, Error> {
+        let server = Arc::new(Server::http(address).map_err(error::bind)?);
+
+        info!("Web display listening on {}", server.server_addr());
+
+        let clients = Arc::new(Mutex::new(Clients {
+            senders: vec![],
+            latest: vec![String::new(); views.len()],
+        }));
+
+        let (requests, new_clients) = (Arc::clone(&server), Arc::clone(&clients));
+        thread::spawn(move || {
+            for request in requests.incoming_requests() {
+                match request.url() {
+                    "/" => {
+                        let content_type =
+                            Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap();
+                        respond(
+                            request,
+                            Response::from_string(PAGE).with_header(content_type),
+                        );
+                    }
+                    "/events" => subscribe(request, &new_clients),
+                    _ => respond(
+                        request,
+                        Response::from_string("not found").with_status_code(404),
+                    ),
+                }
+            }
+        });
+
+        Ok(WebDisplay {
+            shown: vec![None; views.len()],
+            views,
+            server,
+            clients,
+        })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+}
+
+impl Drop for WebDisplay {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+impl Display for WebDisplay {
+    fn name(&self) -> &'static str {
+        "web"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            views: self.views.clone(),
+            dimmable: true,
+            hardware: false,
+        }
+    }
+
+    fn print(&mut self, ctx: &RenderContext) -> Result<(), Error> {
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Nothing is drawn while nobody's watching. Whoever connects next gets the frames as of
+        // the following tick.
+        if clients.senders.is_empty() {
+            self.shown.iter_mut().for_each(|shown| *shown = None);
+            clients.latest.iter_mut().for_each(String::clear);
+            return Ok(());
+        }
+
+        for (i, view) in self.views.iter().enumerate() {
+            let frame = (view.frame(ctx), ctx.light);
+
+            // Most iterations of the main loop don't change what's shown
+            if self.shown[i].as_ref() == Some(&frame) {
+                continue;
+            }
+
+            let png = render_png(&frame.0, frame.1)?;
+            let event = format!("data: {} {}\n\n", i, BASE64.encode(png));
+            clients
+                .senders
+                .retain(|sender| sender.try_send(event.clone()).is_ok());
+            clients.latest[i] = event;
+            self.shown[i] = Some(frame);
+        }
+
+        Ok(())
+    }
+}
+
+fn respond<R: std::io::Read>(request: Request, response: Response<R>) {
+    if let Err(e) = request.respond(response) {
+        warn!("Error responding to web display request: {}", e);
+    }
+}
+
+// Streams events to the client on its own thread, so that slow clients don't hold up the main
+// loop.
+fn subscribe(request: Request, clients: &Mutex<Clients>) {
+    let (sender, events) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+
+    {
+        let mut clients = clients.lock().unwrap_or_else(PoisonError::into_inner);
+        for event in clients.latest.iter().filter(|e| !e.is_empty()) {
+            let _ = sender.try_send(event.clone());
+        }
+        clients.senders.push(sender);
+    }
+
+    thread::spawn(move || {
+        let mut stream = request.into_writer();
+        let result = stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                Content-Type: text/event-stream\r\n\
+                Cache-Control: no-cache\r\n\r\n",
+            )
+            .and_then(|_| stream.flush())
+            .and_then(|_| {
+                for event in events {
+                    stream.write_all(event.as_bytes())?;
+                    stream.flush()?;
+                }
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            debug!("Web display client disconnected: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Layout;
+    use chrono::{Local, TimeZone};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_streams_frames() -> Result<(), Box<dyn std::error::Error>> {
+        let mut display = WebDisplay::new("127.0.0.1:0", vec![Layout::Text16x2.into()])?;
+        let address = display.address().unwrap();
+        let mut ctx = RenderContext {
+            time: Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap(),
+            current_state_index: 0,
+            scroll_step: 0,
+            weather: &None,
+            light: 1.0,
+            fetch_status: &Default::default(),
+            unit: Default::default(),
+            message: None,
+        };
+
+        // Nothing is drawn without clients
+        display.print(&ctx)?;
+        assert!(display.clients.lock().unwrap().latest[0].is_empty());
+
+        let page = ureq::get(&format!("http://{}/", address))
+            .call()?
+            .into_string()?;
+        assert!(page.contains("EventSource"));
+
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+
+        display.print(&ctx)?;
+
+        line.clear();
+        reader.read_line(&mut line)?;
+        // The base64 encoding of the PNG signature
+        assert!(line.starts_with("data: 0 iVBORw0KGgo"), "{:?}", line);
+        let first = line.clone();
+
+        // Frames are only sent when they change
+        display.print(&ctx)?;
+        ctx.time = Local.with_ymd_and_hms(2024, 1, 15, 9, 6, 0).unwrap();
+        display.print(&ctx)?;
+        reader.read_line(&mut line)?;
+        line.clear();
+        reader.read_line(&mut line)?;
+        assert!(line.starts_with("data: 0 "), "{:?}", line);
+        assert_ne!(line, first);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+// How hardware displays are connected to the Raspberry Pi. The defaults are the clock's own
+// wiring, and each can be changed with parameters like "lcd-16x2@rs=4,pwm=1".
+
+use crate::parameters::parse_number;
+
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Wiring {
+    // Displays that aren't attached to the Pi.
+    #[default]
+    None,
+
+    Lcd(LcdWiring),
+
+    I2c(I2cDevice),
+}
+
+impl Wiring {
+    // Changes the wiring according to a parameter, or returns why it can't be.
+    pub(super) fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), String> {
+        match self {
+            Wiring::None => Err("takes no parameters".to_string()),
+            Wiring::Lcd(lcd) => lcd.set(key, value),
+            Wiring::I2c(device) => device.set(key, value),
+        }
+    }
+
+    // Checks that the wiring could be how a display is connected, or returns why not.
+    pub(super) fn validate(&self) -> Result<(), String> {
+        match self {
+            Wiring::Lcd(lcd) => lcd.validate(),
+            Wiring::None | Wiring::I2c(_) => Ok(()),
+        }
+    }
+
+    // Checks that a display wired like this can be connected alongside one wired like the other,
+    // or returns why not.
+    pub(super) fn check_conflict(&self, other: &Wiring) -> Result<(), String> {
+        match (self, other) {
+            (Wiring::Lcd(lcd), Wiring::Lcd(other)) => lcd.check_conflict(other),
+            (Wiring::I2c(device), Wiring::I2c(other)) if device == other => Err(format!(
+                "I2C address {:#04x} on bus {} is already used",
+                device.address, device.bus
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // The parameters that change the default wiring to this one, e.g. "rs=4".
+    pub(super) fn parameters(&self, default: &Wiring) -> Vec<String> {
+        match (self, default) {
+            (Wiring::Lcd(lcd), Wiring::Lcd(default)) => lcd.parameters(default),
+            (Wiring::I2c(device), Wiring::I2c(default)) => device.parameters(default),
+            _ => vec![],
+        }
+    }
+}
+
+// The pins of an HD44780 character LCD driven in 4-bit mode, as BCM numbers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LcdWiring {
+    pub rs: u64,
+    pub en: u64,
+    pub d4: u64,
+    pub d5: u64,
+    pub d6: u64,
+    pub d7: u64,
+
+    // The pins of an RGB backlight, which light their colour when low. Red is lit and green and
+    // blue are turned off.
+    pub red: Option<u64>,
+    pub green: Option<u64>,
+    pub blue: Option<u64>,
+
+    // The PWM channel that dims the backlight: 0 is on pin 18 and 1 on pin 19.
+    pub pwm_channel: u8,
+}
+
+// The pins of PWM channels 0 and 1.
+const PWM_PINS: [u64; 2] = [18, 19];
+
+impl LcdWiring {
+    pub fn default_16x2() -> Self {
+        LcdWiring {
+            rs: 21,
+            en: 20,
+            d4: 26,
+            d5: 13,
+            d6: 6,
+            d7: 5,
+            red: Some(17),
+            green: Some(16),
+            blue: Some(19),
+            pwm_channel: 0,
+        }
+    }
+
+    pub fn default_20x4() -> Self {
+        LcdWiring {
+            d4: 19,
+            green: None,
+            blue: None,
+            ..Self::default_16x2()
+        }
+    }
+
+    // The pins that are connected, with their names.
+    fn pins(&self) -> Vec<(&'static str, u64)> {
+        [
+            ("rs", Some(self.rs)),
+            ("en", Some(self.en)),
+            ("d4", Some(self.d4)),
+            ("d5", Some(self.d5)),
+            ("d6", Some(self.d6)),
+            ("d7", Some(self.d7)),
+            ("red", self.red),
+            ("green", self.green),
+            ("blue", self.blue),
+        ]
+        .iter()
+        .filter_map(|&(name, pin)| Some((name, pin?)))
+        .collect()
+    }
+
+    fn pwm_pin(&self) -> u64 {
+        PWM_PINS[self.pwm_channel as usize]
+    }
+
+    // The PWM channel's pin can't also be one of the LCD's.
+    fn validate(&self) -> Result<(), String> {
+        let pwm_pin = self.pwm_pin();
+        match self.pins().iter().find(|(_, pin)| *pin == pwm_pin) {
+            Some((name, _)) => Err(format!(
+                "pin {} can't be both {} and PWM channel {}",
+                pwm_pin, name, self.pwm_channel
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Two LCDs can't share a pin or a PWM channel.
+    fn check_conflict(&self, other: &LcdWiring) -> Result<(), String> {
+        if self.pwm_channel == other.pwm_channel {
+            return Err(format!("PWM channel {} is already used", self.pwm_channel));
+        }
+
+        let pins = self.pins();
+        let other_pins = other.pins();
+        let other_pwm = ("PWM", other.pwm_pin());
+        for (_, pin) in pins.iter().chain(Some(&("PWM", self.pwm_pin()))) {
+            if let Some((name, _)) = other_pins
+                .iter()
+                .chain(Some(&other_pwm))
+                .find(|(_, other_pin)| other_pin == pin)
+            {
+                return Err(format!("pin {} is already used as {}", pin, name));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parameters(&self, default: &LcdWiring) -> Vec<String> {
+        let pin = |pin: Option<u64>| pin.map_or("none".to_string(), |p| p.to_string());
+        [
+            ("rs", Some(self.rs), Some(default.rs)),
+            ("en", Some(self.en), Some(default.en)),
+            ("d4", Some(self.d4), Some(default.d4)),
+            ("d5", Some(self.d5), Some(default.d5)),
+            ("d6", Some(self.d6), Some(default.d6)),
+            ("d7", Some(self.d7), Some(default.d7)),
+            ("red", self.red, default.red),
+            ("green", self.green, default.green),
+            ("blue", self.blue, default.blue),
+        ]
+        .iter()
+        .filter(|(_, value, default)| value != default)
+        .map(|(key, value, _)| format!("{}={}", key, pin(*value)))
+        .chain(
+            Some(format!("pwm={}", self.pwm_channel))
+                .filter(|_| self.pwm_channel != default.pwm_channel),
+        )
+        .collect()
+    }
+
+    fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), String> {
+        let value = value.ok_or_else(|| format!("expected a value for {}", key))?;
+        let pin = || parse_number(value).ok_or_else(|| format!("invalid pin {:?}", value));
+        let optional_pin = || match value {
+            "none" => Ok(None),
+            _ => pin().map(Some),
+        };
+
+        match key {
+            "rs" => self.rs = pin()?,
+            "en" => self.en = pin()?,
+            "d4" => self.d4 = pin()?,
+            "d5" => self.d5 = pin()?,
+            "d6" => self.d6 = pin()?,
+            "d7" => self.d7 = pin()?,
+            "red" => self.red = optional_pin()?,
+            "green" => self.green = optional_pin()?,
+            "blue" => self.blue = optional_pin()?,
+            "pwm" => {
+                self.pwm_channel = match value {
+                    "0" => 0,
+                    "1" => 1,
+                    _ => return Err(format!("invalid PWM channel {:?}, expected 0 or 1", value)),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "unknown parameter {:?}, expected rs, en, d4-d7, red, green, blue or pwm",
+                    key
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The 7-bit addresses that devices can have. The rest are reserved, e.g. for general calls.
+const I2C_ADDRESSES: RangeInclusive<u8> = 0x08..=0x77;
+
+// A device on an I2C bus.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I2cDevice {
+    pub bus: u8,
+    pub address: u8,
+}
+
+impl I2cDevice {
+    pub fn default_alphanum4() -> Self {
+        I2cDevice {
+            bus: 1,
+            address: 0x71,
+        }
+    }
+
+    pub fn default_seven_segment4() -> Self {
+        I2cDevice {
+            bus: 1,
+            address: 0x70,
+        }
+    }
+
+    fn parameters(&self, default: &I2cDevice) -> Vec<String> {
+        let mut parameters = vec![];
+        if self.bus != default.bus {
+            parameters.push(format!("bus={}", self.bus));
+        }
+        if self.address != default.address {
+            parameters.push(format!("address={:#04x}", self.address));
+        }
+        parameters
+    }
+
+    fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), String> {
+        match (key, value) {
+            // Just an address, e.g. "alphanum4@0x72"
+            (address, None) | ("address", Some(address)) => {
+                self.address = parse_number(address)
+                    .filter(|a| I2C_ADDRESSES.contains(a))
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid I2C address {:?}, expected {:#04x} to {:#04x}",
+                            address,
+                            I2C_ADDRESSES.start(),
+                            I2C_ADDRESSES.end()
+                        )
+                    })?
+            }
+            ("bus", Some(bus)) => {
+                self.bus = parse_number(bus).ok_or_else(|| format!("invalid I2C bus {:?}", bus))?
+            }
+            _ => {
+                return Err(format!(
+                    "unknown parameter {:?}, expected bus or address",
+                    key
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_wiring() {
+        let mut lcd = Wiring::Lcd(LcdWiring::default_20x4());
+        lcd.set("rs", Some("4")).unwrap();
+        lcd.set("red", Some("none")).unwrap();
+        lcd.set("pwm", Some("1")).unwrap();
+        assert_eq!(
+            lcd,
+            Wiring::Lcd(LcdWiring {
+                rs: 4,
+                red: None,
+                pwm_channel: 1,
+                ..LcdWiring::default_20x4()
+            })
+        );
+        assert!(lcd.set("pwm", Some("2")).is_err());
+        assert!(lcd.set("d8", Some("2")).is_err());
+        assert!(lcd.set("d4", None).is_err());
+
+        let mut device = Wiring::I2c(I2cDevice::default_alphanum4());
+        device.set("0x72", None).unwrap();
+        device.set("bus", Some("3")).unwrap();
+        assert_eq!(
+            device,
+            Wiring::I2c(I2cDevice {
+                bus: 3,
+                address: 0x72
+            })
+        );
+        assert!(device.set("address", Some("0x100")).is_err());
+        for reserved in ["0x00", "0x07", "0x78", "0x7f", "0xff"] {
+            assert!(
+                device.set("address", Some(reserved)).is_err(),
+                "{}",
+                reserved
+            );
+        }
+        device.set("address", Some("0x08")).unwrap();
+        device.set("0x77", None).unwrap();
+
+        assert!(Wiring::None.set("0x72", None).is_err());
+    }
+
+    #[test]
+    fn test_validate_wiring() {
+        assert!(Wiring::Lcd(LcdWiring::default_16x2()).validate().is_ok());
+        assert!(Wiring::Lcd(LcdWiring::default_20x4()).validate().is_ok());
+
+        // Pin 19 is blue on the 16x2 LCD and d4 on the 20x4 LCD
+        let mut lcd = Wiring::Lcd(LcdWiring::default_16x2());
+        lcd.set("pwm", Some("1")).unwrap();
+        assert_eq!(
+            lcd.validate(),
+            Err("pin 19 can't be both blue and PWM channel 1".to_string())
+        );
+        lcd.set("blue", Some("none")).unwrap();
+        assert!(lcd.validate().is_ok());
+
+        let mut lcd = Wiring::Lcd(LcdWiring::default_20x4());
+        lcd.set("rs", Some("18")).unwrap();
+        assert!(lcd.validate().is_err());
+    }
+
+    #[test]
+    fn test_wiring_conflicts() {
+        let lcd_16x2 = Wiring::Lcd(LcdWiring::default_16x2());
+        let alphanum4 = Wiring::I2c(I2cDevice::default_alphanum4());
+        let seven_segment4 = Wiring::I2c(I2cDevice::default_seven_segment4());
+        assert!(alphanum4.check_conflict(&seven_segment4).is_ok());
+        assert!(alphanum4.check_conflict(&lcd_16x2).is_ok());
+        assert!(Wiring::None.check_conflict(&Wiring::None).is_ok());
+        assert_eq!(
+            alphanum4.check_conflict(&alphanum4),
+            Err("I2C address 0x71 on bus 1 is already used".to_string())
+        );
+
+        // The same address on another bus is a different device
+        let mut device = alphanum4.clone();
+        device.set("bus", Some("3")).unwrap();
+        assert!(alphanum4.check_conflict(&device).is_ok());
+
+        let mut lcd = Wiring::Lcd(LcdWiring::default_20x4());
+        assert_eq!(
+            lcd.check_conflict(&lcd_16x2),
+            Err("PWM channel 0 is already used".to_string())
+        );
+        lcd.set("pwm", Some("1")).unwrap();
+        assert_eq!(
+            lcd.check_conflict(&lcd_16x2),
+            Err("pin 21 is already used as rs".to_string())
+        );
+
+        // A second LCD on pins of its own, dimmed by the other PWM channel, whose pin is the
+        // 16x2 LCD's blue backlight unless that's disconnected
+        for (key, value) in [
+            ("rs", "4"),
+            ("en", "27"),
+            ("d4", "22"),
+            ("d5", "23"),
+            ("d6", "24"),
+            ("d7", "25"),
+            ("red", "12"),
+        ] {
+            lcd.set(key, Some(value)).unwrap();
+        }
+        assert_eq!(
+            lcd.check_conflict(&lcd_16x2),
+            Err("pin 19 is already used as blue".to_string())
+        );
+        let mut lcd_16x2 = lcd_16x2;
+        lcd_16x2.set("blue", Some("none")).unwrap();
+        assert!(lcd.check_conflict(&lcd_16x2).is_ok());
+        assert!(lcd_16x2.check_conflict(&lcd).is_ok());
+    }
+
+    #[test]
+    fn test_wiring_parameters() {
+        let default = Wiring::Lcd(LcdWiring::default_20x4());
+        let mut lcd = default.clone();
+        assert!(lcd.parameters(&default).is_empty());
+        for (key, value) in [("rs", "4"), ("red", "none"), ("green", "16"), ("pwm", "1")] {
+            lcd.set(key, Some(value)).unwrap();
+        }
+        assert_eq!(
+            lcd.parameters(&default),
+            vec!["rs=4", "red=none", "green=16", "pwm=1"]
+        );
+
+        let default = Wiring::I2c(I2cDevice::default_alphanum4());
+        let mut device = default.clone();
+        device.set("0x72", None).unwrap();
+        assert_eq!(device.parameters(&default), vec!["address=0x72"]);
+    }
+}
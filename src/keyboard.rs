@@ -0,0 +1,112 @@
+// Keyboard controls for the terminal UI. Raw mode is enabled while a `Keyboard` is alive, so that
+// keypresses are read as soon as they are made and aren't echoed over the displays.
+
+use crate::command::Command;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use log::warn;
+use std::io;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+const BRIGHTNESS_STEP: f32 = 0.1;
+
+// Shown by the terminal UI.
+pub const KEYBOARD_HELP: &str =
+    "←/→ rotate | r refresh | u units | +/- brightness | a auto brightness | q quit";
+
+pub struct Keyboard {}
+
+impl Keyboard {
+    // Reads keypresses on a background thread, sending the commands they map to.
+    pub fn spawn(commands: Sender<Command>) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+
+        thread::spawn(move || loop {
+            match event::read() {
+                Ok(Event::Key(key)) => {
+                    if let Some(command) = command_for_key(&key) {
+                        if commands.send(command).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Error reading keyboard: {}", e);
+                    break;
+                }
+            }
+        });
+
+        Ok(Keyboard {})
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        if let Err(e) = terminal::disable_raw_mode() {
+            warn!("Error restoring terminal: {}", e);
+        }
+    }
+}
+
+fn command_for_key(key: &KeyEvent) -> Option<Command> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+
+    match key.code {
+        // Raw mode means Ctrl-C no longer sends SIGINT
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Command::Quit),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Command::Quit),
+
+        KeyCode::Right | KeyCode::Char('n') => Some(Command::NextState),
+        KeyCode::Left | KeyCode::Char('p') => Some(Command::PreviousState),
+        KeyCode::Char('r') => Some(Command::RefreshWeather),
+        KeyCode::Char('u') => Some(Command::ToggleUnit),
+
+        KeyCode::Up | KeyCode::Char('+') | KeyCode::Char('=') => {
+            Some(Command::AdjustBrightness(BRIGHTNESS_STEP))
+        }
+        KeyCode::Down | KeyCode::Char('-') => Some(Command::AdjustBrightness(-BRIGHTNESS_STEP)),
+        KeyCode::Char('a') => Some(Command::SetBrightness(None)),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_command_for_key() {
+        assert_eq!(
+            command_for_key(&key(KeyCode::Right)),
+            Some(Command::NextState)
+        );
+        assert_eq!(
+            command_for_key(&key(KeyCode::Char('p'))),
+            Some(Command::PreviousState)
+        );
+        assert_eq!(
+            command_for_key(&key(KeyCode::Char('-'))),
+            Some(Command::AdjustBrightness(-BRIGHTNESS_STEP))
+        );
+        assert_eq!(
+            command_for_key(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Command::Quit)
+        );
+        assert_eq!(command_for_key(&key(KeyCode::Char('c'))), None);
+
+        let mut release = key(KeyCode::Char('r'));
+        release.kind = KeyEventKind::Release;
+        assert_eq!(command_for_key(&release), None);
+    }
+}
@@ -1,23 +1,50 @@
+mod command;
 mod config;
 mod display;
+mod keyboard;
 mod light;
+mod logging;
+mod metrics;
+mod mqtt;
+mod parameters;
+mod server;
+mod status;
+mod systemd;
 mod weather;
 
-use chrono::{Local, Timelike};
+use chrono::Local;
+pub use command::Command;
 pub use config::Config;
+pub use display::{
+    alphanum_segments, custom_glyph, hd44780_glyph, seven_segment_digit, str_to_lcd_bytes,
+    Capabilities, CharacterLcd, CompositeDisplay, Console16x2Display, Console20x4Display, Display,
+    DisplayKind, DisplayOptions, DisplaySpec, DisplayType, Frame, HD44780Emulator, HT16K33Emulator,
+    I2cDevice, Layout, LcdPanel, LcdWiring, Message, RenderContext, Screen, SegmentPanel,
+    ShadowBuffer, SnapshotDisplay, TemperatureUnit, TerminalDisplay, View, WebDisplay, Wiring,
+};
 #[cfg(feature = "rpi-hw")]
 pub use display::{AlphaNum4Display, LCD16x2Display, LCD20x4Display, SevenSegment4Display};
-pub use display::{Console16x2Display, Console20x4Display, Display, DisplayType};
+pub use keyboard::Keyboard;
 #[cfg(feature = "rpi-hw")]
 pub use light::VEML7700LightSensor;
-pub use light::{LightSensor, LightSensorType, RandomLightSensor, TimeLightSensor};
-use log::{info, warn};
-use std::collections::HashMap;
+pub use light::{
+    LightSensor, LightSensorSpec, LightSensorType, RandomLightSensor, TimeLightSensor,
+};
+use log::{debug, info, warn};
+pub use logging::{init_logging, LevelFilters, LogConfig, LogFormat, LogTarget};
+use metrics::Metrics;
+pub use mqtt::{MqttClient, MqttConfig};
+pub use server::HttpServer;
+pub use status::Status;
 use std::fmt;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
 use std::{thread, time};
-pub use weather::OpenWeather;
+pub use systemd::{SystemdNotifier, SystemdUnit};
+pub use weather::{parse_weather, FetchStatus, OpenWeather, WeatherReport};
 
-const STATE_COUNT: u32 = 3;
+// Consecutive failures of a display or light sensor after which it's set up again.
+const REINIT_AFTER_FAILURES: u32 = 3;
 
 #[derive(Debug)]
 pub struct Error {
@@ -40,6 +67,7 @@ pub enum ErrorKind {
     Weather(Box<weather::Error>),
     Display(display::Error),
     Light(light::Error),
+    Server(server::Error),
 }
 
 impl fmt::Display for Error {
@@ -48,6 +76,7 @@ impl fmt::Display for Error {
             ErrorKind::Weather(ref err) => err.fmt(f),
             ErrorKind::Display(ref err) => err.fmt(f),
             ErrorKind::Light(ref err) => err.fmt(f),
+            ErrorKind::Server(ref err) => err.fmt(f),
         }
     }
 }
@@ -76,19 +105,37 @@ impl From<light::Error> for Error {
     }
 }
 
+impl From<server::Error> for Error {
+    fn from(e: server::Error) -> Self {
+        Error {
+            kind: ErrorKind::Server(e),
+        }
+    }
+}
+
 pub fn run(
     config: &Config,
-    display: &mut display::DisplayType,
+    display: &mut CompositeDisplay,
     light_sensor: &light::LightSensorType,
+    commands: &Receiver<Command>,
+    status: &Mutex<Status>,
+    notifier: &mut SystemdNotifier,
 ) -> Result<(), Error> {
     let no_weather_error_duration = config.weather_request_polling_interval * 3;
 
-    let state_machine = StateMachine::new(STATE_COUNT, config.state_duration.as_secs() as u32);
+    let mut state_machine = StateMachine::new(config.state_duration.as_secs() as u32);
 
     let mut last_weather_attempt = time::Instant::now();
     let mut last_weather_success = time::Instant::now();
 
-    let mut weather = match weather::get_weather(&config.uri, config.weather_request_timeout) {
+    let mut fetch_status = FetchStatus::default();
+    let mut metrics = Metrics::default();
+
+    let fallback_light_sensor = TimeLightSensor::new();
+    let mut light_sensor_health = DeviceHealth::default();
+    let mut display_health = Vec::new();
+
+    let mut weather = match fetch_weather(config, &mut fetch_status, &mut metrics) {
         Ok(w) => Some(w),
         Err(e) => {
             warn!("Error getting initial weather: {}", e);
@@ -96,24 +143,40 @@ pub fn run(
         }
     };
 
+    let mut unit = TemperatureUnit::default();
+    let mut brightness_override = None;
+    let mut refresh_requested = false;
+    // The message being shown, and when it should stop being shown
+    let mut message: Option<(String, time::Instant)> = None;
+    let mut scroll = ScrollCounter::default();
+    let mut weather_updated = true;
+
     loop {
         let now = time::Instant::now();
 
+        if matches!(message, Some((_, until)) if now >= until) {
+            message = None;
+        }
+
         let duration_since_last_weather = now.duration_since(last_weather_attempt);
-        if duration_since_last_weather > config.weather_request_polling_interval {
+        if refresh_requested
+            || duration_since_last_weather > config.weather_request_polling_interval
+        {
             last_weather_attempt = now;
+            refresh_requested = false;
 
             info!(
                 "Getting updated weather ({}s since last attempt)",
                 config.weather_request_polling_interval.as_secs(),
             );
 
-            match weather::get_weather(&config.uri, config.weather_request_timeout) {
+            match fetch_weather(config, &mut fetch_status, &mut metrics) {
                 Ok(updated_weather) => {
                     info!("successfully updated weather");
 
                     last_weather_success = now;
-                    weather = Some(updated_weather)
+                    weather = Some(updated_weather);
+                    weather_updated = true;
                 }
                 Err(e) => {
                     warn!(
@@ -124,59 +187,344 @@ pub fn run(
             };
         }
 
-        let light_normalized = light_sensor.read_light_normalized()?;
+        let (light_normalized, lux) = match brightness_override {
+            Some(brightness) => (brightness, None),
+            None => read_light(
+                light_sensor,
+                &fallback_light_sensor,
+                &mut light_sensor_health,
+                &mut metrics,
+            ),
+        };
+        metrics.record_light(light_normalized, lux);
 
-        if now > last_weather_success + no_weather_error_duration {
+        let displayed_weather = if now > last_weather_success + no_weather_error_duration {
             warn!(
                 "no successful weather in over {}s. Displaying empty weather",
                 no_weather_error_duration.as_secs()
             );
-            display.print(
-                &Local::now(),
-                state_machine.current_state(),
-                &None,
-                light_normalized,
-            )?;
+            &None
         } else {
-            display.print(
-                &Local::now(),
-                state_machine.current_state(),
-                &weather,
-                light_normalized,
-            )?;
+            &weather
+        };
+
+        let current_state_index = state_machine.current_state();
+        let shown_message = message.as_ref().map(|(text, _)| Message { text });
+        let ctx = RenderContext {
+            time: Local::now(),
+            current_state_index,
+            scroll_step: scroll.tick(
+                current_state_index,
+                shown_message.map(|m| m.text),
+                config.scroll_ticks,
+            ),
+            weather: displayed_weather,
+            light: light_normalized,
+            fetch_status: &fetch_status,
+            unit,
+            message: shown_message,
+        };
+
+        // Each display is looked after separately, so that one failing doesn't affect the others
+        let displays = display.displays_mut();
+        display_health.resize_with(displays.len(), DeviceHealth::default);
+        let mut printed = displays.is_empty();
+        for (display, health) in displays.iter_mut().zip(display_health.iter_mut()) {
+            printed |= print_display(display.as_mut(), &ctx, health, &mut metrics);
         }
 
-        thread::sleep(config.loop_sleep_duration);
+        // If every display is failing, systemd restarts the clock in case that helps
+        if printed {
+            notifier.watchdog();
+        }
+
+        metrics.record_loop(now.elapsed());
+
+        {
+            let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            status.metrics = metrics.clone();
+            status.frames = display.frames(&ctx);
+            status.fetch_status = fetch_status.clone();
+            status.light = light_normalized;
+            status.brightness_override = brightness_override;
+            status.lux = lux;
+            status.message = ctx.message.map(|m| m.text.to_string());
+            if weather_updated {
+                status.weather = weather.clone();
+                weather_updated = false;
+            }
+        }
+
+        // Commands interrupt the sleep, so that their effect is shown straight away
+        match commands.recv_timeout(config.loop_sleep_duration) {
+            Ok(command) => {
+                info!("Received command: {:?}", command);
+
+                match command {
+                    Command::NextState => state_machine.advance(),
+                    Command::PreviousState => state_machine.rewind(),
+                    Command::RefreshWeather => refresh_requested = true,
+                    Command::ToggleUnit => unit = unit.toggled(),
+                    Command::AdjustBrightness(delta) => {
+                        brightness_override = Some((light_normalized + delta).clamp(0.0, 1.0))
+                    }
+                    Command::SetBrightness(brightness) => {
+                        brightness_override = brightness.map(|b| b.clamp(0.0, 1.0))
+                    }
+                    Command::ShowMessage(text, duration) => {
+                        message = time::Instant::now()
+                            .checked_add(duration)
+                            .map(|until| (text, until))
+                    }
+                    Command::Quit => return Ok(()),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(config.loop_sleep_duration),
+        }
     }
 }
 
-struct StateMachine {
-    state_map: HashMap<u32, u32>,
-    state_count: u32,
-    state_duration_secs: u32,
+// Reads the light level and lux, falling back to a light level based on the time of day if the
+// sensor fails, and setting the sensor up again if it keeps failing.
+fn read_light(
+    light_sensor: &LightSensorType,
+    fallback: &TimeLightSensor,
+    health: &mut DeviceHealth,
+    metrics: &mut Metrics,
+) -> (f32, Option<f32>) {
+    let err = match light_sensor.read_light_normalized() {
+        Ok(light) => {
+            if health.succeeded() {
+                info!("Light sensor recovered");
+            }
+            return (light, light_sensor.last_lux());
+        }
+        Err(e) => e,
+    };
+
+    metrics.record_light_error();
+    let reinit = health.failed();
+    if health.consecutive_failures == 1 || reinit {
+        warn!(
+            "Error reading light sensor: {}. Using time-based light",
+            err
+        );
+    } else {
+        debug!("Error reading light sensor: {}", err);
+    }
+
+    if reinit {
+        warn!(
+            "Reinitializing light sensor after {} failures",
+            health.consecutive_failures
+        );
+        if let Err(e) = light_sensor.reinit() {
+            warn!("Error reinitializing light sensor: {}", e);
+        }
+    }
+
+    (fallback.read_light_normalized().unwrap_or(1.0), None)
 }
 
-impl StateMachine {
-    fn new(state_count: u32, state_duration_secs: u32) -> Self {
-        let mut state_map = HashMap::new();
-
-        let mut current_build_state = 0;
-        for i in 0..state_duration_secs * state_count {
-            state_map.insert(i, current_build_state);
-            if (i + 1) % state_duration_secs == 0 {
-                current_build_state += 1;
+// Prints to a display, setting it up again if it keeps failing. Returns whether it printed.
+fn print_display(
+    display: &mut dyn Display,
+    ctx: &RenderContext,
+    health: &mut DeviceHealth,
+    metrics: &mut Metrics,
+) -> bool {
+    let err = match display.print(ctx) {
+        Ok(()) => {
+            if health.succeeded() {
+                info!("{} display recovered", display.label());
             }
+            return true;
         }
+        Err(e) => e,
+    };
+
+    let label = display.label();
+    metrics.record_display_error(err.display().unwrap_or(&label));
+    let reinit = health.failed();
+    if health.consecutive_failures == 1 || reinit {
+        warn!("Error printing to {} display: {}", label, err);
+    } else {
+        debug!("Error printing to {} display: {}", label, err);
+    }
+
+    if reinit {
+        warn!(
+            "Reinitializing {} display after {} failures",
+            label, health.consecutive_failures
+        );
+        if let Err(e) = display.reinit() {
+            warn!("Error reinitializing {} display: {}", label, e);
+        }
+    }
+
+    false
+}
+
+// Fetches the weather, recording the outcome.
+fn fetch_weather(
+    config: &Config,
+    fetch_status: &mut FetchStatus,
+    metrics: &mut Metrics,
+) -> Result<OpenWeather, weather::Error> {
+    let start = time::Instant::now();
+    let result = weather::get_weather(&config.uri, config.weather_request_timeout);
+    metrics.record_fetch(start.elapsed(), &result);
+
+    match &result {
+        Ok(_) => fetch_status.record_success(Local::now()),
+        Err(e) => fetch_status.record_failure(Local::now(), e),
+    }
 
+    result
+}
+
+// Counts the consecutive failures of a display or light sensor.
+#[derive(Default)]
+struct DeviceHealth {
+    consecutive_failures: u32,
+}
+
+impl DeviceHealth {
+    // Records a success, returning whether the device had been failing.
+    fn succeeded(&mut self) -> bool {
+        std::mem::take(&mut self.consecutive_failures) > 0
+    }
+
+    // Records a failure, returning whether it's time to try setting the device up again.
+    fn failed(&mut self) -> bool {
+        self.consecutive_failures += 1;
+        self.consecutive_failures % REINIT_AFTER_FAILURES == 0
+    }
+}
+
+// Counts the loop iterations that the same rotation step and message have been shown for, so that
+// text too long to show at once scrolls from its start whenever either changes.
+#[derive(Default)]
+struct ScrollCounter {
+    shown: Option<(u32, Option<String>)>,
+    ticks: u32,
+}
+
+impl ScrollCounter {
+    // Records a loop iteration, returning how many places text has scrolled: one every
+    // `ticks_per_step` iterations.
+    fn tick(&mut self, state: u32, message: Option<&str>, ticks_per_step: u32) -> u32 {
+        let shown = (state, message.map(str::to_string));
+        if self.shown.as_ref() == Some(&shown) {
+            self.ticks += 1;
+        } else {
+            self.shown = Some(shown);
+            self.ticks = 0;
+        }
+        self.ticks / ticks_per_step.max(1)
+    }
+}
+
+// Counts the steps of the screen rotation, one every `state_duration_secs`. Each display shows the
+// step modulo the length of its own playlist, so displays with playlists of different lengths
+// rotate together.
+//
+// Steps wrap at a multiple of every playlist length up to 16, so the rotation carries on seamlessly
+// rather than showing a screen twice.
+const ROTATION_PERIOD: i64 = 720_720;
+
+struct StateMachine {
+    state_duration_secs: u32,
+
+    // Steps that the rotation has been moved forward by advance() and rewind()
+    offset: i64,
+}
+
+impl StateMachine {
+    fn new(state_duration_secs: u32) -> Self {
         StateMachine {
-            state_map,
-            state_duration_secs,
-            state_count,
+            state_duration_secs: state_duration_secs.max(1),
+            offset: 0,
         }
     }
 
     fn current_state(&self) -> u32 {
-        let second_mod = Local::now().second() % (self.state_duration_secs * self.state_count);
-        *self.state_map.get(&second_mod).unwrap()
+        self.state_at(Local::now().timestamp())
+    }
+
+    // The step at a Unix timestamp, wrapped within the rotation period before narrowing.
+    fn state_at(&self, timestamp: i64) -> u32 {
+        let step = timestamp.div_euclid(self.state_duration_secs as i64) + self.offset;
+        step.rem_euclid(ROTATION_PERIOD) as u32
+    }
+
+    // Moves the rotation on by one step
+    fn advance(&mut self) {
+        self.offset += 1;
+    }
+
+    // Moves the rotation back by one step
+    fn rewind(&mut self) {
+        self.offset -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_machine() {
+        let mut state_machine = StateMachine::new(2);
+
+        // Steps shown by a display with a playlist of three screens
+        let states = |sm: &StateMachine| (60..68).map(|t| sm.state_at(t) % 3).collect::<Vec<_>>();
+        assert_eq!(states(&state_machine), vec![0, 0, 1, 1, 2, 2, 0, 0]);
+
+        state_machine.advance();
+        assert_eq!(states(&state_machine), vec![1, 1, 2, 2, 0, 0, 1, 1]);
+
+        state_machine.rewind();
+        state_machine.rewind();
+        assert_eq!(states(&state_machine), vec![2, 2, 0, 0, 1, 1, 2, 2]);
+
+        // Rewinding past zero still shows every screen in turn, whatever the length of the playlist
+        let last = (ROTATION_PERIOD - 1) as u32;
+        let steps = (0..4)
+            .map(|t| state_machine.state_at(t * 2))
+            .collect::<Vec<_>>();
+        assert_eq!(steps, vec![last, 0, 1, 2]);
+        for len in 1..=16 {
+            let screens = steps.iter().map(|step| step % len).collect::<Vec<_>>();
+            let expected = (0..4).map(|i| (i + len - 1) % len).collect::<Vec<_>>();
+            assert_eq!(screens, expected, "playlist of {} screens", len);
+        }
+    }
+
+    #[test]
+    fn test_scroll_counter() {
+        let mut scroll = ScrollCounter::default();
+        let steps = (0..5).map(|_| scroll.tick(7, None, 2)).collect::<Vec<_>>();
+        assert_eq!(steps, vec![0, 0, 1, 1, 2]);
+
+        // Text starts scrolling again when the screen or message changes
+        assert_eq!(scroll.tick(8, None, 1), 0);
+        assert_eq!(scroll.tick(8, None, 1), 1);
+        assert_eq!(scroll.tick(8, Some("Hi"), 1), 0);
+        assert_eq!(scroll.tick(8, Some("Hi"), 0), 1);
+        assert_eq!(scroll.tick(8, Some("Bye"), 1), 0);
+    }
+
+    #[test]
+    fn test_device_health() {
+        let mut health = DeviceHealth::default();
+        assert!(!health.succeeded());
+
+        let reinits = (0..7).map(|_| health.failed()).collect::<Vec<_>>();
+        assert_eq!(reinits, vec![false, false, true, false, false, true, false]);
+
+        assert!(health.succeeded());
+        assert!(!health.failed());
     }
 }
@@ -1,4 +1,5 @@
 mod error;
+mod spec;
 
 // From: https://en.wikipedia.org/wiki/Lux
 //
@@ -20,6 +21,7 @@ mod error;
 // 32,000–100,000	    Direct sunlight
 
 pub use error::Error;
+pub use spec::LightSensorSpec;
 
 use chrono::{Local, NaiveTime};
 use lazy_static::*;
@@ -60,11 +62,39 @@ impl LightSensor for LightSensorType {
             Self::VEML7700(sensor) => sensor.read_light_normalized(),
         }
     }
+
+    fn last_lux(&self) -> Option<f32> {
+        match &self {
+            Self::Random(sensor) => sensor.last_lux(),
+            Self::Time(sensor) => sensor.last_lux(),
+            #[cfg(feature = "rpi-hw")]
+            Self::VEML7700(sensor) => sensor.last_lux(),
+        }
+    }
+
+    fn reinit(&self) -> Result<(), Error> {
+        match &self {
+            Self::Random(sensor) => sensor.reinit(),
+            Self::Time(sensor) => sensor.reinit(),
+            #[cfg(feature = "rpi-hw")]
+            Self::VEML7700(sensor) => sensor.reinit(),
+        }
+    }
 }
 
 // Returns a value between 0 and 1
 pub trait LightSensor {
     fn read_light_normalized(&self) -> Result<f32, Error>;
+
+    // The raw reading behind the most recent normalized value, for sensors that measure lux.
+    fn last_lux(&self) -> Option<f32> {
+        None
+    }
+
+    // Sets the sensor up again after it's stopped working.
+    fn reinit(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub struct TimeLightSensor {}
@@ -140,17 +170,19 @@ fn time_based_brightness_for_time(t: &NaiveTime) -> f32 {
 #[cfg(feature = "rpi-hw")]
 pub struct VEML7700LightSensor {
     sensor: Mutex<Veml6030<I2c>>,
+    last_lux: Mutex<Option<f32>>,
 }
 
 #[cfg(feature = "rpi-hw")]
 impl VEML7700LightSensor {
-    pub fn new() -> Result<Self, Error> {
-        let i2c = I2c::new()?;
+    pub fn new(bus: u8) -> Result<Self, Error> {
+        let i2c = I2c::with_bus(bus)?;
         let mut sensor = Veml6030::new(i2c, SlaveAddr::default());
         sensor.enable()?;
 
         Ok(VEML7700LightSensor {
             sensor: Mutex::new(sensor),
+            last_lux: Mutex::new(None),
         })
     }
 }
@@ -161,8 +193,21 @@ impl LightSensor for VEML7700LightSensor {
         let lux = self.sensor.lock()?.read_lux()?;
         debug!("Lux: {}", lux);
 
+        if let Ok(mut last_lux) = self.last_lux.lock() {
+            *last_lux = Some(lux);
+        }
+
         Ok(normalize_lux(lux))
     }
+
+    fn last_lux(&self) -> Option<f32> {
+        self.last_lux.lock().ok().and_then(|l| *l)
+    }
+
+    fn reinit(&self) -> Result<(), Error> {
+        self.sensor.lock()?.enable()?;
+        Ok(())
+    }
 }
 
 pub struct RandomLightSensor {
@@ -197,6 +242,8 @@ fn normalize_lux(lux: f32) -> f32 {
 }
 
 #[cfg(test)]
+// The tests dereference the lazy statics explicitly
+#[allow(clippy::explicit_auto_deref)]
 mod tests {
     use super::*;
 
@@ -26,6 +26,20 @@ pub enum ErrorKind {
 
     LockLightSensor,
 
+    // A light sensor type that doesn't exist, and the ones that do.
+    UnknownType {
+        name: String,
+        expected: Vec<String>,
+    },
+
+    InvalidParameter {
+        spec: String,
+        reason: String,
+    },
+
+    // A hardware light sensor, when built without the rpi-hw feature.
+    Unsupported(String),
+
     #[cfg(feature = "rpi-hw")]
     I2C(rppal::i2c::Error),
 
@@ -42,6 +56,27 @@ impl fmt::Display for Error {
                 write!(f, "a task failed while holding Light Sensor lock")
             }
 
+            ErrorKind::UnknownType {
+                ref name,
+                ref expected,
+            } => write!(
+                f,
+                "unknown light sensor type {:?}, expected one of: {}",
+                name,
+                expected.join(", ")
+            ),
+
+            ErrorKind::InvalidParameter {
+                ref spec,
+                ref reason,
+            } => write!(f, "invalid light sensor {:?}: {}", spec, reason),
+
+            ErrorKind::Unsupported(ref name) => write!(
+                f,
+                "{} light sensors need the clock to be built with the rpi-hw feature",
+                name
+            ),
+
             #[cfg(feature = "rpi-hw")]
             ErrorKind::I2C(ref err) => err.fmt(f),
 
@@ -51,6 +86,31 @@ impl fmt::Display for Error {
     }
 }
 
+pub fn unknown_type(name: &str, expected: &[String]) -> Error {
+    Error {
+        kind: ErrorKind::UnknownType {
+            name: name.to_string(),
+            expected: expected.to_vec(),
+        },
+    }
+}
+
+pub fn invalid_parameter(spec: &str, reason: String) -> Error {
+    Error {
+        kind: ErrorKind::InvalidParameter {
+            spec: spec.to_string(),
+            reason,
+        },
+    }
+}
+
+#[cfg_attr(feature = "rpi-hw", allow(dead_code))]
+pub fn unsupported(name: &str) -> Error {
+    Error {
+        kind: ErrorKind::Unsupported(name.to_string()),
+    }
+}
+
 impl From<PoisonError<MutexGuard<'_, ThreadRng>>> for Error {
     fn from(_: PoisonError<MutexGuard<'_, ThreadRng>>) -> Self {
         Error {
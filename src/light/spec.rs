@@ -0,0 +1,155 @@
+// Light sensors as they're chosen on the command line, e.g. "veml7700" or "veml7700@bus=3", and
+// building them.
+
+use super::error::{self, Error};
+#[cfg(feature = "rpi-hw")]
+use super::VEML7700LightSensor;
+use super::{LightSensorType, RandomLightSensor, TimeLightSensor};
+use crate::parameters::{self, parse_number};
+
+use std::fmt;
+use std::str::FromStr;
+
+// The I2C bus of the Raspberry Pi's GPIO header.
+const DEFAULT_BUS: u8 = 1;
+
+const SPECS: [LightSensorSpec; 3] = [
+    LightSensorSpec::Random,
+    LightSensorSpec::Time,
+    LightSensorSpec::VEML7700 { bus: DEFAULT_BUS },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightSensorSpec {
+    // A random light level, for trying out dimming.
+    Random,
+
+    // A light level that follows the time of day.
+    Time,
+
+    // The VEML7700 ambient light sensor, on an I2C bus.
+    VEML7700 { bus: u8 },
+}
+
+impl LightSensorSpec {
+    // Whether this light sensor can be built with the features this was compiled with.
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, LightSensorSpec::VEML7700 { .. }) || cfg!(feature = "rpi-hw")
+    }
+
+    // The name of the light sensor, without its parameters.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightSensorSpec::Random => "random",
+            LightSensorSpec::Time => "time",
+            LightSensorSpec::VEML7700 { .. } => "veml7700",
+        }
+    }
+}
+
+impl FromStr for LightSensorSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameters) = parameters::split(s);
+
+        let mut spec = SPECS
+            .iter()
+            .find(|spec| spec.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names = SPECS
+                    .iter()
+                    .filter(|spec| spec.is_supported())
+                    .map(|spec| spec.name().to_string())
+                    .collect::<Vec<_>>();
+                error::unknown_type(name, &names)
+            })?;
+
+        for (key, value) in parameters {
+            match (&mut spec, key, value) {
+                (LightSensorSpec::VEML7700 { bus }, "bus", Some(value)) => {
+                    *bus = parse_number(value).ok_or_else(|| {
+                        error::invalid_parameter(s, format!("invalid I2C bus {:?}", value))
+                    })?
+                }
+                (LightSensorSpec::VEML7700 { .. }, _, _) => {
+                    return Err(error::invalid_parameter(
+                        s,
+                        format!("unknown parameter {:?}, expected bus", key),
+                    ))
+                }
+                _ => {
+                    return Err(error::invalid_parameter(
+                        s,
+                        "takes no parameters".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+// The spec as it's given on the command line, with parameters that aren't the default.
+impl fmt::Display for LightSensorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LightSensorSpec::VEML7700 { bus } if *bus != DEFAULT_BUS => {
+                write!(f, "{}@bus={}", self.name(), bus)
+            }
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl LightSensorType {
+    pub fn from_spec(spec: &LightSensorSpec) -> Result<Self, Error> {
+        match spec {
+            LightSensorSpec::Random => Ok(LightSensorType::Random(RandomLightSensor::new())),
+            LightSensorSpec::Time => Ok(LightSensorType::Time(TimeLightSensor::new())),
+            #[cfg(feature = "rpi-hw")]
+            LightSensorSpec::VEML7700 { bus } => {
+                Ok(LightSensorType::VEML7700(VEML7700LightSensor::new(*bus)?))
+            }
+            #[cfg(not(feature = "rpi-hw"))]
+            LightSensorSpec::VEML7700 { .. } => Err(error::unsupported(spec.name())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::error::ErrorKind;
+
+    #[test]
+    fn test_parse_light_sensor_spec() {
+        for spec in SPECS {
+            assert_eq!(spec.to_string().parse::<LightSensorSpec>().unwrap(), spec);
+        }
+
+        assert_eq!(
+            "veml7700@bus=3".parse::<LightSensorSpec>().unwrap(),
+            LightSensorSpec::VEML7700 { bus: 3 }
+        );
+        assert_eq!(
+            LightSensorSpec::VEML7700 { bus: 3 }.to_string(),
+            "veml7700@bus=3"
+        );
+        assert!(matches!(
+            "time@bus=3".parse::<LightSensorSpec>().unwrap_err().kind(),
+            ErrorKind::InvalidParameter { .. }
+        ));
+
+        let err = "veml6030".parse::<LightSensorSpec>().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnknownType { .. }));
+        assert!(err.to_string().contains("random, time"));
+
+        assert!(matches!(
+            LightSensorType::from_spec(&LightSensorSpec::Time),
+            Ok(LightSensorType::Time(_))
+        ));
+    }
+}
@@ -0,0 +1,530 @@
+// Log output: levels per module, written to the terminal, a rotating file, syslog and/or journald,
+// as text or as JSON lines.
+
+mod error;
+
+pub use error::Error;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use crossterm::style::Stylize;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+// The syslog "daemon" facility, for system services.
+const SYSLOG_FACILITY: u8 = 3;
+
+const IDENTIFIER: &str = "pi_clock";
+
+// The level to log at by default, and for particular modules (including those of dependencies),
+// parsed from e.g. "warn,pi_clock::weather=debug,rumqttc=off".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelFilters {
+    default: LevelFilter,
+
+    // Longest module first, so that the most specific filter is found first
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LevelFilters {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for LevelFilters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filters = LevelFilters {
+            default: LevelFilter::Warn,
+            modules: Vec::new(),
+        };
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let parse_level = |level: &str| {
+                level
+                    .parse::<LevelFilter>()
+                    .map_err(|_| format!("invalid log level {:?}", level))
+            };
+
+            match directive.split_once('=') {
+                Some((module, level)) => filters
+                    .modules
+                    .push((module.trim().to_string(), parse_level(level.trim())?)),
+                None => filters.default = parse_level(directive)?,
+            }
+        }
+
+        filters
+            .modules
+            .sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(filters)
+    }
+}
+
+// The filters as they're given on the command line, with levels in lower case.
+impl fmt::Display for LevelFilters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.default.as_str().to_lowercase())?;
+        for (module, level) in &self.modules {
+            write!(f, ",{}={}", module, level.as_str().to_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+
+    // One JSON object per line, with time, level, target and message fields.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    // Standard error, so that logs don't get mixed up with console displays.
+    Terminal,
+
+    // A file that's moved to `path.1` once it reaches `max_bytes`, with `path.1` moved to
+    // `path.2` and so on, keeping `keep` old files.
+    File {
+        path: PathBuf,
+        max_bytes: u64,
+        keep: usize,
+    },
+
+    Syslog,
+
+    // The systemd journal, with the module a message came from as a field of its own.
+    Journald,
+}
+
+/// Which messages are logged, how they're written and where to.
+pub struct LogConfig {
+    pub levels: LevelFilters,
+    pub format: LogFormat,
+    pub targets: Vec<LogTarget>,
+}
+
+/// Sets up logging for the whole process. Can only be called once.
+pub fn init_logging(config: LogConfig) -> Result<(), Error> {
+    let outputs = config
+        .targets
+        .iter()
+        .map(|target| Output::open(target).map(Mutex::new))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let max_level = config.levels.max();
+    log::set_boxed_logger(Box::new(Logger {
+        levels: config.levels,
+        format: config.format,
+        outputs,
+    }))?;
+    log::set_max_level(max_level);
+
+    Ok(())
+}
+
+struct Logger {
+    levels: LevelFilters,
+    format: LogFormat,
+    outputs: Vec<Mutex<Output>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let entry = Entry {
+            time: Local::now(),
+            level: record.level(),
+            target: record.target(),
+            message: record.args().to_string(),
+        };
+
+        for output in &self.outputs {
+            if let Ok(mut output) = output.lock() {
+                // There's nowhere to report errors writing logs, so they're dropped
+                let _ = output.write(&entry, self.format);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for output in &self.outputs {
+            if let Ok(mut output) = output.lock() {
+                let _ = output.flush();
+            }
+        }
+    }
+}
+
+struct Entry<'a> {
+    time: DateTime<Local>,
+    level: Level,
+    target: &'a str,
+    message: String,
+}
+
+impl Entry<'_> {
+    fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => format!(
+                "{} [{}] {}: {}",
+                self.time.format("%Y-%m-%d %H:%M:%S%.3f"),
+                self.level,
+                self.target,
+                self.message
+            ),
+            LogFormat::Json => json!({
+                "time": self.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+                "level": self.level.as_str(),
+                "target": self.target,
+                "message": self.message,
+            })
+            .to_string(),
+        }
+    }
+}
+
+enum Output {
+    Terminal {
+        colored: bool,
+    },
+    File(RotatingFile),
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+    #[cfg(unix)]
+    Journald(std::os::unix::net::UnixDatagram),
+}
+
+impl Output {
+    fn open(target: &LogTarget) -> io::Result<Self> {
+        match target {
+            LogTarget::Terminal => Ok(Output::Terminal {
+                colored: io::stderr().is_terminal(),
+            }),
+            LogTarget::File {
+                path,
+                max_bytes,
+                keep,
+            } => Ok(Output::File(RotatingFile::open(
+                path.clone(),
+                *max_bytes,
+                *keep,
+            )?)),
+            #[cfg(unix)]
+            LogTarget::Syslog => Ok(Output::Syslog(connect(SYSLOG_SOCKET)?)),
+            #[cfg(unix)]
+            LogTarget::Journald => Ok(Output::Journald(connect(JOURNALD_SOCKET)?)),
+            #[cfg(not(unix))]
+            LogTarget::Syslog | LogTarget::Journald => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "syslog and journald are only supported on Unix",
+            )),
+        }
+    }
+
+    fn write(&mut self, entry: &Entry, format: LogFormat) -> io::Result<()> {
+        match self {
+            Output::Terminal { colored } => {
+                let line = match format {
+                    LogFormat::Text if *colored => entry.format(format).replacen(
+                        &format!("[{}]", entry.level),
+                        &format!("[{}]", colored_level(entry.level)),
+                        1,
+                    ),
+                    _ => entry.format(format),
+                };
+                writeln!(io::stderr().lock(), "{}", line)
+            }
+            Output::File(file) => file.write_line(&entry.format(format)),
+            #[cfg(unix)]
+            Output::Syslog(socket) => {
+                let message = format!(
+                    "<{}>{}[{}]: {}",
+                    SYSLOG_FACILITY * 8 + severity(entry.level),
+                    IDENTIFIER,
+                    std::process::id(),
+                    entry.format(format)
+                );
+                socket.send(message.as_bytes()).map(|_| ())
+            }
+            #[cfg(unix)]
+            Output::Journald(socket) => socket.send(&journald_fields(entry)).map(|_| ()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Terminal { .. } => io::stderr().flush(),
+            Output::File(file) => file.file.flush(),
+            #[cfg(unix)]
+            Output::Syslog(_) | Output::Journald(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect(path: &str) -> io::Result<std::os::unix::net::UnixDatagram> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+fn colored_level(level: Level) -> String {
+    let name = level.to_string();
+    match level {
+        Level::Error => name.red().to_string(),
+        Level::Warn => name.yellow().to_string(),
+        Level::Info => name.green().to_string(),
+        Level::Debug => name.blue().to_string(),
+        Level::Trace => name.magenta().to_string(),
+    }
+}
+
+// The syslog severity of a level, which journald also uses for priorities.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+// A message in journald's native protocol: one field per line, or for values with newlines in,
+// the field name, a line break, the length as a little-endian u64 and then the value.
+fn journald_fields(entry: &Entry) -> Vec<u8> {
+    let mut fields = Vec::new();
+    let priority = severity(entry.level).to_string();
+
+    for (name, value) in [
+        ("PRIORITY", priority.as_str()),
+        ("SYSLOG_IDENTIFIER", IDENTIFIER),
+        ("TARGET", entry.target),
+        ("MESSAGE", entry.message.as_str()),
+    ] {
+        fields.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            fields.push(b'\n');
+            fields.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            fields.push(b'=');
+        }
+        fields.extend_from_slice(value.as_bytes());
+        fields.push(b'\n');
+    }
+
+    fields
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(message: &str) -> Entry<'_> {
+        Entry {
+            time: Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap(),
+            level: Level::Warn,
+            target: "pi_clock::weather",
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_level_filters() {
+        let filters: LevelFilters = "info, pi_clock=debug, pi_clock::weather=trace, rumqttc=off"
+            .parse()
+            .unwrap();
+
+        assert_eq!(filters.level_for("pi_clock::weather"), LevelFilter::Trace);
+        assert_eq!(
+            filters.level_for("pi_clock::weather::error"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filters.level_for("pi_clock::server"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("pi_clock_extra"), LevelFilter::Info);
+        assert_eq!(filters.level_for("rumqttc::state"), LevelFilter::Off);
+        assert_eq!(filters.max(), LevelFilter::Trace);
+        assert_eq!(
+            filters.to_string(),
+            "info,pi_clock::weather=trace,pi_clock=debug,rumqttc=off"
+        );
+        assert_eq!(filters.to_string().parse::<LevelFilters>(), Ok(filters));
+
+        assert_eq!(
+            "".parse::<LevelFilters>().unwrap().level_for("pi_clock"),
+            LevelFilter::Warn
+        );
+        assert!("pi_clock=loud".parse::<LevelFilters>().is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let entry = entry("Error updating weather");
+
+        assert_eq!(
+            entry.format(LogFormat::Text),
+            "2024-01-15 09:05:00.000 [WARN] pi_clock::weather: Error updating weather"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&entry.format(LogFormat::Json)).unwrap();
+        assert_eq!(json["level"], "WARN");
+        assert_eq!(json["target"], "pi_clock::weather");
+        assert_eq!(json["message"], "Error updating weather");
+        assert!(json["time"]
+            .as_str()
+            .unwrap()
+            .starts_with("2024-01-15T09:05:00.000"));
+    }
+
+    #[test]
+    fn test_journald_fields() {
+        assert_eq!(
+            journald_fields(&entry("a\nb")),
+            [
+                &b"PRIORITY=4\nSYSLOG_IDENTIFIER=pi_clock\nTARGET=pi_clock::weather\nMESSAGE\n"[..],
+                &3u64.to_le_bytes(),
+                b"a\nb\n",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_rotating_file() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("pi_clock_logs_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("clock.log");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2)?;
+        for line in ["one", "two", "three", "four", "five", "six"] {
+            file.write_line(line)?;
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name));
+        assert_eq!(read("clock.log")?, "six\n");
+        assert_eq!(read("clock.log.1")?, "four\nfive\n");
+        assert_eq!(read("clock.log.2")?, "three\n");
+        assert!(!dir.join("clock.log.3").exists());
+
+        fs::remove_dir_all(&dir)
+    }
+}
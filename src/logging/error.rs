@@ -0,0 +1,49 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Return the kind of this error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+}
+
+/// The kind of an error that can occur.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    Io(std::io::Error),
+
+    SetLogger(log::SetLoggerError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::Io(ref err) => write!(f, "failed to open log: {}", err),
+            ErrorKind::SetLogger(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error {
+            kind: ErrorKind::Io(e),
+        }
+    }
+}
+
+impl From<log::SetLoggerError> for Error {
+    fn from(e: log::SetLoggerError) -> Self {
+        Error {
+            kind: ErrorKind::SetLogger(e),
+        }
+    }
+}
@@ -1,142 +1,300 @@
-use log::{debug, info};
-use simplelog::{ColorChoice, ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use log::{debug, info, warn};
+use pi_clock::Display;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
+use std::{env, fs};
 use structopt::StructOpt;
 
-const CONSOLE_16X2_DISPLAY_TYPE: &str = "console-16x2";
-const CONSOLE_20X4_DISPLAY_TYPE: &str = "console-20x4";
-
-#[cfg(feature = "rpi-hw")]
-const LCD_16X2_DISPLAY_TYPE: &str = "lcd-16x2";
-#[cfg(feature = "rpi-hw")]
-const LCD_20X4_DISPLAY_TYPE: &str = "lcd-20x4";
-#[cfg(feature = "rpi-hw")]
-const ALPHANUM4_DISPLAY_TYPE: &str = "alphanum4";
-#[cfg(feature = "rpi-hw")]
-const SEVEN_SEGMENT_4_DISPLAY_TYPE: &str = "seven_segment4";
-
-const RANDOM_LIGHT_SENSOR_TYPE: &str = "random";
-const TIME_LIGHT_SENSOR_TYPE: &str = "time";
-#[cfg(feature = "rpi-hw")]
-const VEML7700_LIGHT_SENSOR_TYPE: &str = "veml7700";
-
-#[cfg(not(feature = "rpi-hw"))]
-const VALID_DISPLAY_TYPES: &[&str] = &[CONSOLE_16X2_DISPLAY_TYPE, CONSOLE_20X4_DISPLAY_TYPE];
-
-#[cfg(feature = "rpi-hw")]
-const VALID_DISPLAY_TYPES: &[&str] = &[
-    CONSOLE_16X2_DISPLAY_TYPE,
-    CONSOLE_20X4_DISPLAY_TYPE,
-    LCD_16X2_DISPLAY_TYPE,
-    LCD_20X4_DISPLAY_TYPE,
-    ALPHANUM4_DISPLAY_TYPE,
-    SEVEN_SEGMENT_4_DISPLAY_TYPE,
-];
+const TERMINAL_LOG_TARGET: &str = "terminal";
+const FILE_LOG_TARGET: &str = "file";
+const SYSLOG_LOG_TARGET: &str = "syslog";
+const JOURNALD_LOG_TARGET: &str = "journald";
 
-#[cfg(not(feature = "rpi-hw"))]
-const VALID_LIGHT_SENSOR_TYPES: &[&str] = &[RANDOM_LIGHT_SENSOR_TYPE, TIME_LIGHT_SENSOR_TYPE];
+// The environment variable that --mqtt-password can be given in, which is how the systemd service
+// gets it without it being in the unit file.
+const MQTT_PASSWORD_ENV: &str = "PI_CLOCK_MQTT_PASSWORD";
 
-#[cfg(feature = "rpi-hw")]
-const VALID_LIGHT_SENSOR_TYPES: &[&str] = &[
-    RANDOM_LIGHT_SENSOR_TYPE,
-    TIME_LIGHT_SENSOR_TYPE,
-    VEML7700_LIGHT_SENSOR_TYPE,
+const VALID_LOG_TARGETS: &[&str] = &[
+    TERMINAL_LOG_TARGET,
+    FILE_LOG_TARGET,
+    SYSLOG_LOG_TARGET,
+    JOURNALD_LOG_TARGET,
 ];
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let log_config = ConfigBuilder::new()
-        .set_time_offset_to_local()
-        .unwrap_or_else(|e| e)
-        .set_time_format_rfc2822()
-        // .set_time_format_str("%F %T") // i.e. '2020-02-27 15:12:02'
-        .build();
-    TermLogger::init(
-        LevelFilter::Warn,
-        log_config,
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )?;
-    debug!("logger initialized");
-
     let args = Cli::from_args();
 
-    let light_sensor_type_str = args.light_sensor_type;
-    let light_sensor = match light_sensor_type_str.as_str() {
-        RANDOM_LIGHT_SENSOR_TYPE => {
-            pi_clock::LightSensorType::Random(pi_clock::RandomLightSensor::new())
-        }
-        TIME_LIGHT_SENSOR_TYPE => pi_clock::LightSensorType::Time(pi_clock::TimeLightSensor::new()),
+    // With --tui, console displays are drawn side by side and redrawn in place, and web displays
+    // are all served on the same page
+    let display_options = pi_clock::DisplayOptions {
+        snapshot_dir: args.snapshot_dir.clone(),
+        web_bind: args.web_bind.clone(),
+        tui: args.tui,
+    };
+    // Only the clock itself draws the terminal UI, not its subcommands
+    let tui = args.command.is_none()
+        && args
+            .display_types
+            .iter()
+            .any(|spec| display_options.is_tui(spec));
+
+    pi_clock::init_logging(pi_clock::LogConfig {
+        levels: args.log_level.clone(),
+        format: args.log_format,
+        targets: args.log_targets(tui),
+    })?;
+    debug!("logger initialized");
 
-        #[cfg(feature = "rpi-hw")]
-        VEML7700_LIGHT_SENSOR_TYPE => {
-            pi_clock::LightSensorType::VEML7700(pi_clock::VEML7700LightSensor::new()?)
+    match &args.command {
+        Some(Subcommand::Install {
+            unit_path,
+            user,
+            watchdog_secs,
+        }) => {
+            // The service would fail on every start without the weather URI
+            required_uri(&args.uri);
+            return install(
+                &args,
+                unit_path,
+                user.as_deref(),
+                Duration::from_secs(*watchdog_secs),
+            );
         }
-        _ => {
-            panic!("Unrecognized light sensor type: {}", light_sensor_type_str)
+        Some(Subcommand::FetchWeather) => {
+            let report = pi_clock::WeatherReport::fetch(
+                &required_uri(&args.uri),
+                Duration::from_millis(args.weather_request_timeout_millis),
+            );
+            print!("{}", report);
+
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            return Ok(());
         }
+        Some(Subcommand::Render(render_args)) => return render(render_args),
+        None => {}
+    }
+
+    // Checked before any device is set up, since exiting here skips shutting them down
+    let uri = required_uri(&args.uri);
+
+    let light_sensor = pi_clock::LightSensorType::from_spec(&args.light_sensor_type)?;
+
+    let mut display = pi_clock::DisplayType::from_specs(&args.display_types, &display_options)?
+        .into_iter()
+        .collect::<pi_clock::CompositeDisplay>();
+    display.init()?;
+
+    // Keyboard controls come with the terminal UI, which leaves raw mode output readable
+    let (command_sender, commands) = mpsc::channel();
+    let _keyboard = if tui {
+        Some(pi_clock::Keyboard::spawn(command_sender.clone())?)
+    } else {
+        None
     };
 
-    let mut displays = args
-        .display_types
-        .iter()
-        .map(|d| -> Result<pi_clock::DisplayType, pi_clock::Error> {
-            match d.as_str() {
-                CONSOLE_16X2_DISPLAY_TYPE => Ok(pi_clock::DisplayType::Console16x2(
-                    pi_clock::Console16x2Display::new(),
-                )),
-
-                CONSOLE_20X4_DISPLAY_TYPE => Ok(pi_clock::DisplayType::Console20x4(
-                    pi_clock::Console20x4Display::new(),
-                )),
-
-                #[cfg(feature = "rpi-hw")]
-                LCD_16X2_DISPLAY_TYPE => Ok(pi_clock::DisplayType::LCD16x2(
-                    pi_clock::LCD16x2Display::new()?,
-                )),
-
-                #[cfg(feature = "rpi-hw")]
-                LCD_20X4_DISPLAY_TYPE => Ok(pi_clock::DisplayType::LCD20x4(
-                    pi_clock::LCD20x4Display::new()?,
-                )),
-
-                #[cfg(feature = "rpi-hw")]
-                ALPHANUM4_DISPLAY_TYPE => Ok(pi_clock::DisplayType::AlphaNum4(
-                    pi_clock::AlphaNum4Display::new()?,
-                )),
-
-                #[cfg(feature = "rpi-hw")]
-                SEVEN_SEGMENT_4_DISPLAY_TYPE => Ok(pi_clock::DisplayType::SevenSegment4(
-                    pi_clock::SevenSegment4Display::new()?,
-                )),
-                _ => {
-                    panic!("Unrecognized display type: {}", d)
-                }
-            }
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+    let status = Arc::new(Mutex::new(pi_clock::Status::default()));
+    let _http_server = match &args.http_bind {
+        Some(address) => Some(pi_clock::HttpServer::spawn(
+            address,
+            Arc::clone(&status),
+            command_sender.clone(),
+        )?),
+        None => None,
+    };
 
-    let mut display = pi_clock::DisplayType::Composite(displays.as_mut_slice());
+    let _mqtt_client = match args.mqtt_host {
+        Some(host) => Some(pi_clock::MqttClient::spawn(
+            &pi_clock::MqttConfig {
+                host,
+                port: args.mqtt_port,
+                client_id: args.mqtt_client_id,
+                topic_prefix: args.mqtt_topic_prefix,
+                credentials: args.mqtt_username.zip(args.mqtt_password),
+                discovery_prefix: if args.no_mqtt_discovery {
+                    None
+                } else {
+                    Some(args.mqtt_discovery_prefix)
+                },
+            },
+            Arc::clone(&status),
+            command_sender.clone(),
+        )),
+        None => None,
+    };
 
     info!("Initialization complete");
 
     let config = pi_clock::Config {
-        uri: args.uri,
+        uri,
         loop_sleep_duration: Duration::from_millis(args.loop_duration_millis),
         state_duration: Duration::from_secs(args.state_duration_secs),
+        scroll_ticks: args.scroll_ticks,
         weather_request_timeout: Duration::from_millis(args.weather_request_timeout_millis),
         weather_request_polling_interval: Duration::from_secs(
             args.weather_request_polling_interval_secs,
         ),
     };
 
-    pi_clock::run(&config, &mut display, &light_sensor)?;
+    let mut notifier = pi_clock::SystemdNotifier::from_env();
+    notifier.ready();
+
+    let result = pi_clock::run(
+        &config,
+        &mut display,
+        &light_sensor,
+        &commands,
+        &status,
+        &mut notifier,
+    );
+    notifier.stopping();
+    if let Err(e) = display.shutdown() {
+        warn!(
+            "Error shutting down {} display: {}",
+            e.display().unwrap_or(display.name()),
+            e
+        );
+    }
+    result?;
+
+    Ok(())
+}
+
+// Writes a systemd unit file that runs the clock with the flags given before `install`. The MQTT
+// password is written to an environment file beside it that only root can read.
+fn install(
+    args: &Cli,
+    unit_path: &std::path::Path,
+    user: Option<&str>,
+    watchdog: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = vec![env::current_exe()?.to_string_lossy().into_owned()];
+    command.extend(args.service_args());
+
+    let environment_path = unit_path
+        .with_extension("env")
+        .to_string_lossy()
+        .into_owned();
+    if let Some(password) = &args.mqtt_password {
+        write_private(
+            environment_path.as_ref(),
+            &pi_clock::SystemdUnit::render_environment(&[(MQTT_PASSWORD_ENV, password)]),
+        )?;
+    }
+
+    let working_directory = env::current_dir()?;
+    let unit = pi_clock::SystemdUnit {
+        command: &command,
+        working_directory: &working_directory.to_string_lossy(),
+        user,
+        watchdog,
+        environment_file: args
+            .mqtt_password
+            .as_ref()
+            .map(|_| environment_path.as_str()),
+    };
+    fs::write(unit_path, unit.render())?;
+
+    let service = unit_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    println!(
+        "Wrote {}. To start the clock now and on every boot, run:\n\n  \
+         sudo systemctl daemon-reload\n  \
+         sudo systemctl enable --now {}",
+        unit_path.display(),
+        service
+    );
+
+    Ok(())
+}
+
+// Writes a file that only its owner can read or write.
+fn write_private(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())
+}
+
+// The weather URI, for everything that needs one.
+fn required_uri(uri: &Option<String>) -> String {
+    uri.clone().unwrap_or_else(|| {
+        structopt::clap::Error::with_description(
+            "The following required arguments were not provided:\n    --uri <uri>",
+            structopt::clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    })
+}
+
+// Renders a single frame, to check a layout without running the clock.
+fn render(args: &RenderArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let weather = match &args.weather {
+        Some(path) => Some(pi_clock::parse_weather(&fs::read_to_string(path)?)?),
+        None => None,
+    };
+    let fetch_status = pi_clock::FetchStatus::default();
+    let ctx = pi_clock::RenderContext {
+        time: args.time.unwrap_or_else(Local::now),
+        current_state_index: args.state,
+        scroll_step: args.scroll_step,
+        weather: &weather,
+        light: args.light,
+        fetch_status: &fetch_status,
+        unit: if args.celsius {
+            pi_clock::TemperatureUnit::Celsius
+        } else {
+            pi_clock::TemperatureUnit::Fahrenheit
+        },
+        message: args
+            .message
+            .as_deref()
+            .map(|text| pi_clock::Message { text }),
+    };
+
+    let text = args.layout.frame(&ctx).boxed(args.layout.columns());
+    match &args.output {
+        Some(path) if path.extension().is_some_and(|ext| ext == "png") => {
+            pi_clock::SnapshotDisplay::new(args.layout, path).print(&ctx)?
+        }
+        Some(path) => fs::write(path, text)?,
+        None => print!("{}", text),
+    }
 
     Ok(())
 }
+
+// Parses a local time like 2024-01-15T09:05, with or without seconds.
+fn parse_local_time(s: &str) -> Result<DateTime<Local>, String> {
+    let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .map_err(|e| format!("{} (expected e.g. 2024-01-15T09:05)", e))?;
+
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", s))
+}
+
 #[derive(StructOpt)]
 struct Cli {
+    /// Where to get the weather from. Needed by everything but the render subcommand.
     #[structopt(long)]
-    uri: String,
+    uri: Option<String>,
 
     #[structopt(long, default_value = "500")]
     loop_duration_millis: u64,
@@ -150,9 +308,395 @@ struct Cli {
     #[structopt(long, default_value = "3")]
     state_duration_secs: u64,
 
-    #[structopt(long, possible_values(VALID_LIGHT_SENSOR_TYPES),default_value=RANDOM_LIGHT_SENSOR_TYPE)]
-    light_sensor_type: String,
+    /// Loop iterations that text scrolling across segment displays stays at each place
+    #[structopt(long, default_value = "1")]
+    scroll_ticks: u32,
+
+    /// random, time or veml7700
+    #[structopt(long, default_value = "random")]
+    light_sensor_type: pi_clock::LightSensorSpec,
+
+    /// Can be given more than once, e.g. console-20x4, snapshot-lcd-16x2, web-alphanum4 or
+    /// lcd-20x4. Segment displays at other I2C addresses are given like alphanum4@0x72.
+    #[structopt(
+        long = "display-type",
+        number_of_values = 1,
+        default_value = "console-16x2"
+    )]
+    display_types: Vec<pi_clock::DisplaySpec>,
+
+    /// Directory that snapshot display types write their PNG images to
+    #[structopt(long, default_value = ".", parse(from_os_str))]
+    snapshot_dir: PathBuf,
+
+    /// Draw console displays side by side, in place, instead of printing a new box every loop,
+    /// with keyboard controls
+    #[structopt(long)]
+    tui: bool,
+
+    /// Address to serve the HTTP status and control API on, e.g. 0.0.0.0:8080
+    #[structopt(long)]
+    http_bind: Option<String>,
+
+    /// Address to serve the page showing web-* display types on
+    #[structopt(long, default_value = "0.0.0.0:8081")]
+    web_bind: String,
+
+    /// MQTT broker to publish the temperature, conditions and light level to, and to take commands
+    /// from
+    #[structopt(long)]
+    mqtt_host: Option<String>,
+
+    #[structopt(long, default_value = "1883")]
+    mqtt_port: u16,
+
+    #[structopt(long, default_value = "pi_clock")]
+    mqtt_client_id: String,
+
+    /// Topics are published under this prefix, e.g. pi_clock/temperature
+    #[structopt(long, default_value = "pi_clock")]
+    mqtt_topic_prefix: String,
+
+    #[structopt(long)]
+    mqtt_username: Option<String>,
+
+    /// Written to an environment file rather than the unit file by install
+    #[structopt(long, env = MQTT_PASSWORD_ENV, hide_env_values = true)]
+    mqtt_password: Option<String>,
+
+    /// Where Home Assistant looks for MQTT discovery config
+    #[structopt(long, default_value = "homeassistant")]
+    mqtt_discovery_prefix: String,
+
+    /// Don't publish Home Assistant discovery config
+    #[structopt(long)]
+    no_mqtt_discovery: bool,
+
+    /// Log level, overall and for particular modules, e.g. warn,pi_clock::weather=debug,rumqttc=off
+    #[structopt(long, default_value = "warn")]
+    log_level: pi_clock::LevelFilters,
+
+    /// Where to write logs. Can be given more than once.
+    #[structopt(long = "log-target", number_of_values = 1, possible_values(VALID_LOG_TARGETS), default_value = TERMINAL_LOG_TARGET)]
+    log_targets: Vec<String>,
+
+    /// Log lines as text, or as JSON objects with time, level, target and message fields
+    #[structopt(long, possible_values(&["text", "json"]), default_value = "text")]
+    log_format: pi_clock::LogFormat,
+
+    /// The file logged to by the file log target
+    #[structopt(long, default_value = "pi_clock.log", parse(from_os_str))]
+    log_file: PathBuf,
+
+    /// Size at which the log file is rotated
+    #[structopt(long, default_value = "10485760")]
+    log_file_max_bytes: u64,
+
+    /// How many rotated log files to keep
+    #[structopt(long, default_value = "5")]
+    log_file_keep: usize,
+
+    #[structopt(subcommand)]
+    command: Option<Subcommand>,
+}
+
+impl Cli {
+    // Where to log. The terminal UI redraws the whole terminal, so while it's running, logs meant
+    // for the terminal go to the log file instead.
+    fn log_targets(&self, tui: bool) -> Vec<pi_clock::LogTarget> {
+        let mut targets = vec![];
+        for target in &self.log_targets {
+            let target = match target.as_str() {
+                SYSLOG_LOG_TARGET => pi_clock::LogTarget::Syslog,
+                JOURNALD_LOG_TARGET => pi_clock::LogTarget::Journald,
+                TERMINAL_LOG_TARGET if !tui => pi_clock::LogTarget::Terminal,
+                _ => pi_clock::LogTarget::File {
+                    path: self.log_file.clone(),
+                    max_bytes: self.log_file_max_bytes,
+                    keep: self.log_file_keep,
+                },
+            };
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        targets
+    }
+
+    // The flags that run the clock as these do, leaving out those at their defaults, and the MQTT
+    // password, which install passes in the environment instead.
+    fn service_args(&self) -> Vec<String> {
+        let Cli {
+            uri,
+            loop_duration_millis,
+            weather_request_polling_interval_secs,
+            weather_request_timeout_millis,
+            state_duration_secs,
+            scroll_ticks,
+            light_sensor_type,
+            display_types,
+            snapshot_dir,
+            tui,
+            http_bind,
+            web_bind,
+            mqtt_host,
+            mqtt_port,
+            mqtt_client_id,
+            mqtt_topic_prefix,
+            mqtt_username,
+            mqtt_password: _,
+            mqtt_discovery_prefix,
+            no_mqtt_discovery,
+            log_level,
+            log_targets,
+            log_format,
+            log_file,
+            log_file_max_bytes,
+            log_file_keep,
+            command: _,
+        } = self;
+        let defaults = Cli::from_iter(["pi_clock"]);
+
+        fn values<'a, T: ToString + 'a>(values: impl IntoIterator<Item = &'a T>) -> Vec<String> {
+            values.into_iter().map(ToString::to_string).collect()
+        }
+        let path = |path: &PathBuf| vec![path.to_string_lossy().into_owned()];
+        let flags = [
+            ("uri", values(uri), values(&defaults.uri)),
+            (
+                "loop-duration-millis",
+                values([loop_duration_millis]),
+                values([&defaults.loop_duration_millis]),
+            ),
+            (
+                "weather-request-polling-interval-secs",
+                values([weather_request_polling_interval_secs]),
+                values([&defaults.weather_request_polling_interval_secs]),
+            ),
+            (
+                "weather-request-timeout-millis",
+                values([weather_request_timeout_millis]),
+                values([&defaults.weather_request_timeout_millis]),
+            ),
+            (
+                "state-duration-secs",
+                values([state_duration_secs]),
+                values([&defaults.state_duration_secs]),
+            ),
+            (
+                "scroll-ticks",
+                values([scroll_ticks]),
+                values([&defaults.scroll_ticks]),
+            ),
+            (
+                "light-sensor-type",
+                values([light_sensor_type]),
+                values([&defaults.light_sensor_type]),
+            ),
+            (
+                "display-type",
+                values(display_types),
+                values(&defaults.display_types),
+            ),
+            (
+                "snapshot-dir",
+                path(snapshot_dir),
+                path(&defaults.snapshot_dir),
+            ),
+            ("http-bind", values(http_bind), values(&defaults.http_bind)),
+            ("web-bind", values([web_bind]), values([&defaults.web_bind])),
+            ("mqtt-host", values(mqtt_host), values(&defaults.mqtt_host)),
+            (
+                "mqtt-port",
+                values([mqtt_port]),
+                values([&defaults.mqtt_port]),
+            ),
+            (
+                "mqtt-client-id",
+                values([mqtt_client_id]),
+                values([&defaults.mqtt_client_id]),
+            ),
+            (
+                "mqtt-topic-prefix",
+                values([mqtt_topic_prefix]),
+                values([&defaults.mqtt_topic_prefix]),
+            ),
+            (
+                "mqtt-username",
+                values(mqtt_username),
+                values(&defaults.mqtt_username),
+            ),
+            (
+                "mqtt-discovery-prefix",
+                values([mqtt_discovery_prefix]),
+                values([&defaults.mqtt_discovery_prefix]),
+            ),
+            (
+                "log-level",
+                values([log_level]),
+                values([&defaults.log_level]),
+            ),
+            (
+                "log-target",
+                values(log_targets),
+                values(&defaults.log_targets),
+            ),
+            (
+                "log-format",
+                values([log_format]),
+                values([&defaults.log_format]),
+            ),
+            ("log-file", path(log_file), path(&defaults.log_file)),
+            (
+                "log-file-max-bytes",
+                values([log_file_max_bytes]),
+                values([&defaults.log_file_max_bytes]),
+            ),
+            (
+                "log-file-keep",
+                values([log_file_keep]),
+                values([&defaults.log_file_keep]),
+            ),
+        ];
+
+        let mut args = vec![];
+        for (name, values, defaults) in flags {
+            if values != defaults {
+                args.extend(values.iter().map(|value| format!("--{}={}", name, value)));
+            }
+        }
+        for (name, set) in [("tui", *tui), ("no-mqtt-discovery", *no_mqtt_discovery)] {
+            if set {
+                args.push(format!("--{}", name));
+            }
+        }
+        args
+    }
+}
+
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Writes a systemd unit file that runs the clock with the flags given before `install`, e.g.
+    /// `pi_clock --uri=... --display-type=lcd-20x4 install`
+    Install {
+        #[structopt(
+            long,
+            default_value = "/etc/systemd/system/pi_clock.service",
+            parse(from_os_str)
+        )]
+        unit_path: PathBuf,
+
+        /// User to run the clock as, e.g. one in the gpio and i2c groups
+        #[structopt(long)]
+        user: Option<String>,
+
+        /// How long the main loop can hang before systemd restarts the clock
+        #[structopt(long, default_value = "30")]
+        watchdog_secs: u64,
+    },
+
+    /// Prints what a display would show, without running the clock
+    Render(RenderArgs),
+
+    /// Requests the weather from --uri once and prints what the clock would make of it, exiting
+    /// with an error if the clock wouldn't show it
+    FetchWeather,
+}
+
+#[derive(StructOpt)]
+struct RenderArgs {
+    #[structopt(long, possible_values(&["16x2", "20x4", "alphanum4", "seven_segment4"]), default_value = "20x4")]
+    layout: pi_clock::Layout,
+
+    /// Local time to show, e.g. 2024-01-15T09:05. Defaults to now.
+    #[structopt(long, parse(try_from_str = parse_local_time))]
+    time: Option<DateTime<Local>>,
+
+    /// Which step of the screen rotation to show
+    #[structopt(long, default_value = "0")]
+    state: u32,
+
+    /// How many places text too long to show at once has scrolled
+    #[structopt(long, default_value = "0")]
+    scroll_step: u32,
+
+    /// A forecast in the format --uri returns, e.g. saved with curl. No weather if not given.
+    #[structopt(long, parse(from_os_str))]
+    weather: Option<PathBuf>,
+
+    /// Light level between 0 and 1, which dims PNG output
+    #[structopt(long, default_value = "1.0")]
+    light: f32,
+
+    #[structopt(long)]
+    celsius: bool,
+
+    /// A message to show in place of the rotating rows
+    #[structopt(long)]
+    message: Option<String>,
+
+    /// File to write to instead of printing, as a PNG if it ends in .png and as text otherwise
+    #[structopt(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_args() {
+        let args = Cli::from_iter([
+            "pi_clock",
+            "--uri=http://cache.local",
+            "--mqtt-host",
+            "install",
+            "--mqtt-password=hunter2",
+            "--display-type=lcd-20x4",
+            "--display-type",
+            "alphanum4@0x72,screens=high+low",
+            "--log-level=info,rumqttc=off",
+            "--tui",
+            "install",
+            "--user=pi",
+        ]);
+
+        assert_eq!(
+            args.service_args(),
+            vec![
+                "--uri=http://cache.local",
+                "--display-type=lcd-20x4",
+                "--display-type=alphanum4@address=0x72,screens=high+low",
+                "--mqtt-host=install",
+                "--log-level=info,rumqttc=off",
+                "--tui",
+            ]
+        );
+        assert!(Cli::from_iter(["pi_clock"]).service_args().is_empty());
+    }
+
+    #[test]
+    fn test_log_targets() {
+        let file = pi_clock::LogTarget::File {
+            path: PathBuf::from("pi_clock.log"),
+            max_bytes: 10_485_760,
+            keep: 5,
+        };
+
+        let args = Cli::from_iter(["pi_clock"]);
+        assert_eq!(args.log_targets(false), vec![pi_clock::LogTarget::Terminal]);
+        assert_eq!(args.log_targets(true), vec![file.clone()]);
 
-    #[structopt(long = "display-type", possible_values(VALID_DISPLAY_TYPES),default_value=CONSOLE_16X2_DISPLAY_TYPE)]
-    display_types: Vec<String>,
+        // Logs for the terminal and the file both go to the file, once
+        let args = Cli::from_iter([
+            "pi_clock",
+            "--log-target=terminal",
+            "--log-target=journald",
+            "--log-target=file",
+        ]);
+        assert_eq!(
+            args.log_targets(true),
+            vec![file, pi_clock::LogTarget::Journald]
+        );
+    }
 }
@@ -0,0 +1,265 @@
+// Counters and gauges served in the Prometheus text format on the HTTP server's /metrics.
+
+use crate::weather::{self, OpenWeather};
+
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    weather_fetch_attempts: u64,
+    weather_fetch_successes: u64,
+    // Keyed by the name of the weather::ErrorKind
+    weather_fetch_failures: BTreeMap<&'static str, u64>,
+    weather_fetch_duration: Summary,
+
+    // When the most recently fetched forecast was made, in seconds since the epoch
+    forecast_timestamp: Option<i64>,
+
+    light_normalized: f32,
+    light_lux: Option<f32>,
+    light_sensor_errors: u64,
+
+    loop_duration: Summary,
+
+    // Keyed by the label of the display, which tells apart displays of the same kind
+    display_errors: BTreeMap<String, u64>,
+}
+
+// The sum and count of observations, from which Prometheus can calculate averages over time.
+#[derive(Clone, Copy, Debug, Default)]
+struct Summary {
+    sum: f64,
+    count: u64,
+}
+
+impl Summary {
+    fn observe(&mut self, duration: Duration) {
+        self.sum += duration.as_secs_f64();
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn record_fetch(
+        &mut self,
+        duration: Duration,
+        result: &Result<OpenWeather, weather::Error>,
+    ) {
+        self.weather_fetch_attempts += 1;
+        self.weather_fetch_duration.observe(duration);
+
+        match result {
+            Ok(w) => {
+                self.weather_fetch_successes += 1;
+                self.forecast_timestamp = Some(w.current.dt);
+            }
+            Err(e) => {
+                *self
+                    .weather_fetch_failures
+                    .entry(e.kind().name())
+                    .or_default() += 1
+            }
+        }
+    }
+
+    pub fn record_light(&mut self, normalized: f32, lux: Option<f32>) {
+        self.light_normalized = normalized;
+        self.light_lux = lux;
+    }
+
+    pub fn record_light_error(&mut self) {
+        self.light_sensor_errors += 1;
+    }
+
+    // Records how long an iteration of the main loop took, not including its sleep.
+    pub fn record_loop(&mut self, duration: Duration) {
+        self.loop_duration.observe(duration);
+    }
+
+    pub fn record_display_error(&mut self, display: &str) {
+        *self.display_errors.entry(display.to_string()).or_default() += 1;
+    }
+
+    pub fn render(&self, now: &DateTime<Local>) -> String {
+        let mut out = String::new();
+
+        write_metric(
+            &mut out,
+            "pi_clock_weather_fetch_attempts_total",
+            "counter",
+            "Attempts to fetch the weather.",
+            &[("", self.weather_fetch_attempts as f64)],
+        );
+        write_metric(
+            &mut out,
+            "pi_clock_weather_fetch_successes_total",
+            "counter",
+            "Successful fetches of the weather.",
+            &[("", self.weather_fetch_successes as f64)],
+        );
+        write_metric(
+            &mut out,
+            "pi_clock_weather_fetch_failures_total",
+            "counter",
+            "Failed fetches of the weather, by kind of error.",
+            &labelled("kind", &self.weather_fetch_failures),
+        );
+        write_summary(
+            &mut out,
+            "pi_clock_weather_fetch_duration_seconds",
+            "Time taken to fetch the weather.",
+            &self.weather_fetch_duration,
+        );
+        if let Some(timestamp) = self.forecast_timestamp {
+            write_metric(
+                &mut out,
+                "pi_clock_forecast_age_seconds",
+                "gauge",
+                "Age of the most recently fetched forecast.",
+                &[("", (now.timestamp() - timestamp) as f64)],
+            );
+        }
+
+        write_metric(
+            &mut out,
+            "pi_clock_light_normalized",
+            "gauge",
+            "Light level the displays are dimmed to, between 0 and 1.",
+            &[("", self.light_normalized as f64)],
+        );
+        if let Some(lux) = self.light_lux {
+            write_metric(
+                &mut out,
+                "pi_clock_light_lux",
+                "gauge",
+                "Raw light sensor reading.",
+                &[("", lux as f64)],
+            );
+        }
+        write_metric(
+            &mut out,
+            "pi_clock_light_sensor_errors_total",
+            "counter",
+            "Errors reading the light sensor.",
+            &[("", self.light_sensor_errors as f64)],
+        );
+
+        write_summary(
+            &mut out,
+            "pi_clock_loop_duration_seconds",
+            "Time taken by each iteration of the main loop, not including sleeping.",
+            &self.loop_duration,
+        );
+        write_metric(
+            &mut out,
+            "pi_clock_display_errors_total",
+            "counter",
+            "Errors printing to displays, by display.",
+            &labelled("display", &self.display_errors),
+        );
+
+        out
+    }
+}
+
+fn labelled<K: AsRef<str>>(label: &str, counts: &BTreeMap<K, u64>) -> Vec<(String, f64)> {
+    counts
+        .iter()
+        .map(|(value, count)| {
+            let value = escape_label_value(value.as_ref());
+            (format!("{{{}=\"{}\"}}", label, value), *count as f64)
+        })
+        .collect()
+}
+
+// Label values can be anything, e.g. the path of a snapshot, so quotes need escaping.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_metric<L: AsRef<str>>(
+    out: &mut String,
+    name: &str,
+    metric_type: &str,
+    help: &str,
+    samples: &[(L, f64)],
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels.as_ref(), value);
+    }
+}
+
+fn write_summary(out: &mut String, name: &str, help: &str, summary: &Summary) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    let _ = writeln!(out, "{}_sum {}", name, summary.sum);
+    let _ = writeln!(out, "{}_count {}", name, summary.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render() {
+        let now = Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap();
+        let mut metrics = Metrics::default();
+
+        let mut weather = OpenWeather::default();
+        weather.current.dt = now.timestamp() - 90;
+        metrics.record_fetch(Duration::from_millis(250), &Ok(weather));
+        let parse_error = serde_json::from_str::<OpenWeather>("").unwrap_err();
+        metrics.record_fetch(Duration::from_millis(500), &Err(parse_error.into()));
+        metrics.record_light(0.5, None);
+        metrics.record_loop(Duration::from_millis(20));
+        metrics.record_display_error("alphanum4@bus=1,address=0x71");
+        metrics.record_display_error("alphanum4@bus=1,address=0x72");
+        metrics.record_display_error("snapshot@/tmp/\"quoted\".png");
+
+        assert_eq!(
+            metrics.render(&now),
+            "\
+# HELP pi_clock_weather_fetch_attempts_total Attempts to fetch the weather.
+# TYPE pi_clock_weather_fetch_attempts_total counter
+pi_clock_weather_fetch_attempts_total 2
+# HELP pi_clock_weather_fetch_successes_total Successful fetches of the weather.
+# TYPE pi_clock_weather_fetch_successes_total counter
+pi_clock_weather_fetch_successes_total 1
+# HELP pi_clock_weather_fetch_failures_total Failed fetches of the weather, by kind of error.
+# TYPE pi_clock_weather_fetch_failures_total counter
+pi_clock_weather_fetch_failures_total{kind=\"json_parse\"} 1
+# HELP pi_clock_weather_fetch_duration_seconds Time taken to fetch the weather.
+# TYPE pi_clock_weather_fetch_duration_seconds summary
+pi_clock_weather_fetch_duration_seconds_sum 0.75
+pi_clock_weather_fetch_duration_seconds_count 2
+# HELP pi_clock_forecast_age_seconds Age of the most recently fetched forecast.
+# TYPE pi_clock_forecast_age_seconds gauge
+pi_clock_forecast_age_seconds 90
+# HELP pi_clock_light_normalized Light level the displays are dimmed to, between 0 and 1.
+# TYPE pi_clock_light_normalized gauge
+pi_clock_light_normalized 0.5
+# HELP pi_clock_light_sensor_errors_total Errors reading the light sensor.
+# TYPE pi_clock_light_sensor_errors_total counter
+pi_clock_light_sensor_errors_total 0
+# HELP pi_clock_loop_duration_seconds Time taken by each iteration of the main loop, not including sleeping.
+# TYPE pi_clock_loop_duration_seconds summary
+pi_clock_loop_duration_seconds_sum 0.02
+pi_clock_loop_duration_seconds_count 1
+# HELP pi_clock_display_errors_total Errors printing to displays, by display.
+# TYPE pi_clock_display_errors_total counter
+pi_clock_display_errors_total{display=\"alphanum4@bus=1,address=0x71\"} 1
+pi_clock_display_errors_total{display=\"alphanum4@bus=1,address=0x72\"} 1
+pi_clock_display_errors_total{display=\"snapshot@/tmp/\\\"quoted\\\".png\"} 1
+"
+        );
+    }
+}
@@ -0,0 +1,533 @@
+// Publishes what the clock knows to an MQTT broker, for home automation:
+//
+//   {prefix}/status         "online", or "offline" once the clock disconnects (its last will)
+//   {prefix}/temperature    the current temperature, in °F
+//   {prefix}/conditions     e.g. "Rain"
+//   {prefix}/precipitation  {"change": "start", "precipitation": "Rain", "at": "2024-01-15T10:00:00+00:00"}
+//   {prefix}/high           {"temperature": 45.2, "at": "2024-01-15T14:00:00+00:00"}
+//   {prefix}/low            {"temperature": 31.0, "at": "2024-01-16T06:00:00+00:00"}
+//   {prefix}/light          the light level the displays are dimmed to, between 0 and 1
+//   {prefix}/display        "OFF" while the displays are dimmed fully by a command, otherwise "ON"
+//   {prefix}/lux            the raw light sensor reading, for sensors that measure lux
+//   {prefix}/forecast_age   minutes since the forecast was made
+//
+// Messages are retained, so subscribers get the latest values as soon as they connect, and are
+// only published when they change.
+//
+// Other systems control the clock by publishing to command topics, with the same payloads as the
+// HTTP API:
+//
+//   {prefix}/command/refresh     fetch the weather now
+//   {prefix}/command/brightness  {"brightness": 0.5} overrides the light sensor, {"brightness": null} clears it
+//   {prefix}/command/message     {"text": "Laundry done", "seconds": 30} shows a message
+//
+// and, for Home Assistant's light entity:
+//
+//   {prefix}/command/light             "OFF" dims the displays fully, "ON" follows the light sensor
+//   {prefix}/command/light_brightness  0 to 255 overrides the light sensor
+
+mod home_assistant;
+#[cfg(test)]
+pub(crate) mod test_broker;
+
+use crate::command::Command;
+use crate::status::Status;
+use crate::weather::{self, OpenWeather, PrecipitationChange};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+// How often the status is checked for changes.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+// How long to wait before trying to reconnect to the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// How long to wait for "offline" to be sent when shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Publishes and acknowledgements waiting to be sent to the broker.
+const REQUEST_CAPACITY: usize = 32;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How to connect to the broker and what to publish under.
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub topic_prefix: String,
+    pub credentials: Option<(String, String)>,
+
+    /// Where Home Assistant looks for discovery config, usually "homeassistant", or None to not
+    /// publish any.
+    pub discovery_prefix: Option<String>,
+}
+
+/// Publishes to the broker on background threads until dropped.
+pub struct MqttClient {
+    client: Client,
+    status_topic: String,
+
+    // Disconnected once the connection thread has finished
+    stopped: Receiver<()>,
+}
+
+impl MqttClient {
+    /// Connects to the broker, publishing the clock's status and sending on the commands it
+    /// receives.
+    pub fn spawn(
+        config: &MqttConfig,
+        status: Arc<Mutex<Status>>,
+        commands: Sender<Command>,
+    ) -> Self {
+        let status_topic = topic(&config.topic_prefix, "status");
+        let command_topic = topic(&config.topic_prefix, "command/");
+        let discovery = match &config.discovery_prefix {
+            Some(discovery_prefix) => home_assistant::discovery_messages(
+                discovery_prefix,
+                &config.topic_prefix,
+                &config.client_id,
+            ),
+            None => Vec::new(),
+        };
+
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            &status_topic,
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some((username, password)) = &config.credentials {
+            options.set_credentials(username, password);
+        }
+
+        let (client, mut connection) = Client::new(options, REQUEST_CAPACITY);
+
+        // Set on each reconnection, in case the broker has lost its retained messages
+        let republish = Arc::new(AtomicBool::new(false));
+
+        let (stopped_sender, stopped) = mpsc::channel();
+        {
+            let client = client.clone();
+            let status_topic = status_topic.clone();
+            let republish = Arc::clone(&republish);
+            let address = format!("{}:{}", config.host, config.port);
+            thread::spawn(move || {
+                let _stopped_sender = stopped_sender;
+                let mut connected = false;
+                let mut connected_before = false;
+
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            info!("Connected to MQTT broker at {}", address);
+                            connected = true;
+                            if connected_before {
+                                republish.store(true, Ordering::Relaxed);
+                            }
+                            connected_before = true;
+
+                            // Subscriptions don't outlast the session, so are made on every connection
+                            let subscribed = client
+                                .try_subscribe(format!("{}+", command_topic), QoS::AtLeastOnce);
+                            let published =
+                                client.try_publish(&status_topic, QoS::AtLeastOnce, true, ONLINE);
+                            if subscribed.is_err() || published.is_err() {
+                                return;
+                            }
+
+                            // In case Home Assistant's broker has lost its retained messages
+                            for (topic, payload) in &discovery {
+                                if client
+                                    .try_publish(topic, QoS::AtLeastOnce, true, payload.clone())
+                                    .is_err()
+                                {
+                                    return;
+                                }
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            let Some(name) = publish.topic.strip_prefix(&command_topic) else {
+                                continue;
+                            };
+
+                            // A retained command would be carried out again on every restart
+                            if publish.retain {
+                                warn!("Ignoring retained MQTT command on {}", publish.topic);
+                                continue;
+                            }
+
+                            match parse_command(name, &publish.payload) {
+                                Ok(command) => {
+                                    let _ = commands.send(command);
+                                }
+                                Err(e) => {
+                                    warn!("Invalid MQTT command on {}: {}", publish.topic, e)
+                                }
+                            }
+                        }
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                        Ok(_) => {}
+                        Err(e) => {
+                            if connected {
+                                warn!("Lost connection to MQTT broker at {}: {}", address, e);
+                            } else {
+                                warn!("Error connecting to MQTT broker at {}: {}", address, e);
+                            }
+                            connected = false;
+                            thread::sleep(RECONNECT_DELAY);
+                        }
+                    }
+                }
+            });
+        }
+
+        {
+            let client = client.clone();
+            let prefix = config.topic_prefix.clone();
+            thread::spawn(move || {
+                let mut published = HashMap::new();
+
+                loop {
+                    if republish.swap(false, Ordering::Relaxed) {
+                        published.clear();
+                    }
+
+                    let messages = {
+                        let status = status.lock().unwrap_or_else(PoisonError::into_inner);
+                        state_messages(&status, &Local::now())
+                    };
+
+                    for (name, payload) in messages {
+                        if published.get(name) == Some(&payload) {
+                            continue;
+                        }
+
+                        if client
+                            .publish(
+                                topic(&prefix, name),
+                                QoS::AtLeastOnce,
+                                true,
+                                payload.clone(),
+                            )
+                            .is_err()
+                        {
+                            // The client has been disconnected
+                            return;
+                        }
+                        published.insert(name, payload);
+                    }
+
+                    thread::sleep(PUBLISH_INTERVAL);
+                }
+            });
+        }
+
+        MqttClient {
+            client,
+            status_topic,
+            stopped,
+        }
+    }
+}
+
+impl Drop for MqttClient {
+    // A clean disconnect doesn't trigger the last will, so "offline" is published first.
+    fn drop(&mut self) {
+        let _ = self
+            .client
+            .try_publish(&self.status_topic, QoS::AtLeastOnce, true, OFFLINE);
+        let _ = self.client.try_disconnect();
+        let _ = self.stopped.recv_timeout(SHUTDOWN_TIMEOUT);
+    }
+}
+
+fn topic(prefix: &str, name: &str) -> String {
+    format!("{}/{}", prefix, name)
+}
+
+// Parses the payload of a message to a command topic, named without the prefix.
+fn parse_command(name: &str, payload: &[u8]) -> Result<Command, String> {
+    let payload = std::str::from_utf8(payload).map_err(|e| e.to_string())?;
+
+    match name {
+        "refresh" => Ok(Command::RefreshWeather),
+        "brightness" => Command::from_brightness_json(payload),
+        "message" => Command::from_message_json(payload),
+        "light" => match payload {
+            "ON" => Ok(Command::SetBrightness(None)),
+            "OFF" => Ok(Command::SetBrightness(Some(0.0))),
+            _ => Err(format!("expected ON or OFF, got {:?}", payload)),
+        },
+        "light_brightness" => match payload.trim().parse::<u8>() {
+            Ok(brightness) => Ok(Command::SetBrightness(Some(brightness as f32 / 255.0))),
+            Err(e) => Err(e.to_string()),
+        },
+        _ => Err(format!("unknown command {:?}", name)),
+    }
+}
+
+// The payload of each topic, other than status, that there's currently a value for.
+fn state_messages(status: &Status, now: &DateTime<Local>) -> Vec<(&'static str, String)> {
+    let mut messages = Vec::new();
+
+    if let Some(w) = &status.weather {
+        messages.extend(weather_messages(w, now));
+    }
+
+    // Rounded so that small changes in the light don't flood the broker
+    messages.push(("light", format!("{:.2}", status.light)));
+    let display = if status.brightness_override == Some(0.0) {
+        "OFF"
+    } else {
+        "ON"
+    };
+    messages.push(("display", display.to_string()));
+    if let Some(lux) = status.lux {
+        messages.push(("lux", format!("{:.0}", lux)));
+    }
+
+    messages
+}
+
+fn weather_messages(w: &OpenWeather, now: &DateTime<Local>) -> Vec<(&'static str, String)> {
+    let mut messages = vec![
+        ("temperature", format!("{:.1}", w.current.temp)),
+        (
+            "forecast_age",
+            ((now.timestamp() - w.current.dt) / 60).to_string(),
+        ),
+    ];
+
+    if let Some(conditions) = w.current.weather.first() {
+        messages.push(("conditions", conditions.main.to_string()));
+
+        let (change, precipitation, at) = match weather::next_precipitation_change(w, now) {
+            PrecipitationChange::Start(at, p) => ("start", Some(p), Some(at)),
+            PrecipitationChange::Stop(at, p) => ("stop", Some(p), Some(at)),
+            PrecipitationChange::NoChange(p) => ("none", p, None),
+        };
+        messages.push((
+            "precipitation",
+            json!({
+                "change": change,
+                "precipitation": precipitation.map(|p| p.to_string()),
+                "at": at.map(|t| t.to_rfc3339()),
+            })
+            .to_string(),
+        ));
+    }
+
+    if !w.hourly.is_empty() {
+        let (high, low) = weather::high_low_temp(w, now);
+        for (name, (at, temperature)) in [("high", high), ("low", low)] {
+            messages.push((
+                name,
+                json!({ "temperature": temperature, "at": at.to_rfc3339() }).to_string(),
+            ));
+        }
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::open_weather_types::{Main, Weather};
+    use chrono::TimeZone;
+    use test_broker::TestBroker;
+
+    fn weather(now: &DateTime<Local>) -> OpenWeather {
+        let mut w = OpenWeather::default();
+        w.current.temp = 38.25;
+        w.current.dt = now.timestamp() - 600;
+        w.current.weather = vec![Weather {
+            main: Main::Clear,
+            ..Default::default()
+        }];
+
+        w.hourly = vec![Default::default(), Default::default()];
+        w.hourly[0].dt = (*now + chrono::TimeDelta::try_hours(1).unwrap()).timestamp();
+        w.hourly[0].temp = 40.0;
+        w.hourly[0].weather = w.current.weather.clone();
+        w.hourly[1].dt = (*now + chrono::TimeDelta::try_hours(2).unwrap()).timestamp();
+        w.hourly[1].temp = 36.5;
+        w.hourly[1].weather = vec![Weather {
+            main: Main::Rain,
+            ..Default::default()
+        }];
+        w
+    }
+
+    #[test]
+    fn test_state_messages() {
+        let now = Local.with_ymd_and_hms(2024, 1, 15, 9, 0, 0).unwrap();
+        let mut status = Status {
+            light: 0.123,
+            lux: Some(41.6),
+            ..Default::default()
+        };
+        assert_eq!(
+            state_messages(&status, &now),
+            vec![
+                ("light", "0.12".to_string()),
+                ("display", "ON".to_string()),
+                ("lux", "42".to_string())
+            ]
+        );
+
+        status.weather = Some(weather(&now));
+        let at = |hours| (now + chrono::TimeDelta::try_hours(hours).unwrap()).to_rfc3339();
+        assert_eq!(
+            state_messages(&status, &now),
+            vec![
+                ("temperature", "38.2".to_string()),
+                ("forecast_age", "10".to_string()),
+                ("conditions", "Clear".to_string()),
+                (
+                    "precipitation",
+                    json!({ "change": "start", "precipitation": "Rain", "at": at(2) }).to_string()
+                ),
+                (
+                    "high",
+                    json!({ "temperature": 40.0, "at": at(1) }).to_string()
+                ),
+                (
+                    "low",
+                    json!({ "temperature": 36.5, "at": at(2) }).to_string()
+                ),
+                ("light", "0.12".to_string()),
+                ("display", "ON".to_string()),
+                ("lux", "42".to_string()),
+            ]
+        );
+
+        // The display is only off when it's been turned off, not when it's dark
+        status.weather = None;
+        status.light = 0.0;
+        assert!(state_messages(&status, &now).contains(&("display", "ON".to_string())));
+        status.brightness_override = Some(0.0);
+        assert!(state_messages(&status, &now).contains(&("display", "OFF".to_string())));
+    }
+
+    fn spawn_client(
+        broker: &TestBroker,
+        status: Arc<Mutex<Status>>,
+        commands: Sender<Command>,
+    ) -> MqttClient {
+        MqttClient::spawn(
+            &MqttConfig {
+                host: "127.0.0.1".to_string(),
+                port: broker.port(),
+                client_id: "pi_clock_test".to_string(),
+                topic_prefix: "clock".to_string(),
+                credentials: None,
+                discovery_prefix: Some("homeassistant".to_string()),
+            },
+            status,
+            commands,
+        )
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("refresh", b""), Ok(Command::RefreshWeather));
+        assert_eq!(
+            parse_command("brightness", br#"{"brightness": null}"#),
+            Ok(Command::SetBrightness(None))
+        );
+        assert_eq!(
+            parse_command("message", br#"{"text": "Laundry done", "seconds": 30}"#),
+            Ok(Command::ShowMessage(
+                "Laundry done".to_string(),
+                Duration::from_secs(30)
+            ))
+        );
+        assert!(parse_command("brightness", br#"{"brightness": 2}"#).is_err());
+        assert!(parse_command("message", b"Laundry done").is_err());
+        assert_eq!(
+            parse_command("light", b"OFF"),
+            Ok(Command::SetBrightness(Some(0.0)))
+        );
+        assert_eq!(
+            parse_command("light_brightness", b"51"),
+            Ok(Command::SetBrightness(Some(0.2)))
+        );
+        assert!(parse_command("light_brightness", b"256").is_err());
+        assert!(parse_command("reboot", b"").is_err());
+    }
+
+    #[test]
+    fn test_receives_commands() {
+        let broker = TestBroker::spawn();
+        let (sender, commands) = mpsc::channel();
+        let _client = spawn_client(&broker, Arc::default(), sender);
+
+        broker.wait_for_subscribe("clock/command/+");
+        broker.publish("clock/command/brightness", r#"{"brightness": 0.25}"#);
+        broker.publish("clock/command/reboot", "");
+        broker.publish("clock/command/refresh", "");
+
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            commands.recv_timeout(timeout),
+            Ok(Command::SetBrightness(Some(0.25)))
+        );
+        assert_eq!(commands.recv_timeout(timeout), Ok(Command::RefreshWeather));
+    }
+
+    #[test]
+    fn test_publishes_to_broker() {
+        let broker = TestBroker::spawn();
+        let status = Arc::new(Mutex::new(Status {
+            weather: Some(weather(&Local::now())),
+            light: 0.5,
+            ..Default::default()
+        }));
+
+        let (sender, _commands) = mpsc::channel();
+        let client = spawn_client(&broker, Arc::clone(&status), sender);
+
+        let will = broker.wait_for_connect();
+        assert_eq!(
+            will,
+            Some(("clock/status".to_string(), OFFLINE.to_string()))
+        );
+        assert!(broker.wait_for_publish("clock/status", ONLINE).retain);
+        assert!(
+            broker
+                .wait_for_topic("homeassistant/sensor/pi_clock_test/temperature/config")
+                .retain
+        );
+        assert!(broker.wait_for_publish("clock/temperature", "38.2").retain);
+        broker.wait_for_publish("clock/light", "0.50");
+
+        // Only changes are published again
+        status.lock().unwrap().light = 0.75;
+        broker.wait_for_publish("clock/light", "0.75");
+        let temperatures = broker
+            .published()
+            .iter()
+            .filter(|p| p.topic == "clock/temperature")
+            .count();
+        assert_eq!(temperatures, 1);
+
+        drop(client);
+        broker.wait_for_publish("clock/status", OFFLINE);
+    }
+}
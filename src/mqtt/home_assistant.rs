@@ -0,0 +1,191 @@
+// Home Assistant MQTT discovery: retained config messages that make the clock show up as a device
+// with entities for the topics it publishes and the commands it takes.
+// See https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
+
+use super::{topic, OFFLINE, ONLINE};
+
+use serde_json::{json, Value};
+
+// The topic and payload of the config of each entity.
+pub(super) fn discovery_messages(
+    discovery_prefix: &str,
+    topic_prefix: &str,
+    client_id: &str,
+) -> Vec<(String, String)> {
+    let device = json!({
+        "identifiers": [client_id],
+        "name": "Pi Clock",
+        "model": "pi_clock",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let entities = vec![
+        (
+            "sensor",
+            "temperature",
+            json!({
+                "name": "Temperature",
+                "state_topic": topic(topic_prefix, "temperature"),
+                "device_class": "temperature",
+                "state_class": "measurement",
+                "unit_of_measurement": "°F",
+            }),
+        ),
+        (
+            "sensor",
+            "conditions",
+            json!({
+                "name": "Conditions",
+                "state_topic": topic(topic_prefix, "conditions"),
+                "icon": "mdi:weather-partly-cloudy",
+            }),
+        ),
+        (
+            "sensor",
+            "lux",
+            json!({
+                "name": "Illuminance",
+                "state_topic": topic(topic_prefix, "lux"),
+                "device_class": "illuminance",
+                "state_class": "measurement",
+                "unit_of_measurement": "lx",
+            }),
+        ),
+        (
+            "sensor",
+            "forecast_age",
+            json!({
+                "name": "Forecast age",
+                "state_topic": topic(topic_prefix, "forecast_age"),
+                "device_class": "duration",
+                "unit_of_measurement": "min",
+                "entity_category": "diagnostic",
+            }),
+        ),
+        (
+            // The brightness of the displays. Turning it off dims the displays fully, and turning
+            // it on again follows the light sensor. Home Assistant turns it on before setting the
+            // brightness, which then overrides the light sensor.
+            "light",
+            "display",
+            json!({
+                "name": "Display",
+                "state_topic": topic(topic_prefix, "display"),
+                "command_topic": topic(topic_prefix, "command/light"),
+                "brightness_state_topic": topic(topic_prefix, "light"),
+                "brightness_value_template": "{{ (value | float * 255) | round(0) }}",
+                "brightness_command_topic": topic(topic_prefix, "command/light_brightness"),
+            }),
+        ),
+        (
+            "button",
+            "automatic_brightness",
+            json!({
+                "name": "Automatic brightness",
+                "command_topic": topic(topic_prefix, "command/brightness"),
+                "payload_press": json!({ "brightness": null }).to_string(),
+                "icon": "mdi:brightness-auto",
+            }),
+        ),
+        (
+            "button",
+            "refresh_weather",
+            json!({
+                "name": "Refresh weather",
+                "command_topic": topic(topic_prefix, "command/refresh"),
+                "icon": "mdi:refresh",
+            }),
+        ),
+    ];
+
+    entities
+        .into_iter()
+        .map(|(component, object_id, mut config)| {
+            let common = json!({
+                "unique_id": format!("{}_{}", client_id, object_id),
+                "object_id": format!("{}_{}", client_id, object_id),
+                "availability_topic": topic(topic_prefix, "status"),
+                "payload_available": ONLINE,
+                "payload_not_available": OFFLINE,
+                "device": device,
+            });
+            if let (Value::Object(config), Value::Object(common)) = (&mut config, common) {
+                config.extend(common);
+            }
+
+            (
+                format!(
+                    "{}/{}/{}/{}/config",
+                    discovery_prefix, component, client_id, object_id
+                ),
+                config.to_string(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::mqtt::parse_command;
+
+    #[test]
+    fn test_discovery_messages() {
+        let messages = discovery_messages("homeassistant", "clock", "kitchen_clock");
+
+        let topics = messages.iter().map(|(t, _)| t.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/sensor/kitchen_clock/temperature/config",
+                "homeassistant/sensor/kitchen_clock/conditions/config",
+                "homeassistant/sensor/kitchen_clock/lux/config",
+                "homeassistant/sensor/kitchen_clock/forecast_age/config",
+                "homeassistant/light/kitchen_clock/display/config",
+                "homeassistant/button/kitchen_clock/automatic_brightness/config",
+                "homeassistant/button/kitchen_clock/refresh_weather/config",
+            ]
+        );
+
+        let temperature: Value = serde_json::from_str(&messages[0].1).unwrap();
+        assert_eq!(temperature["state_topic"], "clock/temperature");
+        assert_eq!(temperature["unique_id"], "kitchen_clock_temperature");
+        assert_eq!(temperature["availability_topic"], "clock/status");
+        assert_eq!(
+            temperature["device"]["identifiers"],
+            json!(["kitchen_clock"])
+        );
+
+        let light: Value = serde_json::from_str(&messages[4].1).unwrap();
+        assert_eq!(light["state_topic"], "clock/display");
+        assert_eq!(
+            light["brightness_command_topic"],
+            "clock/command/light_brightness"
+        );
+        // Home Assistant sends ON to the command topic unless told otherwise
+        assert_eq!(light.get("on_command_type"), None);
+
+        // What Home Assistant sends when the light is turned on, turned off, and dimmed
+        let command = |topic: &Value, payload: &[u8]| {
+            let name = topic
+                .as_str()
+                .unwrap()
+                .strip_prefix("clock/command/")
+                .unwrap();
+            parse_command(name, payload)
+        };
+        assert_eq!(
+            command(&light["command_topic"], b"ON"),
+            Ok(Command::SetBrightness(None))
+        );
+        assert_eq!(
+            command(&light["command_topic"], b"OFF"),
+            Ok(Command::SetBrightness(Some(0.0)))
+        );
+        assert_eq!(
+            command(&light["brightness_command_topic"], b"255"),
+            Ok(Command::SetBrightness(Some(1.0)))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+// A stand-in for an MQTT broker such as Mosquitto, which records what clients send it.
+
+use bytes::BytesMut;
+use rumqttc::mqttbytes::v4::{self, Packet};
+use rumqttc::mqttbytes::{self, QoS};
+use rumqttc::{ConnAck, ConnectReturnCode, PingResp, PubAck, Publish, SubAck, SubscribeReasonCode};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_PACKET_BYTES: usize = 64 * 1024;
+
+// How long to wait for a client to send something before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct State {
+    // The last will of each client that connected
+    wills: Vec<Option<(String, String)>>,
+
+    // Everything published, including last wills of clients that went away without disconnecting
+    published: Vec<Publish>,
+
+    // The topic filter of each subscription, and the connection to send matching messages to
+    subscriptions: Vec<(String, TcpStream)>,
+}
+
+pub(crate) struct TestBroker {
+    port: u16,
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl TestBroker {
+    pub(crate) fn spawn() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new((Mutex::new(State::default()), Condvar::new()));
+
+        let connection_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&connection_state);
+                thread::spawn(move || serve(stream, &state));
+            }
+        });
+
+        TestBroker { port, state }
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub(crate) fn published(&self) -> Vec<Publish> {
+        self.state.0.lock().unwrap().published.clone()
+    }
+
+    // Waits for a client to connect, returning the topic and payload of its last will.
+    pub(crate) fn wait_for_connect(&self) -> Option<(String, String)> {
+        self.wait_for(|s| s.wills.first().cloned())
+    }
+
+    // Waits for a client to subscribe to exactly this topic filter.
+    pub(crate) fn wait_for_subscribe(&self, filter: &str) {
+        self.wait_for(|s| {
+            s.subscriptions
+                .iter()
+                .find(|(f, _)| f == filter)
+                .map(|_| ())
+        })
+    }
+
+    // Sends a message to the clients subscribed to the topic.
+    pub(crate) fn publish(&self, topic: &str, payload: &str) {
+        let mut packet = BytesMut::new();
+        Publish::new(topic, QoS::AtMostOnce, payload)
+            .write(&mut packet)
+            .unwrap();
+
+        for (filter, stream) in &mut self.state.0.lock().unwrap().subscriptions {
+            if mqttbytes::matches(topic, filter) {
+                stream.write_all(&packet).unwrap();
+            }
+        }
+    }
+
+    pub(crate) fn wait_for_publish(&self, topic: &str, payload: &str) -> Publish {
+        self.wait_for(|s| {
+            s.published
+                .iter()
+                .find(|p| p.topic == topic && p.payload == payload.as_bytes())
+                .cloned()
+        })
+    }
+
+    pub(crate) fn wait_for_topic(&self, topic: &str) -> Publish {
+        self.wait_for(|s| s.published.iter().find(|p| p.topic == topic).cloned())
+    }
+
+    fn wait_for<T, F: Fn(&State) -> Option<T>>(&self, f: F) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        let (state, changed) = &*self.state;
+        let mut state = state.lock().unwrap();
+        loop {
+            if let Some(found) = f(&state) {
+                return found;
+            }
+
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .expect("timed out waiting for the MQTT client");
+            state = changed.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &(Mutex<State>, Condvar)) {
+    let mut will = None;
+    let mut incoming = BytesMut::new();
+    let mut buf = [0; 4096];
+
+    loop {
+        let packet = match v4::read(&mut incoming, MAX_PACKET_BYTES) {
+            Ok(packet) => packet,
+            Err(mqttbytes::Error::InsufficientBytes(_)) => match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    incoming.extend_from_slice(&buf[..n]);
+                    continue;
+                }
+            },
+            Err(e) => panic!("invalid MQTT packet: {}", e),
+        };
+
+        let mut outgoing = BytesMut::new();
+        match packet {
+            Packet::Connect(connect) => {
+                will = connect.last_will.map(|w| {
+                    let mut publish = Publish::new(w.topic, w.qos, w.message.to_vec());
+                    publish.retain = w.retain;
+                    publish
+                });
+                record(state, |s| {
+                    s.wills.push(will.as_ref().map(|w| {
+                        (
+                            w.topic.clone(),
+                            String::from_utf8_lossy(&w.payload).to_string(),
+                        )
+                    }))
+                });
+                ConnAck::new(ConnectReturnCode::Success, false)
+                    .write(&mut outgoing)
+                    .unwrap();
+            }
+            Packet::Publish(publish) => {
+                if publish.qos != QoS::AtMostOnce {
+                    PubAck::new(publish.pkid).write(&mut outgoing).unwrap();
+                }
+                record(state, |s| s.published.push(publish));
+            }
+            Packet::Subscribe(subscribe) => {
+                let codes = subscribe
+                    .filters
+                    .iter()
+                    .map(|f| SubscribeReasonCode::Success(f.qos))
+                    .collect();
+                SubAck::new(subscribe.pkid, codes)
+                    .write(&mut outgoing)
+                    .unwrap();
+
+                // Acknowledged before anything is sent to the new subscription
+                if stream.write_all(&outgoing).is_err() {
+                    break;
+                }
+                outgoing.clear();
+                let subscriber = stream.try_clone().unwrap();
+                record(state, |s| {
+                    s.subscriptions.extend(
+                        subscribe
+                            .filters
+                            .into_iter()
+                            .map(|f| (f.path, subscriber.try_clone().unwrap())),
+                    )
+                });
+            }
+            Packet::PingReq => {
+                PingResp.write(&mut outgoing).unwrap();
+            }
+            Packet::Disconnect => {
+                will = None;
+                break;
+            }
+            _ => {}
+        }
+
+        if stream.write_all(&outgoing).is_err() {
+            break;
+        }
+    }
+
+    if let Some(will) = will {
+        record(state, |s| s.published.push(will));
+    }
+}
+
+fn record<F: FnOnce(&mut State)>(state: &(Mutex<State>, Condvar), f: F) {
+    f(&mut state.0.lock().unwrap());
+    state.1.notify_all();
+}
@@ -0,0 +1,54 @@
+// Parameters given with display and light sensor types on the command line, e.g. the pins in
+// "lcd-16x2@rs=21,en=20,pwm=1".
+
+use std::convert::TryFrom;
+
+// Splits a spec into its name and its parameters, each with or without a value.
+pub(crate) fn split(spec: &str) -> (&str, Vec<(&str, Option<&str>)>) {
+    let Some((name, parameters)) = spec.split_once('@') else {
+        return (spec, vec![]);
+    };
+
+    let parameters = parameters
+        .split(',')
+        .map(|parameter| match parameter.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (parameter.trim(), None),
+        })
+        .collect();
+
+    (name, parameters)
+}
+
+// Parses a number in decimal, or in hexadecimal like 0x71.
+pub(crate) fn parse_number<T: TryFrom<u64>>(s: &str) -> Option<T> {
+    let number = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+
+    T::try_from(number).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("lcd-16x2"), ("lcd-16x2", vec![]));
+        assert_eq!(split("alphanum4@0x72"), ("alphanum4", vec![("0x72", None)]));
+        assert_eq!(
+            split("lcd-20x4@rs=4, pwm=1"),
+            ("lcd-20x4", vec![("rs", Some("4")), ("pwm", Some("1"))])
+        );
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number::<u8>("0x72"), Some(0x72));
+        assert_eq!(parse_number::<u8>("17"), Some(17));
+        assert_eq!(parse_number::<u8>("0x1ff"), None);
+        assert_eq!(parse_number::<u64>("pin"), None);
+    }
+}
@@ -0,0 +1,348 @@
+// A small HTTP API for checking on clocks and controlling them without SSH:
+//
+//   GET  /status      everything below in one document
+//   GET  /frames      what each display is showing
+//   GET  /weather     the latest forecast and fetch status
+//   GET  /light       the light level and brightness override
+//   POST /refresh     fetch the weather now
+//   POST /brightness  {"brightness": 0.5} overrides the light sensor, {"brightness": null} clears it
+//   POST /message     {"text": "Laundry done", "seconds": 30} shows a message
+//   GET  /metrics     Prometheus metrics
+
+mod error;
+
+pub use error::Error;
+
+use crate::command::Command;
+use crate::display::Frame;
+use crate::status::Status;
+use crate::weather::FetchStatus;
+
+use chrono::Local;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Requests are small JSON documents; anything bigger is cut off and fails to parse.
+const MAX_BODY_BYTES: u64 = 4096;
+
+// Serves the API on a background thread until dropped.
+pub struct HttpServer {
+    server: Arc<Server>,
+}
+
+impl HttpServer {
+    pub fn spawn(
+        address: &str,
+        status: Arc<Mutex<Status>>,
+        commands: Sender<Command>,
+    ) -> Result<Self, Error> {
+        let server = Arc::new(Server::http(address)?);
+
+        info!("HTTP server listening on {}", server.server_addr());
+
+        let requests = Arc::clone(&server);
+        thread::spawn(move || {
+            for mut request in requests.incoming_requests() {
+                let mut body = String::new();
+                let (code, response) = match request
+                    .as_reader()
+                    .take(MAX_BODY_BYTES)
+                    .read_to_string(&mut body)
+                {
+                    Ok(_) => handle(request.method(), request.url(), &body, &status, &commands),
+                    Err(e) => (400, Body::Json(error_json(e))),
+                };
+
+                let (response, content_type) = match response {
+                    Body::Json(json) => (json.to_string(), &b"application/json"[..]),
+                    Body::Metrics(metrics) => (metrics, &b"text/plain; version=0.0.4"[..]),
+                };
+                let response = Response::from_string(response)
+                    .with_status_code(code)
+                    .with_header(Header::from_bytes(&b"Content-Type"[..], content_type).unwrap());
+                respond(request, response);
+            }
+        });
+
+        Ok(HttpServer { server })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) {
+    if let Err(e) = request.respond(response) {
+        warn!("Error responding to HTTP request: {}", e);
+    }
+}
+
+// The body of a response: JSON for the API, or text for Prometheus.
+#[derive(Debug, PartialEq)]
+enum Body {
+    Json(Value),
+    Metrics(String),
+}
+
+// Returns the status code and body of the response to a request.
+fn handle(
+    method: &Method,
+    url: &str,
+    body: &str,
+    status: &Mutex<Status>,
+    commands: &Sender<Command>,
+) -> (u16, Body) {
+    let path = url.split('?').next().unwrap_or_default();
+
+    match (method, path) {
+        (Method::Get, "/metrics") => {
+            let metrics = status
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .metrics
+                .render(&Local::now());
+            (200, Body::Metrics(metrics))
+        }
+        _ => {
+            let (code, json) = handle_json(method, path, body, status, commands);
+            (code, Body::Json(json))
+        }
+    }
+}
+
+// Returns the status code and JSON body of the response to a request to the API.
+fn handle_json(
+    method: &Method,
+    path: &str,
+    body: &str,
+    status: &Mutex<Status>,
+    commands: &Sender<Command>,
+) -> (u16, Value) {
+    let command = match (method, path) {
+        (Method::Get, "/status" | "/frames" | "/weather" | "/light") => {
+            let status = match status.lock() {
+                Ok(status) => status,
+                Err(_) => return (500, json!({ "error": "status lock poisoned" })),
+            };
+
+            let response = match path {
+                "/frames" => frames_json(&status.frames),
+                "/weather" => weather_json(&status),
+                "/light" => light_json(&status),
+                _ => json!({
+                    "frames": frames_json(&status.frames),
+                    "weather": weather_json(&status),
+                    "light": light_json(&status),
+                    "message": status.message,
+                }),
+            };
+            return (200, response);
+        }
+
+        (Method::Post, "/refresh") => Command::RefreshWeather,
+        (Method::Post, "/brightness") => match Command::from_brightness_json(body) {
+            Ok(command) => command,
+            Err(e) => return (400, error_json(e)),
+        },
+        (Method::Post, "/message") => match Command::from_message_json(body) {
+            Ok(command) => command,
+            Err(e) => return (400, error_json(e)),
+        },
+
+        (
+            _,
+            "/status" | "/frames" | "/weather" | "/light" | "/refresh" | "/brightness" | "/message"
+            | "/metrics",
+        ) => return (405, json!({ "error": "method not allowed" })),
+        _ => return (404, json!({ "error": "not found" })),
+    };
+
+    match commands.send(command) {
+        Ok(()) => (202, json!({})),
+        Err(_) => (503, json!({ "error": "clock is shutting down" })),
+    }
+}
+
+fn error_json<E: std::fmt::Display>(e: E) -> Value {
+    json!({ "error": e.to_string() })
+}
+
+fn frames_json(frames: &[(&'static str, Frame)]) -> Value {
+    frames
+        .iter()
+        .map(|(display, frame)| json!({ "display": display, "rows": frame.rows() }))
+        .collect()
+}
+
+fn weather_json(status: &Status) -> Value {
+    json!({
+        "forecast": status.weather,
+        "fetch_status": fetch_status_json(&status.fetch_status),
+    })
+}
+
+fn fetch_status_json(fetch_status: &FetchStatus) -> Value {
+    json!({
+        "last_attempt": fetch_status.last_attempt.map(|t| t.to_rfc3339()),
+        "last_success": fetch_status.last_success.map(|t| t.to_rfc3339()),
+        "last_error": fetch_status.last_error,
+    })
+}
+
+fn light_json(status: &Status) -> Value {
+    json!({
+        "light": status.light,
+        "brightness_override": status.brightness_override,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn status() -> Mutex<Status> {
+        Mutex::new(Status {
+            frames: vec![(
+                "console-16x2",
+                Frame::Text(vec!["12:34".to_string(), "Mon Jan 15".to_string()]),
+            )],
+            light: 0.25,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_handle() {
+        let status = status();
+        let (sender, commands) = mpsc::channel();
+
+        assert_eq!(
+            handle(&Method::Get, "/frames", "", &status, &sender),
+            (
+                200,
+                Body::Json(json!([{ "display": "console-16x2", "rows": ["12:34", "Mon Jan 15"] }]))
+            )
+        );
+        assert_eq!(
+            handle(&Method::Get, "/light", "", &status, &sender),
+            (
+                200,
+                Body::Json(json!({ "light": 0.25, "brightness_override": null }))
+            )
+        );
+        assert_eq!(
+            handle_json(&Method::Get, "/weather", "", &status, &sender).1["fetch_status"]
+                ["last_error"],
+            Value::Null
+        );
+
+        assert_eq!(
+            handle(&Method::Post, "/refresh", "", &status, &sender).0,
+            202
+        );
+        assert_eq!(
+            handle(
+                &Method::Post,
+                "/brightness",
+                r#"{"brightness": 0.5}"#,
+                &status,
+                &sender
+            )
+            .0,
+            202
+        );
+        assert_eq!(
+            handle(
+                &Method::Post,
+                "/message",
+                r#"{"text": "Laundry done", "seconds": 30}"#,
+                &status,
+                &sender
+            )
+            .0,
+            202
+        );
+        assert_eq!(
+            commands.try_iter().collect::<Vec<_>>(),
+            vec![
+                Command::RefreshWeather,
+                Command::SetBrightness(Some(0.5)),
+                Command::ShowMessage("Laundry done".to_string(), Duration::from_secs(30)),
+            ]
+        );
+
+        assert_eq!(
+            handle(
+                &Method::Post,
+                "/brightness",
+                r#"{"brightness": 2}"#,
+                &status,
+                &sender
+            )
+            .0,
+            400
+        );
+        assert_eq!(
+            handle(&Method::Post, "/message", "{}", &status, &sender).0,
+            400
+        );
+        assert_eq!(
+            handle(
+                &Method::Post,
+                "/message",
+                r#"{"text": "x", "seconds": 18446744073709551615}"#,
+                &status,
+                &sender
+            )
+            .0,
+            400
+        );
+        assert_eq!(
+            handle(&Method::Get, "/refresh", "", &status, &sender).0,
+            405
+        );
+        assert_eq!(
+            handle(&Method::Post, "/metrics", "", &status, &sender).0,
+            405
+        );
+        assert_eq!(handle(&Method::Get, "/nope", "", &status, &sender).0, 404);
+        assert_eq!(commands.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_serves_requests() -> Result<(), Box<dyn std::error::Error>> {
+        let (sender, commands) = mpsc::channel();
+        let server = HttpServer::spawn("127.0.0.1:0", Arc::new(status()), sender)?;
+        let url = format!("http://{}", server.address().unwrap());
+
+        let response = ureq::get(&format!("{}/status", url)).call()?;
+        assert_eq!(response.content_type(), "application/json");
+        let body: Value = response.into_json()?;
+        assert_eq!(body["light"]["light"], json!(0.25));
+
+        ureq::post(&format!("{}/brightness", url)).send_string(r#"{"brightness": null}"#)?;
+        assert_eq!(commands.recv()?, Command::SetBrightness(None));
+
+        let response = ureq::get(&format!("{}/metrics", url)).call()?;
+        assert_eq!(response.content_type(), "text/plain");
+        let metrics = response.into_string()?;
+        assert!(metrics.contains("pi_clock_weather_fetch_attempts_total 0\n"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use crate::display::Frame;
+use crate::metrics::Metrics;
+use crate::weather::{FetchStatus, OpenWeather};
+
+// What the main loop is currently showing, shared with the HTTP server.
+#[derive(Default)]
+pub struct Status {
+    // The frame of each display, labelled with the display's name.
+    pub frames: Vec<(&'static str, Frame)>,
+
+    // The most recently fetched forecast, even if it's since become too old to show.
+    pub weather: Option<OpenWeather>,
+    pub fetch_status: FetchStatus,
+
+    // The light level the displays are dimmed to, and the override if one is set.
+    pub light: f32,
+    pub brightness_override: Option<f32>,
+
+    // The raw reading of light sensors that measure lux.
+    pub lux: Option<f32>,
+
+    pub message: Option<String>,
+
+    pub metrics: Metrics,
+}
@@ -0,0 +1,297 @@
+// Integration with systemd: readiness and watchdog notifications for services with Type=notify,
+// and the unit file that runs the clock as one.
+// See https://www.freedesktop.org/software/systemd/man/sd_notify.html
+
+use log::{debug, warn};
+use std::env;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Sends notifications to systemd, if the clock was started by it with a notification socket, and
+/// otherwise does nothing.
+pub struct SystemdNotifier {
+    socket: Option<String>,
+
+    // How often systemd expects to hear from the clock, if it has a watchdog.
+    watchdog_interval: Option<Duration>,
+    last_watchdog: Option<Instant>,
+}
+
+impl SystemdNotifier {
+    /// A notifier for the socket and watchdog that systemd passed to the clock, if any.
+    pub fn from_env() -> Self {
+        let socket = env::var("NOTIFY_SOCKET").ok();
+
+        // The watchdog is meant for this process, unless WATCHDOG_PID says otherwise
+        let for_this_process = match env::var("WATCHDOG_PID") {
+            Ok(pid) => pid.parse() == Ok(std::process::id()),
+            Err(_) => true,
+        };
+        let watchdog_interval = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| for_this_process)
+            .map(Duration::from_micros);
+
+        Self::new(socket, watchdog_interval)
+    }
+
+    fn new(socket: Option<String>, watchdog_interval: Option<Duration>) -> Self {
+        SystemdNotifier {
+            socket,
+            watchdog_interval,
+            last_watchdog: None,
+        }
+    }
+
+    /// Tells systemd that the clock has started up.
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// Tells systemd that the clock is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Tells systemd that the clock is still running, if it's been long enough since last time.
+    /// Called every loop iteration, so that if the loop hangs systemd restarts the clock.
+    pub fn watchdog(&mut self) {
+        let Some(interval) = self.watchdog_interval else {
+            return;
+        };
+
+        // Pinging at half the interval leaves time for a slow loop iteration
+        if matches!(self.last_watchdog, Some(last) if last.elapsed() < interval / 2) {
+            return;
+        }
+
+        self.notify("WATCHDOG=1");
+        self.last_watchdog = Some(Instant::now());
+    }
+
+    fn notify(&self, state: &str) {
+        if let Some(socket) = &self.socket {
+            debug!("Notifying systemd: {}", state);
+
+            if let Err(e) = send(socket, state) {
+                warn!("Error notifying systemd ({}): {}", state, e);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send(socket: &str, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let datagram = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        // An abstract socket, which has no path on the filesystem
+        Some(name) => {
+            datagram.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)?
+        }
+        None => datagram.send_to(state.as_bytes(), socket)?,
+    };
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send(_socket: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "systemd is only supported on Linux",
+    ))
+}
+
+/// A service that runs the clock, restarting it if it fails or hangs.
+pub struct SystemdUnit<'a> {
+    /// The command line that runs the clock, starting with the executable
+    pub command: &'a [String],
+    pub working_directory: &'a str,
+    pub user: Option<&'a str>,
+    pub watchdog: Duration,
+    /// A file of environment variables for the clock, such as secrets that would otherwise be on
+    /// its command line
+    pub environment_file: Option<&'a str>,
+}
+
+impl SystemdUnit<'_> {
+    /// The contents of the unit file.
+    pub fn render(&self) -> String {
+        let exec_start = self
+            .command
+            .iter()
+            .map(|arg| quote_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let user = match self.user {
+            Some(user) => format!("User={}\n", user),
+            None => String::new(),
+        };
+        let environment_file = match self.environment_file {
+            Some(path) => format!("EnvironmentFile={}\n", escape_specifiers(path)),
+            None => String::new(),
+        };
+
+        format!(
+            "\
+[Unit]
+Description=Pi Clock
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=notify
+ExecStart={}
+WorkingDirectory={}
+{}{}WatchdogSec={}
+Restart=always
+RestartSec=5
+
+[Install]
+WantedBy=multi-user.target
+",
+            exec_start,
+            escape_specifiers(self.working_directory),
+            environment_file,
+            user,
+            self.watchdog.as_secs().max(1),
+        )
+    }
+
+    /// The contents of an environment file that sets the variables.
+    pub fn render_environment(variables: &[(&str, &str)]) -> String {
+        variables
+            .iter()
+            .map(|(name, value)| {
+                let mut escaped = String::new();
+                for c in value.chars() {
+                    if matches!(c, '\\' | '"' | '`' | '$') {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+                format!("{}=\"{}\"\n", name, escaped)
+            })
+            .collect()
+    }
+}
+
+// Escapes the % specifiers that systemd expands in paths, which unlike ExecStart aren't unquoted.
+fn escape_specifiers(path: &str) -> String {
+    path.replace('%', "%%")
+}
+
+// Quotes an argument of ExecStart, which systemd splits on whitespace and expands % specifiers
+// and $ variables in.
+fn quote_arg(arg: &str) -> String {
+    let escaped = escape_specifiers(arg).replace('$', "$$");
+    let needs_quotes =
+        escaped.is_empty() || escaped.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c));
+    if !needs_quotes {
+        return escaped;
+    }
+
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_file() {
+        let command = [
+            "/usr/local/bin/pi_clock".to_string(),
+            "--uri=http://cache.local/weather?units=imperial&appid=$KEY".to_string(),
+            "--display-type".to_string(),
+            "lcd-20x4".to_string(),
+            "--snapshot-dir".to_string(),
+            "/srv/clock images".to_string(),
+        ];
+
+        assert_eq!(
+            SystemdUnit {
+                command: &command,
+                working_directory: "/home/pi/clock 100%",
+                user: Some("pi"),
+                watchdog: Duration::from_secs(30),
+                environment_file: Some("/etc/systemd/system/pi_clock.env"),
+            }
+            .render(),
+            "\
+[Unit]
+Description=Pi Clock
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=notify
+ExecStart=/usr/local/bin/pi_clock --uri=http://cache.local/weather?units=imperial&appid=$$KEY --display-type lcd-20x4 --snapshot-dir \"/srv/clock images\"
+WorkingDirectory=/home/pi/clock 100%%
+EnvironmentFile=/etc/systemd/system/pi_clock.env
+User=pi
+WatchdogSec=30
+Restart=always
+RestartSec=5
+
+[Install]
+WantedBy=multi-user.target
+"
+        );
+    }
+
+    #[test]
+    fn test_environment_file() {
+        assert_eq!(
+            SystemdUnit::render_environment(&[
+                ("PI_CLOCK_MQTT_PASSWORD", r#"p@ss "word" $HOME\"#),
+                ("EMPTY", ""),
+            ]),
+            "PI_CLOCK_MQTT_PASSWORD=\"p@ss \\\"word\\\" \\$HOME\\\\\"\nEMPTY=\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("lcd-16x2"), "lcd-16x2");
+        assert_eq!(quote_arg("100%"), "100%%");
+        assert_eq!(quote_arg(""), "\"\"");
+        assert_eq!(quote_arg(r#"say "hi""#), r#""say \"hi\"""#);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_notifier() -> io::Result<()> {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::temp_dir().join(format!("pi_clock_notify_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path)?;
+        systemd.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut notifier = SystemdNotifier::new(
+            Some(path.to_string_lossy().to_string()),
+            Some(Duration::from_secs(60)),
+        );
+        notifier.ready();
+        notifier.watchdog();
+        // Too soon after the last ping to ping again
+        notifier.watchdog();
+        notifier.stopping();
+
+        let mut buf = [0; 64];
+        let mut received = vec![];
+        for _ in 0..3 {
+            let n = systemd.recv(&mut buf)?;
+            received.push(String::from_utf8_lossy(&buf[..n]).to_string());
+        }
+        assert_eq!(received, vec!["READY=1", "WATCHDOG=1", "STOPPING=1"]);
+
+        std::fs::remove_file(&path)
+    }
+}
@@ -1,36 +1,92 @@
 mod error;
-mod open_weather_types;
+pub mod open_weather_types;
+mod report;
 
 use chrono::{DateTime, Local, TimeZone};
 pub use error::Error;
 pub use open_weather_types::{Main, OpenWeather};
+pub use report::WeatherReport;
 use std::time::Duration;
 
-pub fn get_weather(uri: &str, timeout: Duration) -> Result<OpenWeather, Error> {
-    let agent = ureq::builder().timeout(timeout).build();
+// Forecasts older than this are treated as errors.
+const STALE_AFTER_MINUTES: i64 = 30;
 
-    let response = agent.get(uri).call()?.into_string()?;
+pub fn get_weather(uri: &str, timeout: Duration) -> Result<OpenWeather, Error> {
+    let response = agent(timeout).get(uri).call()?;
 
-    let w = serde_json::from_str(&response)?;
+    let w = parse(response)?;
 
-    if weather_stale(&w) {
+    if weather_stale(&w, &Local::now()) {
         return Err(error::new_stale());
     }
 
     Ok(w)
 }
 
-fn weather_stale(w: &OpenWeather) -> bool {
-    Local::now() - Local.timestamp_opt(w.current.dt, 0).earliest().unwrap()
-        > chrono::TimeDelta::try_minutes(30).unwrap()
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::builder().timeout(timeout).build()
 }
 
-fn timestamp_before_now(ts: &DateTime<Local>) -> bool {
-    *ts - Local::now() < chrono::TimeDelta::zero()
+fn parse(response: ureq::Response) -> Result<OpenWeather, Error> {
+    let response = response.into_string()?;
+
+    parse_weather(&response)
 }
 
-fn timestamp_after_24_hours(ts: &DateTime<Local>) -> bool {
-    *ts - Local::now() > chrono::TimeDelta::try_hours(24).unwrap()
+// Parses a forecast in the format of the One Call API, checking that it has the conditions and
+// hourly forecast that the displays show. Missing fields are otherwise left empty.
+pub fn parse_weather(json: &str) -> Result<OpenWeather, Error> {
+    let w: OpenWeather = serde_json::from_str(json)?;
+
+    if w.current.weather.is_empty() {
+        return Err(error::new_incomplete("current conditions"));
+    }
+    if w.hourly.is_empty() {
+        return Err(error::new_incomplete("hourly forecast"));
+    }
+    if w.hourly.iter().any(|h| h.weather.is_empty()) {
+        return Err(error::new_incomplete("hourly conditions"));
+    }
+
+    Ok(w)
+}
+
+// The outcome of recent attempts to get the weather.
+#[derive(Clone, Debug, Default)]
+pub struct FetchStatus {
+    pub last_attempt: Option<DateTime<Local>>,
+    pub last_success: Option<DateTime<Local>>,
+    // The error from the most recent attempt, if it failed.
+    pub last_error: Option<String>,
+}
+
+impl FetchStatus {
+    pub fn record_success(&mut self, time: DateTime<Local>) {
+        self.last_attempt = Some(time);
+        self.last_success = Some(time);
+        self.last_error = None;
+    }
+
+    pub fn record_failure(&mut self, time: DateTime<Local>, err: &Error) {
+        self.last_attempt = Some(time);
+        self.last_error = Some(err.to_string());
+    }
+}
+
+fn forecast_age(w: &OpenWeather, now: &DateTime<Local>) -> chrono::TimeDelta {
+    *now - Local.timestamp_opt(w.current.dt, 0).earliest().unwrap()
+}
+
+fn weather_stale(w: &OpenWeather, now: &DateTime<Local>) -> bool {
+    forecast_age(w, now) > chrono::TimeDelta::try_minutes(STALE_AFTER_MINUTES).unwrap()
+}
+
+fn timestamp_before_now(ts: &DateTime<Local>, now: &DateTime<Local>) -> bool {
+    *ts - *now < chrono::TimeDelta::zero()
+}
+
+fn timestamp_after_24_hours(ts: &DateTime<Local>, now: &DateTime<Local>) -> bool {
+    *ts - *now > chrono::TimeDelta::try_hours(24).unwrap()
 }
 
 fn is_precipitation(w: Main) -> bool {
@@ -40,17 +96,20 @@ fn is_precipitation(w: Main) -> bool {
     )
 }
 
-pub fn high_low_temp(w: &OpenWeather) -> ((DateTime<Local>, f32), (DateTime<Local>, f32)) {
+pub fn high_low_temp(
+    w: &OpenWeather,
+    now: &DateTime<Local>,
+) -> ((DateTime<Local>, f32), (DateTime<Local>, f32)) {
     let mut high = &w.hourly[0];
     let mut low = &w.hourly[0];
 
     for h in w.hourly.iter() {
         let ts = Local.timestamp_opt(h.dt, 0).earliest().unwrap();
-        if timestamp_before_now(&ts) {
+        if timestamp_before_now(&ts, now) {
             continue;
         }
 
-        if timestamp_after_24_hours(&ts) {
+        if timestamp_after_24_hours(&ts, now) {
             continue;
         }
 
@@ -86,7 +145,7 @@ pub enum PrecipitationChange {
 // type.
 // e.g. If it is currently raining, then it snows, then it stops snowing, only the stop time
 // is returned, and the precipitation change type is rain.
-pub fn next_precipitation_change(w: &OpenWeather) -> PrecipitationChange {
+pub fn next_precipitation_change(w: &OpenWeather, now: &DateTime<Local>) -> PrecipitationChange {
     let current_precipitation = if is_precipitation(w.current.weather[0].main) {
         Some(w.current.weather[0].main)
     } else {
@@ -95,11 +154,11 @@ pub fn next_precipitation_change(w: &OpenWeather) -> PrecipitationChange {
 
     for h in w.hourly.iter() {
         let ts = Local.timestamp_opt(h.dt, 0).earliest().unwrap();
-        if timestamp_before_now(&ts) {
+        if timestamp_before_now(&ts, now) {
             continue;
         }
 
-        if timestamp_after_24_hours(&ts) {
+        if timestamp_after_24_hours(&ts, now) {
             return PrecipitationChange::NoChange(current_precipitation);
         }
 
@@ -160,7 +219,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Stop(
             Local.timestamp_opt(w.hourly[2].dt, 0).earliest().unwrap(),
             Main::Rain,
@@ -204,7 +263,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Start(
             Local.timestamp_opt(w.hourly[1].dt, 0).earliest().unwrap(),
             Main::Rain,
@@ -248,7 +307,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Stop(
             Local.timestamp_opt(w.hourly[2].dt, 0).earliest().unwrap(),
             Main::Snow,
@@ -292,7 +351,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Start(
             Local.timestamp_opt(w.hourly[1].dt, 0).earliest().unwrap(),
             Main::Snow,
@@ -336,7 +395,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Stop(
             Local.timestamp_opt(w.hourly[2].dt, 0).earliest().unwrap(),
             Main::Drizzle,
@@ -380,7 +439,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Start(
             Local.timestamp_opt(w.hourly[1].dt, 0).earliest().unwrap(),
             Main::Drizzle,
@@ -424,7 +483,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Stop(
             Local.timestamp_opt(w.hourly[2].dt, 0).earliest().unwrap(),
             Main::Thunderstorm,
@@ -468,7 +527,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Start(
             Local.timestamp_opt(w.hourly[1].dt, 0).earliest().unwrap(),
             Main::Thunderstorm,
@@ -512,7 +571,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
 
         assert_eq!(maybe_next_change, PrecipitationChange::NoChange(None));
     }
@@ -552,7 +611,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Stop(
             Local.timestamp_opt(w.hourly[2].dt, 0).earliest().unwrap(),
             Main::Rain,
@@ -596,7 +655,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::Start(
             Local.timestamp_opt(w.hourly[1].dt, 0).earliest().unwrap(),
             Main::Rain,
@@ -640,7 +699,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
         let expected = PrecipitationChange::NoChange(Some(Main::Rain));
 
         assert_eq!(maybe_next_change, expected)
@@ -681,7 +740,7 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
 
         assert_eq!(
             maybe_next_change,
@@ -727,8 +786,45 @@ mod tests {
             icon: "some-icon".to_string(),
         }];
 
-        let maybe_next_change = next_precipitation_change(&w);
+        let maybe_next_change = next_precipitation_change(&w, &Local::now());
 
         assert_eq!(maybe_next_change, PrecipitationChange::NoChange(None));
     }
+    #[test]
+    fn test_parse_weather() {
+        let conditions =
+            r#"[{"id": 800, "main": "Clear", "description": "clear sky", "icon": "01d"}]"#;
+        let w = parse_weather(&format!(
+            r#"{{"current": {{"weather": {0}}}, "hourly": [{{"weather": {0}}}]}}"#,
+            conditions
+        ))
+        .unwrap();
+        assert_eq!(w.current.weather[0].main, Main::Clear);
+
+        // Forecasts that the displays can't show are errors rather than panics
+        for (json, missing) in [
+            ("{}".to_string(), "current conditions"),
+            (
+                format!(r#"{{"current": {{"weather": {}}}}}"#, conditions),
+                "hourly forecast",
+            ),
+            (
+                format!(
+                    r#"{{"current": {{"weather": {}}}, "hourly": [{{}}]}}"#,
+                    conditions
+                ),
+                "hourly conditions",
+            ),
+        ] {
+            let err = parse_weather(&json).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("incomplete weather: no {}", missing)
+            );
+        }
+        assert_eq!(
+            parse_weather("not json").unwrap_err().kind().name(),
+            "json_parse"
+        );
+    }
 }
@@ -23,6 +23,22 @@ pub enum ErrorKind {
     JSONParse(serde_json::Error),
     Transport(Box<ureq::Error>),
     Stale,
+    // A forecast that parsed but is missing something the displays need.
+    Incomplete(&'static str),
+}
+
+impl ErrorKind {
+    // A short name for the kind of error, e.g. for labelling metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Http(_) => "http",
+            ErrorKind::StringParse(_) => "string_parse",
+            ErrorKind::JSONParse(_) => "json_parse",
+            ErrorKind::Transport(_) => "transport",
+            ErrorKind::Stale => "stale",
+            ErrorKind::Incomplete(_) => "incomplete",
+        }
+    }
 }
 
 pub fn new_stale() -> Error {
@@ -31,6 +47,12 @@ pub fn new_stale() -> Error {
     }
 }
 
+pub fn new_incomplete(missing: &'static str) -> Error {
+    Error {
+        kind: ErrorKind::Incomplete(missing),
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
@@ -39,6 +61,7 @@ impl fmt::Display for Error {
             ErrorKind::JSONParse(ref err) => err.fmt(f),
             ErrorKind::Transport(ref err) => err.fmt(f),
             ErrorKind::Stale => write!(f, "stale weather"),
+            ErrorKind::Incomplete(missing) => write!(f, "incomplete weather: no {}", missing),
         }
     }
 }
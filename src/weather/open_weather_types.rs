@@ -1,7 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct OpenWeather {
     pub lat: f32,
@@ -14,7 +14,7 @@ pub struct OpenWeather {
     pub daily: Vec<Daily>,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Current {
     pub dt: i64,
@@ -36,21 +36,21 @@ pub struct Current {
     pub weather: Vec<Weather>,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Rain {
     #[serde(rename = "1h")]
     pub one_hour: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Snow {
     #[serde(rename = "1h")]
     pub one_hour: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Weather {
     pub id: i32,
@@ -59,7 +59,7 @@ pub struct Weather {
     pub icon: String,
 }
 
-#[derive(Default, Clone, Copy, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Main {
     Thunderstorm,
     Drizzle,
@@ -101,14 +101,14 @@ impl fmt::Display for Main {
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Minutely {
     pub dt: i64,
     pub precipitation: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Hourly {
     pub dt: i64,
@@ -129,7 +129,7 @@ pub struct Hourly {
     pub weather: Vec<Weather>,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Daily {
     pub dt: i64,
@@ -154,7 +154,7 @@ pub struct Daily {
     pub weather: Vec<Weather>,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct Temp {
     pub morn: f32,
@@ -165,7 +165,7 @@ pub struct Temp {
     pub max: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct FeelsLike {
     pub morn: f32,
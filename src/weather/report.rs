@@ -0,0 +1,192 @@
+// A one-off request for the weather, described in full, for working out why a clock isn't
+// showing it.
+
+use super::{
+    agent, forecast_age, high_low_temp, next_precipitation_change, parse, weather_stale, Error,
+    OpenWeather, PrecipitationChange, STALE_AFTER_MINUTES,
+};
+
+use chrono::{DateTime, Local};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub struct WeatherReport {
+    pub uri: String,
+    pub time: DateTime<Local>,
+
+    // The HTTP status of the response, if there was one
+    pub status: Option<u16>,
+    // How long the request took, including reading the response
+    pub latency: Duration,
+
+    // The weather, even if it's stale, or the error that stopped it being parsed
+    pub weather: Result<OpenWeather, Error>,
+}
+
+impl WeatherReport {
+    pub fn fetch(uri: &str, timeout: Duration) -> Self {
+        let start = Instant::now();
+        let response = agent(timeout).get(uri).call();
+        let status = match &response {
+            Ok(response) => Some(response.status()),
+            Err(ureq::Error::Status(status, _)) => Some(*status),
+            Err(ureq::Error::Transport(_)) => None,
+        };
+        let weather = response.map_err(Error::from).and_then(parse);
+
+        WeatherReport {
+            uri: uri.to_string(),
+            time: Local::now(),
+            status,
+            latency: start.elapsed(),
+            weather,
+        }
+    }
+
+    // Whether the clock would show this weather.
+    pub fn is_ok(&self) -> bool {
+        matches!(&self.weather, Ok(w) if !weather_stale(w, &self.time))
+    }
+}
+
+impl fmt::Display for WeatherReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "URI:           {}", self.uri)?;
+        match self.status {
+            Some(status) => writeln!(f, "HTTP status:   {}", status)?,
+            None => writeln!(f, "HTTP status:   no response")?,
+        }
+        writeln!(f, "Latency:       {} ms", self.latency.as_millis())?;
+
+        let w = match &self.weather {
+            Ok(w) => w,
+            Err(e) => return writeln!(f, "Error:         {}", e),
+        };
+
+        let age = forecast_age(w, &self.time);
+        writeln!(
+            f,
+            "Forecast age:  {} minutes ({}, stale after {} minutes)",
+            age.num_minutes(),
+            if weather_stale(w, &self.time) {
+                "STALE"
+            } else {
+                "fresh"
+            },
+            STALE_AFTER_MINUTES
+        )?;
+
+        // The clock's calculations assume there are current conditions and an hourly forecast
+        let Some(conditions) = w.current.weather.first() else {
+            return writeln!(f, "Conditions:    missing");
+        };
+        writeln!(
+            f,
+            "Conditions:    {} ({}), {:.1}°",
+            conditions.main, conditions.description, w.current.temp
+        )?;
+        if w.hourly.is_empty() {
+            return writeln!(f, "Hourly:        missing");
+        }
+
+        let ((high_time, high), (low_time, low)) = high_low_temp(w, &self.time);
+        writeln!(
+            f,
+            "High:          {:.1}° at {}",
+            high,
+            high_time.format("%a %H:%M")
+        )?;
+        writeln!(
+            f,
+            "Low:           {:.1}° at {}",
+            low,
+            low_time.format("%a %H:%M")
+        )?;
+
+        let change = match next_precipitation_change(w, &self.time) {
+            PrecipitationChange::Start(time, main) => {
+                format!("{} starts at {}", main, time.format("%a %H:%M"))
+            }
+            PrecipitationChange::Stop(time, main) => {
+                format!("{} stops at {}", main, time.format("%a %H:%M"))
+            }
+            PrecipitationChange::NoChange(Some(main)) => {
+                format!("{} continues for the next 24 hours", main)
+            }
+            PrecipitationChange::NoChange(None) => "none in the next 24 hours".to_string(),
+        };
+        writeln!(f, "Precipitation: {}", change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::open_weather_types::{Hourly, Main, Weather};
+    use chrono::TimeZone;
+
+    fn report(weather: Result<OpenWeather, Error>) -> WeatherReport {
+        WeatherReport {
+            uri: "http://cache.local/weather".to_string(),
+            time: Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 0).unwrap(),
+            status: Some(200),
+            latency: Duration::from_millis(42),
+            weather,
+        }
+    }
+
+    fn weather(main: Main) -> Vec<Weather> {
+        vec![Weather {
+            main,
+            description: main.to_string().to_lowercase(),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = report(Ok(Default::default()));
+        let time = report.time;
+        let w = report.weather.as_mut().unwrap();
+        w.current.dt = (time - chrono::TimeDelta::try_minutes(12).unwrap()).timestamp();
+        w.current.temp = 45.3;
+        w.current.weather = weather(Main::Clouds);
+        w.hourly = [(48.0, Main::Clouds), (52.5, Main::Rain), (41.0, Main::Rain)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(temp, main))| Hourly {
+                dt: (time + chrono::TimeDelta::try_hours(i as i64 + 1).unwrap()).timestamp(),
+                temp,
+                weather: weather(main),
+                ..Default::default()
+            })
+            .collect();
+
+        assert!(report.is_ok());
+        assert_eq!(
+            report.to_string(),
+            "\
+URI:           http://cache.local/weather
+HTTP status:   200
+Latency:       42 ms
+Forecast age:  12 minutes (fresh, stale after 30 minutes)
+Conditions:    Clouds (clouds), 45.3°
+High:          52.5° at Mon 11:05
+Low:           41.0° at Mon 12:05
+Precipitation: Rain starts at Mon 11:05
+"
+        );
+    }
+
+    #[test]
+    fn test_report_stale() {
+        let mut report = report(Ok(Default::default()));
+        report.weather.as_mut().unwrap().current.dt =
+            (report.time - chrono::TimeDelta::try_minutes(45).unwrap()).timestamp();
+
+        assert!(!report.is_ok());
+        assert!(report.to_string().contains(
+            "Forecast age:  45 minutes (STALE, stale after 30 minutes)\nConditions:    missing\n"
+        ));
+    }
+}